@@ -0,0 +1,36 @@
+//! Gate counts of the keccak256 sponge for 1 KB, 10 KB and 100 KB messages. The statements are
+//! left unverified, so the counts cover only the absorption of the message into the
+//! permutation inputs. Run with `cargo bench --bench gate_count`.
+
+use plonky2::plonk::{
+    circuit_builder::CircuitBuilder,
+    circuit_data::CircuitConfig,
+    config::{GenericConfig, PoseidonGoldilocksConfig},
+};
+use starky_keccak::keccak256_circuit::{keccak256_circuit_with_statements, keccak256_num_perms};
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+fn main() {
+    println!(
+        "{:>10} {:>8} {:>8} {:>14}",
+        "bytes", "perms", "gates", "gates per perm"
+    );
+    for num_bytes in [1 << 10, 10 << 10, 100 << 10] {
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let input = builder.add_virtual_targets(num_bytes / 4);
+        let (_, statements) = keccak256_circuit_with_statements(&mut builder, input);
+        assert_eq!(statements.len(), keccak256_num_perms(num_bytes));
+        let num_gates = builder.num_gates();
+        println!(
+            "{:>10} {:>8} {:>8} {:>14.1}",
+            num_bytes,
+            statements.len(),
+            num_gates,
+            num_gates as f64 / statements.len() as f64
+        );
+    }
+}
@@ -0,0 +1,8 @@
+#!/usr/bin/env bash
+# Runs the wasm verifier tests headlessly under Node.js. Requires wasm-pack and node.
+set -euo pipefail
+cd "$(dirname "$0")/.."
+
+# The fixtures are proven natively, as the wasm build contains no prover.
+cargo test --release --lib write_wasm_fixtures -- --ignored
+wasm-pack test --node --release -- --no-default-features --features wasm --test wasm
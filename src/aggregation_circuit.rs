@@ -0,0 +1,221 @@
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+use plonky2::{
+    field::extension::Extendable,
+    hash::{
+        hash_types::{HashOut, RichField},
+        poseidon::PoseidonHash,
+    },
+    iop::witness::{PartialWitness, WitnessWrite},
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CircuitData, VerifierCircuitData},
+        config::{GenericConfig, Hasher, PoseidonGoldilocksConfig},
+        proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget},
+    },
+};
+
+use crate::proving_stats::ProvingStats;
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+/// Poseidon commitment to the public inputs of a single child proof. For a keccak256 proof these
+/// are the message words followed by the digest words.
+pub fn public_inputs_commitment(public_inputs: &[F]) -> HashOut<F> {
+    PoseidonHash::hash_no_pad(public_inputs)
+}
+
+/// The public inputs of an aggregation proof over the given children: the Poseidon hash of the
+/// concatenated commitments of every child, in order.
+pub fn aggregated_commitment(children_public_inputs: &[Vec<F>]) -> HashOut<F> {
+    let elements = children_public_inputs
+        .iter()
+        .flat_map(|pis| public_inputs_commitment(pis).elements)
+        .collect_vec();
+    PoseidonHash::hash_no_pad(&elements)
+}
+
+/// Computes the root commitment of `aggregate_proofs` natively, from the public inputs of the
+/// leaf proofs.
+pub fn tree_commitment(leaves_public_inputs: Vec<Vec<F>>, arity: usize) -> HashOut<F> {
+    assert!(arity >= 2);
+    assert!(!leaves_public_inputs.is_empty());
+    let mut level = leaves_public_inputs;
+    loop {
+        level = level
+            .chunks(arity)
+            .map(|group| aggregated_commitment(group).elements.to_vec())
+            .collect_vec();
+        if level.len() == 1 {
+            return HashOut::from_partial(&level[0]);
+        }
+    }
+}
+
+pub struct AggregationCircuit<F, C, const D: usize>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+{
+    pub data: CircuitData<F, C, D>,
+    pub proofs_t: Vec<ProofWithPublicInputsTarget<D>>,
+}
+
+/// Builds a circuit that verifies one proof for each of `children` and exposes
+/// `aggregated_commitment` of their public inputs. The children may come from different circuits,
+/// e.g. keccak256 circuits for different message lengths, or from other aggregation circuits.
+pub fn build_aggregation_circuit(
+    children: &[&VerifierCircuitData<F, C, D>],
+) -> AggregationCircuit<F, C, D> {
+    assert!(!children.is_empty());
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let mut proofs_t = vec![];
+    let mut commitments = vec![];
+    for child in children.iter() {
+        let proof_t = builder.add_virtual_proof_with_pis(&child.common);
+        let verifier_data_t = builder.constant_verifier_data(&child.verifier_only);
+        builder.verify_proof::<C>(&proof_t, &verifier_data_t, &child.common);
+        let commitment =
+            builder.hash_n_to_hash_no_pad::<PoseidonHash>(proof_t.public_inputs.clone());
+        commitments.extend(commitment.elements);
+        proofs_t.push(proof_t);
+    }
+    let root = builder.hash_n_to_hash_no_pad::<PoseidonHash>(commitments);
+    builder.register_public_inputs(&root.elements);
+    let data = builder.build::<C>();
+
+    AggregationCircuit { data, proofs_t }
+}
+
+pub fn generate_aggregation_proof(
+    proofs: &[ProofWithPublicInputs<F, C, D>],
+    circuit: &AggregationCircuit<F, C, D>,
+) -> ProofWithPublicInputs<F, C, D> {
+    generate_aggregation_proof_with_stats(proofs, circuit).0
+}
+
+/// Like `generate_aggregation_proof`. Only the recursion time and the proof size are recorded, as
+/// no STARK is proven.
+pub fn generate_aggregation_proof_with_stats(
+    proofs: &[ProofWithPublicInputs<F, C, D>],
+    circuit: &AggregationCircuit<F, C, D>,
+) -> (ProofWithPublicInputs<F, C, D>, ProvingStats) {
+    let now = Instant::now();
+    let proof = prove_aggregation(proofs, circuit);
+    let mut stats = ProvingStats::default();
+    stats.finish(now.elapsed(), proof.to_bytes().len());
+    (proof, stats)
+}
+
+fn prove_aggregation(
+    proofs: &[ProofWithPublicInputs<F, C, D>],
+    circuit: &AggregationCircuit<F, C, D>,
+) -> ProofWithPublicInputs<F, C, D> {
+    assert!(proofs.len() == circuit.proofs_t.len());
+    let mut pw = PartialWitness::new();
+    proofs
+        .iter()
+        .zip(circuit.proofs_t.iter())
+        .for_each(|(proof, proof_t)| pw.set_proof_with_pis_target(proof_t, proof));
+    circuit.data.prove(pw).unwrap()
+}
+
+/// Aggregates the given proofs into a single root proof by verifying them in groups of `arity`,
+/// level by level. Each leaf is given with the verifier data of the circuit that produced it.
+/// Groups made of the same child circuits share one aggregation circuit.
+///
+/// The public inputs of the root proof equal `tree_commitment` of the leaves' public inputs.
+pub fn aggregate_proofs(
+    leaves: Vec<(ProofWithPublicInputs<F, C, D>, VerifierCircuitData<F, C, D>)>,
+    arity: usize,
+) -> (ProofWithPublicInputs<F, C, D>, VerifierCircuitData<F, C, D>) {
+    let (proof, verifier_data, _) = aggregate_proofs_with_stats(leaves, arity);
+    (proof, verifier_data)
+}
+
+/// Like `aggregate_proofs`, also returning the recursion time summed over the whole tree and the
+/// size of the root proof.
+pub fn aggregate_proofs_with_stats(
+    leaves: Vec<(ProofWithPublicInputs<F, C, D>, VerifierCircuitData<F, C, D>)>,
+    arity: usize,
+) -> (
+    ProofWithPublicInputs<F, C, D>,
+    VerifierCircuitData<F, C, D>,
+    ProvingStats,
+) {
+    assert!(arity >= 2);
+    assert!(!leaves.is_empty());
+    let mut cache: Vec<(Vec<HashOut<F>>, AggregationCircuit<F, C, D>)> = vec![];
+    let mut level = leaves;
+    let mut recursion_proving = Duration::ZERO;
+    loop {
+        let mut next_level = vec![];
+        for group in level.chunks(arity) {
+            let digests = group
+                .iter()
+                .map(|(_, vd)| vd.verifier_only.circuit_digest)
+                .collect_vec();
+            let index = match cache.iter().position(|(key, _)| *key == digests) {
+                Some(index) => index,
+                None => {
+                    let children = group.iter().map(|(_, vd)| vd).collect_vec();
+                    cache.push((digests, build_aggregation_circuit(&children)));
+                    cache.len() - 1
+                }
+            };
+            let circuit = &cache[index].1;
+            let proofs = group.iter().map(|(proof, _)| proof.clone()).collect_vec();
+            let now = Instant::now();
+            let proof = prove_aggregation(&proofs, circuit);
+            recursion_proving += now.elapsed();
+            next_level.push((proof, circuit.data.verifier_data()));
+        }
+        if next_level.len() == 1 {
+            let (proof, verifier_data) = next_level.pop().unwrap();
+            let mut stats = ProvingStats::default();
+            stats.finish(recursion_proving, proof.to_bytes().len());
+            return (proof, verifier_data, stats);
+        }
+        level = next_level;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use rand::Rng;
+
+    use crate::aggregation_circuit::{aggregate_proofs_with_stats, tree_commitment};
+    use crate::keccak256_circuit::{build_keccak256_circuit, generate_keccak256_proof};
+
+    #[test]
+    fn test_aggregate_keccak256_proofs() {
+        let input_lens: Vec<usize> = vec![1, 40, 100];
+        let mut rng = rand::thread_rng();
+
+        let leaves = input_lens
+            .iter()
+            .map(|&input_len| {
+                let circuit = build_keccak256_circuit(input_len);
+                let input: Vec<u32> = (0..input_len).map(|_| rng.gen()).collect();
+                let proof = generate_keccak256_proof(input, &circuit);
+                (proof, circuit.data.verifier_data())
+            })
+            .collect_vec();
+        let leaves_public_inputs = leaves
+            .iter()
+            .map(|(proof, _)| proof.public_inputs.clone())
+            .collect_vec();
+
+        let (root_proof, root_verifier_data, stats) = aggregate_proofs_with_stats(leaves, 2);
+        assert_eq!(stats.proof_size, root_proof.to_bytes().len());
+        root_verifier_data.verify(root_proof.clone()).unwrap();
+
+        let expected = tree_commitment(leaves_public_inputs, 2);
+        assert!(root_proof.public_inputs == expected.elements.to_vec());
+    }
+}
@@ -0,0 +1,386 @@
+//! Command-line prover and verifier for keccak256.
+//!
+//! ```text
+//! starky-keccak prove (--hex <HEX> | --file <PATH>)... --out <PROOF> [--public-inputs <PATH>]
+//!                     [--format binary|json] [--circuit <CIRCUIT>]
+//! starky-keccak verify --proof <PROOF> [--circuit <CIRCUIT>]
+//! starky-keccak build-circuit --len <BYTES>... --out <CIRCUIT>
+//! starky-keccak bench --len <BYTES>... [--runs <N>]
+//! ```
+//!
+//! `prove` proves the keccak256 digests of one or more messages with a single proof. `verify`
+//! checks a proof. Both build the circuit for the message lengths unless a circuit file from
+//! `build-circuit` for the same lengths is given.
+//!
+//! File formats, integers are little-endian:
+//!
+//! - Proof file: a `ProofContainer`, in its binary or JSON encoding. The public inputs of the
+//!   proof are, for each message, its bytes packed into u32 words followed by the 8 words of its
+//!   digest.
+//! - Circuit file: the magic `SKVD`, the number of messages `n` as a u32, the byte length of each
+//!   message as a u32, then `Keccak256Circuit::to_bytes` for one message or
+//!   `MultiKeccak256Circuit::to_bytes` for several. These hold the prover data as well as the
+//!   verifier data, and refuse to load for other lengths or another version of the crate.
+//! - Public inputs file: one line per message, `<message hex> <digest hex>`, both `0x`-prefixed.
+
+use std::fs;
+use std::time::Instant;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use itertools::Itertools;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use starky_keccak::keccak256_circuit::{
+    build_keccak256_bytes_circuit, bytes_to_words, generate_keccak256_proof_with_stats,
+    Keccak256Circuit, PublicInputMode,
+};
+use starky_keccak::multi_keccak256_circuit::{
+    build_multi_keccak256_bytes_circuit, generate_multi_keccak256_proof_with_stats,
+    MultiKeccak256Circuit,
+};
+use starky_keccak::proof_container::ProofContainer;
+use starky_keccak::proving_stats::ProvingStats;
+use starky_keccak::public_inputs::decode_keccak256_public_inputs;
+use starky_keccak::types::{self, encode_hex};
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+const CIRCUIT_MAGIC: &[u8; 4] = b"SKVD";
+
+const USAGE: &str = "usage:
+    starky-keccak prove (--hex <HEX> | --file <PATH>)... --out <PROOF> [--public-inputs <PATH>]
+                        [--format binary|json] [--circuit <CIRCUIT>]
+    starky-keccak verify --proof <PROOF> [--circuit <CIRCUIT>]
+    starky-keccak build-circuit --len <BYTES>... --out <CIRCUIT>
+    starky-keccak bench --len <BYTES>... [--runs <N>]";
+
+/// A single-message circuit for one message, a multi-message circuit otherwise.
+enum Circuit {
+    Single(Keccak256Circuit),
+    Multi(MultiKeccak256Circuit<F, C, D>),
+}
+
+impl Circuit {
+    fn build(lens: &[usize]) -> Self {
+        match lens {
+            [len] => Circuit::Single(build_keccak256_bytes_circuit(*len)),
+            _ => Circuit::Multi(build_multi_keccak256_bytes_circuit(lens.to_vec())),
+        }
+    }
+
+    fn prove(&self, messages: &[Vec<u8>]) -> (ProofWithPublicInputs<F, C, D>, ProvingStats) {
+        let inputs = messages.iter().map(|m| bytes_to_words(m)).collect_vec();
+        match self {
+            Circuit::Single(circuit) => {
+                generate_keccak256_proof_with_stats(inputs[0].clone(), circuit)
+            }
+            Circuit::Multi(circuit) => generate_multi_keccak256_proof_with_stats(inputs, circuit),
+        }
+    }
+
+    fn verifier_data(&self) -> VerifierCircuitData<F, C, D> {
+        match self {
+            Circuit::Single(circuit) => circuit.data.verifier_data(),
+            Circuit::Multi(circuit) => circuit.data.verifier_data(),
+        }
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        match self {
+            Circuit::Single(circuit) => circuit.to_bytes(),
+            Circuit::Multi(circuit) => circuit.to_bytes(),
+        }
+    }
+
+    fn from_bytes(bytes: &[u8], lens: &[usize]) -> Result<Self> {
+        Ok(match lens {
+            [len] => Circuit::Single(Keccak256Circuit::from_bytes(
+                bytes,
+                *len,
+                PublicInputMode::Full,
+            )?),
+            _ => Circuit::Multi(MultiKeccak256Circuit::from_bytes(
+                bytes,
+                lens.to_vec(),
+                None,
+            )?),
+        })
+    }
+
+    /// Loads the circuit file at `path`, or builds the circuit if there is none.
+    fn load_or_build(path: Option<&str>, lens: &[usize]) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Circuit::build(lens));
+        };
+        let bytes = fs::read(path).with_context(|| format!("reading {}", path))?;
+        let (circuit_lens, data) = read_header(&bytes, CIRCUIT_MAGIC)?;
+        ensure!(
+            circuit_lens == lens,
+            "the circuit is for message lengths {:?}, not {:?}",
+            circuit_lens,
+            lens
+        );
+        Circuit::from_bytes(data, lens).context("invalid circuit file")
+    }
+}
+
+/// Parsed `--flag value` pairs. Flags may repeat.
+struct Args(Vec<(String, String)>);
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self> {
+        ensure!(args.len() % 2 == 0, "every flag takes a value\n{}", USAGE);
+        let pairs = args
+            .chunks(2)
+            .map(|pair| match pair[0].strip_prefix("--") {
+                Some(flag) => Ok((flag.to_string(), pair[1].clone())),
+                None => Err(anyhow!("unexpected argument {}\n{}", pair[0], USAGE)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Args(pairs))
+    }
+
+    fn all(&self, flag: &str) -> Vec<&str> {
+        self.0
+            .iter()
+            .filter(|(f, _)| f == flag)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    fn get(&self, flag: &str) -> Option<&str> {
+        self.all(flag).last().copied()
+    }
+
+    fn required(&self, flag: &str) -> Result<&str> {
+        self.get(flag)
+            .ok_or_else(|| anyhow!("missing --{}\n{}", flag, USAGE))
+    }
+
+    fn lens(&self) -> Result<Vec<usize>> {
+        let lens = self
+            .all("len")
+            .into_iter()
+            .map(|len| len.parse::<usize>().context("invalid --len"))
+            .collect::<Result<Vec<_>>>()?;
+        ensure!(!lens.is_empty(), "missing --len\n{}", USAGE);
+        Ok(lens)
+    }
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>> {
+    let mut bytes = vec![0; s.strip_prefix("0x").unwrap_or(s).len() / 2];
+    types::decode_hex(s, &mut bytes).context("invalid hex string")?;
+    Ok(bytes)
+}
+
+fn write_header(out: &mut Vec<u8>, magic: &[u8; 4], lens: &[usize]) {
+    out.extend_from_slice(magic);
+    out.extend_from_slice(&(lens.len() as u32).to_le_bytes());
+    for &len in lens {
+        out.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+}
+
+/// Returns the message lengths and the rest of the file.
+fn read_header<'a>(bytes: &'a [u8], magic: &[u8; 4]) -> Result<(Vec<usize>, &'a [u8])> {
+    let read_u32 = |offset: usize| -> Result<usize> {
+        let word = bytes
+            .get(offset..offset + 4)
+            .ok_or_else(|| anyhow!("truncated file"))?;
+        Ok(u32::from_le_bytes(word.try_into().unwrap()) as usize)
+    };
+    ensure!(
+        bytes.starts_with(magic),
+        "not a {} file",
+        String::from_utf8_lossy(magic)
+    );
+    let n = read_u32(4)?;
+    let lens = (0..n)
+        .map(|i| read_u32(8 + 4 * i))
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(!lens.is_empty(), "no messages");
+    Ok((lens, &bytes[8 + 4 * n..]))
+}
+
+fn prove(args: &Args) -> Result<()> {
+    let mut messages = vec![];
+    for (flag, value) in args.0.iter() {
+        match flag.as_str() {
+            "hex" => messages.push(decode_hex(value)?),
+            "file" => messages.push(fs::read(value).with_context(|| format!("reading {}", value))?),
+            _ => {}
+        }
+    }
+    ensure!(!messages.is_empty(), "no messages given\n{}", USAGE);
+    let out = args.required("out")?;
+    let lens = messages.iter().map(|m| m.len()).collect_vec();
+
+    let now = Instant::now();
+    let circuit = Circuit::load_or_build(args.get("circuit"), &lens)?;
+    eprintln!("circuit ready in {:?}", now.elapsed());
+    let (proof, stats) = circuit.prove(&messages);
+    eprintln!("proof generated: {}", stats);
+
+    let container = ProofContainer::new(&proof, &circuit.verifier_data(), &lens);
+    let bytes = match args.get("format").unwrap_or("binary") {
+        "binary" => container.to_bytes(),
+        "json" => container.to_json().into_bytes(),
+        format => bail!("unknown format {}", format),
+    };
+    fs::write(out, bytes).with_context(|| format!("writing {}", out))?;
+
+    let pairs = decode_keccak256_public_inputs(&proof.public_inputs, &lens)?;
+    let lines = pairs
+        .iter()
+        .map(|pair| {
+            format!(
+                "{} {}\n",
+                encode_hex(&pair.message),
+                encode_hex(&pair.digest.0)
+            )
+        })
+        .collect::<String>();
+    match args.get("public-inputs") {
+        Some(path) => fs::write(path, lines).with_context(|| format!("writing {}", path))?,
+        None => print!("{}", lines),
+    }
+    Ok(())
+}
+
+fn verify(args: &Args) -> Result<()> {
+    let path = args.required("proof")?;
+    let bytes = fs::read(path).with_context(|| format!("reading {}", path))?;
+    let container = ProofContainer::decode(&bytes)?;
+    let lens = container.message_lens();
+
+    let verifier_data = Circuit::load_or_build(args.get("circuit"), &lens)?.verifier_data();
+    let proof = container.verify(&verifier_data)?;
+
+    for pair in decode_keccak256_public_inputs(&proof.public_inputs, &lens)? {
+        println!(
+            "{} {}",
+            encode_hex(&pair.message),
+            encode_hex(&pair.digest.0)
+        );
+    }
+    eprintln!("proof verified");
+    Ok(())
+}
+
+fn build_circuit(args: &Args) -> Result<()> {
+    let lens = args.lens()?;
+    let out = args.required("out")?;
+    let mut bytes = vec![];
+    write_header(&mut bytes, CIRCUIT_MAGIC, &lens);
+    bytes.extend(Circuit::build(&lens).to_bytes()?);
+    fs::write(out, bytes).with_context(|| format!("writing {}", out))?;
+    Ok(())
+}
+
+fn bench(args: &Args) -> Result<()> {
+    let lens = args.lens()?;
+    let runs = match args.get("runs") {
+        Some(runs) => runs.parse::<usize>().context("invalid --runs")?,
+        None => 1,
+    };
+    let now = Instant::now();
+    let circuit = Circuit::build(&lens);
+    println!("build: {:?}", now.elapsed());
+    let verifier_data = circuit.verifier_data();
+    for run in 0..runs {
+        let messages = lens
+            .iter()
+            .map(|&len| (0..len).map(|_| rand::random()).collect_vec())
+            .collect_vec();
+        let (proof, stats) = circuit.prove(&messages);
+        let now = Instant::now();
+        verifier_data.verify(proof)?;
+        println!("run {}: {}, verify {:?}", run, stats, now.elapsed());
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some((command, rest)) = args.split_first() else {
+        bail!("{}", USAGE);
+    };
+    let args = Args::parse(rest)?;
+    match command.as_str() {
+        "prove" => prove(&args),
+        "verify" => verify(&args),
+        "build-circuit" => build_circuit(&args),
+        "bench" => bench(&args),
+        _ => bail!("unknown command {}\n{}", command, USAGE),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_hex, read_header, write_header, Args, CIRCUIT_MAGIC};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_args() {
+        let parsed = Args::parse(&args(&["--len", "3", "--len", "136", "--out", "a"])).unwrap();
+        assert_eq!(parsed.all("len"), vec!["3", "136"]);
+        assert_eq!(parsed.lens().unwrap(), vec![3, 136]);
+        assert_eq!(parsed.get("out"), Some("a"));
+        assert_eq!(parsed.get("runs"), None);
+        assert!(parsed.required("proof").is_err());
+
+        let parsed = Args::parse(&args(&["--out", "a", "--out", "b"])).unwrap();
+        assert_eq!(parsed.required("out").unwrap(), "b");
+        assert!(parsed.lens().is_err());
+
+        assert!(Args::parse(&args(&["--len"])).is_err());
+        assert!(Args::parse(&args(&["len", "3"])).is_err());
+        assert!(Args::parse(&args(&["--len", "x"])).unwrap().lens().is_err());
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(decode_hex("616263").unwrap(), b"abc");
+        assert_eq!(decode_hex("0x616263").unwrap(), b"abc");
+        assert_eq!(decode_hex("").unwrap(), b"");
+        assert!(decode_hex("61626").is_err());
+        assert!(decode_hex("6g").is_err());
+        // Multi-byte characters are rejected rather than split.
+        assert!(decode_hex("aéb").is_err());
+        assert!(decode_hex("éé").is_err());
+    }
+
+    #[test]
+    fn test_header() {
+        let lens = [3, 136, 0];
+        let mut bytes = vec![];
+        write_header(&mut bytes, CIRCUIT_MAGIC, &lens);
+        let header_len = bytes.len();
+        assert_eq!(header_len, 4 + 4 + 4 * lens.len());
+        bytes.extend_from_slice(b"rest");
+
+        let (read_lens, rest) = read_header(&bytes, CIRCUIT_MAGIC).unwrap();
+        assert_eq!(read_lens, lens);
+        assert_eq!(rest, b"rest");
+        assert!(read_header(&bytes, b"SKPF").is_err());
+
+        for len in 0..header_len {
+            assert!(
+                read_header(&bytes[..len], CIRCUIT_MAGIC).is_err(),
+                "{}",
+                len
+            );
+        }
+
+        let mut empty = vec![];
+        write_header(&mut empty, CIRCUIT_MAGIC, &[]);
+        assert!(read_header(&empty, CIRCUIT_MAGIC).is_err());
+    }
+}
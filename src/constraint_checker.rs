@@ -0,0 +1,343 @@
+use std::fmt;
+
+use itertools::Itertools;
+use plonky2::{
+    field::{extension::Extendable, polynomial::PolynomialValues, types::Sample},
+    hash::hash_types::RichField,
+    util::transpose,
+};
+use starky::{constraint_consumer::ConstraintConsumer, stark::Stark, vars::StarkEvaluationVars};
+
+use crate::{
+    columns::{reg_step, NUM_COLUMNS},
+    keccak_constraints::{
+        eval_a_prime_booleanity, eval_a_prime_consistency, eval_a_prime_parity,
+        eval_a_prime_prime_0_0_bit_booleanity, eval_c_booleanity, eval_chi, eval_iota,
+        eval_round_transition, eval_theta_c_prime,
+    },
+    keccak_stark::{eval_filter, KeccakStark, NUM_INPUTS, NUM_ROUNDS},
+    pulse::{eval_pulse, get_pulse_col},
+    round_flags::eval_round_flags,
+    utils::{gen_keccak_pulse_positions, read_input, read_output, read_state, state_eq},
+};
+
+/// The groups of constraints of `KeccakStark`, in the order they are evaluated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConstraintGroup {
+    RoundFlags,
+    Filter,
+    Pulse,
+    /// The input of permutation `io` equals its public inputs.
+    InputBinding,
+    /// The output of permutation `io` equals its public inputs.
+    OutputBinding,
+    /// `C'[x, z]`.
+    ThetaCPrime,
+    /// The input lane `A[x, y]` against `A'`, `C` and `C'`.
+    APrimeConsistency,
+    /// The parity of column `x` of `A'` at bit `z` against `C'[x, z]`.
+    APrimeParity,
+    /// `A''[x, y]`.
+    Chi,
+    Iota,
+    /// The output lane `A'''[x, y]` against the next row's input.
+    RoundTransition,
+    /// `C[x, z]` and `C'[x, z]` are bits.
+    CBits,
+    /// `A'[x, y, z]` is a bit.
+    APrimeBits,
+    /// Bit `z` of `A''[0, 0]` is a bit.
+    APrimePrime00Bits,
+}
+
+/// A constraint group that does not vanish on some row of a trace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConstraintFailure {
+    pub row: usize,
+    /// The round of the row, or `None` if the round flags are not a unit vector.
+    pub round: Option<usize>,
+    pub group: ConstraintGroup,
+    /// The permutation, for the IO binding groups.
+    pub io: Option<usize>,
+    pub x: Option<usize>,
+    pub y: Option<usize>,
+    pub z: Option<usize>,
+}
+
+impl fmt::Display for ConstraintFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "row {}", self.row)?;
+        if let Some(round) = self.round {
+            write!(f, " (round {})", round)?;
+        }
+        write!(f, ": {:?}", self.group)?;
+        let coords = [("io", self.io), ("x", self.x), ("y", self.y), ("z", self.z)]
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|value| format!("{} = {}", name, value)))
+            .collect_vec();
+        if !coords.is_empty() {
+            write!(f, " at {}", coords.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Evaluates the constraints of `stark` on every row of `trace` and returns the groups that do not
+/// vanish, without committing to the trace. This is meant for debugging trace generation: a bad
+/// trace makes `prove` produce a proof that fails to verify without saying why.
+///
+/// Transition constraints are not checked on the last row, and first-row constraints are only
+/// checked on row 0, as in the prover.
+pub fn check_keccak_constraints<F: RichField + Extendable<D>, const D: usize>(
+    stark: &KeccakStark<F, D>,
+    trace: &[PolynomialValues<F>],
+    public_inputs: &[F],
+) -> Vec<ConstraintFailure> {
+    assert!(public_inputs.len() == 4 * NUM_INPUTS * stark.num_io);
+    let rows = transpose(&trace.iter().map(|col| col.values.clone()).collect_vec());
+    let num_rows = rows.len();
+    let alpha = F::rand();
+    let pulse_positions = gen_keccak_pulse_positions(stark.num_io);
+
+    let mut failures = vec![];
+    for row in 0..num_rows {
+        let vars = StarkEvaluationVars {
+            local_values: rows[row].as_slice(),
+            next_values: rows[(row + 1) % num_rows].as_slice(),
+            public_inputs,
+        };
+        let round = (0..NUM_ROUNDS).find(|&r| vars.local_values[reg_step(r)] == F::ONE);
+        let violated = |eval: &dyn Fn(&mut ConstraintConsumer<F>)| {
+            let is_first_row = if row == 0 { F::ONE } else { F::ZERO };
+            let is_last_row = if row == num_rows - 1 { F::ONE } else { F::ZERO };
+            let mut consumer = ConstraintConsumer::new(
+                vec![alpha],
+                F::ONE - is_last_row,
+                is_first_row,
+                is_last_row,
+            );
+            eval(&mut consumer);
+            consumer.accumulators()[0] != F::ZERO
+        };
+        let failure = |group, io, x, y, z| ConstraintFailure {
+            row,
+            round,
+            group,
+            io,
+            x,
+            y,
+            z,
+        };
+        let num_failures = failures.len();
+
+        if violated(&|c| eval_round_flags(vars, c)) {
+            failures.push(failure(ConstraintGroup::RoundFlags, None, None, None, None));
+        }
+        if violated(&|c| eval_filter(vars, c)) {
+            failures.push(failure(ConstraintGroup::Filter, None, None, None, None));
+        }
+        if violated(&|c| {
+            eval_pulse(
+                c,
+                vars.local_values,
+                vars.next_values,
+                NUM_COLUMNS,
+                pulse_positions.clone(),
+            )
+        }) {
+            failures.push(failure(ConstraintGroup::Pulse, None, None, None, None));
+        }
+
+        let input = read_input(vars.local_values);
+        let output = read_output(vars.local_values);
+        let mut cur_col = 0;
+        for i in 0..stark.num_io {
+            let input_flag = vars.local_values[get_pulse_col(NUM_COLUMNS, 2 * i)];
+            let output_flag = vars.local_values[get_pulse_col(NUM_COLUMNS, 2 * i + 1)];
+            let input_pi = read_state(public_inputs, &mut cur_col);
+            let output_pi = read_state(public_inputs, &mut cur_col);
+            if violated(&|c| state_eq(c, input_flag, input, input_pi)) {
+                failures.push(failure(
+                    ConstraintGroup::InputBinding,
+                    Some(i),
+                    None,
+                    None,
+                    None,
+                ));
+            }
+            if violated(&|c| state_eq(c, output_flag, output, output_pi)) {
+                failures.push(failure(
+                    ConstraintGroup::OutputBinding,
+                    Some(i),
+                    None,
+                    None,
+                    None,
+                ));
+            }
+        }
+
+        for x in 0..5 {
+            for z in 0..64 {
+                if violated(&|c| eval_theta_c_prime(c, vars, x, z)) {
+                    failures.push(failure(
+                        ConstraintGroup::ThetaCPrime,
+                        None,
+                        Some(x),
+                        None,
+                        Some(z),
+                    ));
+                }
+            }
+        }
+        for x in 0..5 {
+            for y in 0..5 {
+                if violated(&|c| eval_a_prime_consistency(c, vars, x, y)) {
+                    failures.push(failure(
+                        ConstraintGroup::APrimeConsistency,
+                        None,
+                        Some(x),
+                        Some(y),
+                        None,
+                    ));
+                }
+            }
+        }
+        for x in 0..5 {
+            for z in 0..64 {
+                if violated(&|c| eval_a_prime_parity(c, vars, x, z)) {
+                    failures.push(failure(
+                        ConstraintGroup::APrimeParity,
+                        None,
+                        Some(x),
+                        None,
+                        Some(z),
+                    ));
+                }
+            }
+        }
+        for x in 0..5 {
+            for y in 0..5 {
+                if violated(&|c| eval_chi(c, vars, x, y)) {
+                    failures.push(failure(ConstraintGroup::Chi, None, Some(x), Some(y), None));
+                }
+            }
+        }
+        if violated(&|c| eval_iota(c, vars)) {
+            failures.push(failure(ConstraintGroup::Iota, None, None, None, None));
+        }
+        for x in 0..5 {
+            for y in 0..5 {
+                if violated(&|c| eval_round_transition(c, vars, x, y)) {
+                    failures.push(failure(
+                        ConstraintGroup::RoundTransition,
+                        None,
+                        Some(x),
+                        Some(y),
+                        None,
+                    ));
+                }
+            }
+        }
+        for x in 0..5 {
+            for z in 0..64 {
+                if violated(&|c| eval_c_booleanity(c, vars, x, z)) {
+                    failures.push(failure(
+                        ConstraintGroup::CBits,
+                        None,
+                        Some(x),
+                        None,
+                        Some(z),
+                    ));
+                }
+            }
+        }
+        for x in 0..5 {
+            for y in 0..5 {
+                for z in 0..64 {
+                    if violated(&|c| eval_a_prime_booleanity(c, vars, x, y, z)) {
+                        failures.push(failure(
+                            ConstraintGroup::APrimeBits,
+                            None,
+                            Some(x),
+                            Some(y),
+                            Some(z),
+                        ));
+                    }
+                }
+            }
+        }
+        for i in 0..64 {
+            if violated(&|c| eval_a_prime_prime_0_0_bit_booleanity(c, vars, i)) {
+                failures.push(failure(
+                    ConstraintGroup::APrimePrime00Bits,
+                    None,
+                    None,
+                    None,
+                    Some(i),
+                ));
+            }
+        }
+
+        // The groups above must cover every constraint of the stark.
+        let row_violated = violated(&|c| stark.eval_packed_generic::<F, F, 1>(vars, c));
+        assert!(
+            row_violated == (failures.len() > num_failures),
+            "the constraint groups are out of sync with KeccakStark"
+        );
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use plonky2::field::types::Field;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+
+    use crate::columns::reg_c_prime;
+    use crate::constraint_checker::{check_keccak_constraints, ConstraintGroup};
+    use crate::keccak_stark::KeccakStark;
+    use crate::types::KeccakState;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+    type S = KeccakStark<F, D>;
+
+    #[test]
+    fn test_check_keccak_constraints() {
+        let num_io = 2;
+        let inputs = (0..num_io)
+            .map(|_| KeccakState(rand::random()))
+            .collect_vec();
+        let outputs = inputs.iter().map(|input| input.permute()).collect_vec();
+
+        let stark = S::new(num_io);
+        let trace = stark.generate_trace(inputs.clone(), 8);
+        let public_inputs = stark.generate_public_inputs(inputs, outputs);
+        assert!(check_keccak_constraints(&stark, &trace, &public_inputs).is_empty());
+
+        // Flip one bit of C' in round 6 of the second permutation.
+        let (row, x, z) = (30, 2, 17);
+        let mut bad_trace = trace.clone();
+        let col = &mut bad_trace[reg_c_prime(x, z)].values;
+        col[row] = F::ONE - col[row];
+        let failures = check_keccak_constraints(&stark, &bad_trace, &public_inputs);
+        assert!(failures.iter().all(|failure| failure.row == row));
+        assert!(failures.iter().all(|failure| failure.round == Some(6)));
+        assert!(failures
+            .iter()
+            .any(|failure| failure.group == ConstraintGroup::ThetaCPrime
+                && failure.x == Some(x)
+                && failure.z == Some(z)));
+
+        // A wrong public output is reported on the last round of its permutation.
+        let mut bad_public_inputs = public_inputs.clone();
+        bad_public_inputs[150] += F::ONE;
+        let failures = check_keccak_constraints(&stark, &trace, &bad_public_inputs);
+        assert!(failures.len() == 1);
+        assert!(failures[0].group == ConstraintGroup::OutputBinding);
+        assert!(failures[0].io == Some(1));
+        assert!(failures[0].row == 47);
+    }
+}
@@ -0,0 +1,172 @@
+//! Sizes and costs of a keccak workload, computed before any circuit is built.
+//!
+//! The trace shape comes from the same functions the prover uses (`KeccakStark::num_rows`,
+//! `KeccakStark::num_columns` and `keccak256_num_perms`), so the estimates cannot drift from the
+//! actual proofs. Times are extrapolated from a `Calibration` measured on the target machine.
+
+use std::time::Duration;
+
+use itertools::Itertools;
+use plonky2::plonk::{
+    circuit_builder::CircuitBuilder,
+    circuit_data::CircuitConfig,
+    config::{GenericConfig, PoseidonGoldilocksConfig},
+};
+
+use crate::keccak256_circuit::{
+    build_keccak256_bytes_circuit, bytes_to_words, generate_keccak256_proof_with_stats,
+    keccak256_num_perms, verify_keccak_statements_circuit, MIN_TRACE_ROWS,
+};
+use crate::keccak_stark::KeccakStark;
+use crate::proving_stats::estimate_peak_bytes;
+use crate::types::KeccakStateTarget;
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+/// The cost of proving the keccak256 digests of messages of the given byte lengths with one
+/// `KeccakStark` proof, as `build_multi_keccak256_bytes_circuit` does.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeccakCostEstimate {
+    pub num_perms: usize,
+    pub trace_height: usize,
+    pub trace_width: usize,
+    pub stark_public_inputs: usize,
+    /// The message words and digest words of every message.
+    pub circuit_public_inputs: usize,
+    /// See `ProvingStats::estimated_peak_bytes`.
+    pub estimated_peak_bytes: usize,
+    rate_bits: usize,
+}
+
+impl KeccakCostEstimate {
+    pub fn for_byte_lens(input_num_bytes: &[usize]) -> Self {
+        let num_perms = input_num_bytes
+            .iter()
+            .map(|&num_bytes| keccak256_num_perms(num_bytes))
+            .sum();
+        let stark = KeccakStark::<F, D>::new(num_perms);
+        let trace_height = stark.num_rows(MIN_TRACE_ROWS);
+        let trace_width = stark.num_columns();
+        let rate_bits = stark.config().fri_config.rate_bits;
+        Self {
+            num_perms,
+            trace_height,
+            trace_width,
+            stark_public_inputs: stark.num_public_inputs(),
+            circuit_public_inputs: input_num_bytes
+                .iter()
+                .map(|&num_bytes| (num_bytes + 3) / 4 + 8)
+                .sum(),
+            estimated_peak_bytes: estimate_peak_bytes(trace_height, trace_width, rate_bits),
+            rate_bits,
+        }
+    }
+
+    /// Lengths in u32 words, as taken by `build_multi_keccak256_circuit`.
+    pub fn for_word_lens(input_lens: &[usize]) -> Self {
+        Self::for_byte_lens(&input_lens.iter().map(|&len| 4 * len).collect_vec())
+    }
+
+    /// The number of cells of the low-degree extension of the trace.
+    pub fn lde_cells(&self) -> usize {
+        (self.trace_height << self.rate_bits) * self.trace_width
+    }
+
+    /// The degree bits of the recursive circuit. This builds a circuit verifying a `KeccakStark`
+    /// proof of this shape, without the message wiring, which is much cheaper than building the
+    /// full circuit and proving, but not free.
+    pub fn recursive_degree_bits(&self) -> usize {
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let statements_t = (0..self.num_perms)
+            .map(|_| {
+                (
+                    KeccakStateTarget::new_virtual(&mut builder),
+                    KeccakStateTarget::new_virtual(&mut builder),
+                )
+            })
+            .collect_vec();
+        verify_keccak_statements_circuit(&mut builder, &statements_t);
+        let public_inputs_t = builder.add_virtual_targets(self.circuit_public_inputs);
+        builder.register_public_inputs(&public_inputs_t);
+        builder.build::<C>().common.degree_bits()
+    }
+
+    /// Approximate STARK and recursion proving time, given the degree bits of the recursive
+    /// circuit.
+    pub fn proving_time(
+        &self,
+        calibration: &Calibration,
+        recursive_degree_bits: usize,
+    ) -> (Duration, Duration) {
+        let stark = calibration.nanos_per_lde_cell * self.lde_cells() as f64;
+        let recursion = calibration.nanos_per_gate_row * (1u64 << recursive_degree_bits) as f64;
+        (
+            Duration::from_nanos(stark as u64),
+            Duration::from_nanos(recursion as u64),
+        )
+    }
+}
+
+/// Proving throughput of the current machine.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Calibration {
+    pub nanos_per_lde_cell: f64,
+    pub nanos_per_gate_row: f64,
+}
+
+impl Calibration {
+    /// Measures the throughput by proving the digest of a message of `num_bytes` bytes. Larger
+    /// messages amortize fixed costs better and give more accurate estimates for large batches.
+    pub fn measure(num_bytes: usize) -> Self {
+        let circuit = build_keccak256_bytes_circuit(num_bytes);
+        let message = (0..num_bytes).map(|_| rand::random()).collect_vec();
+        let (_proof, stats) =
+            generate_keccak256_proof_with_stats(bytes_to_words(&message), &circuit);
+        let estimate = KeccakCostEstimate::for_byte_lens(&[num_bytes]);
+        Self {
+            nanos_per_lde_cell: stats.stark_proving.as_nanos() as f64 / estimate.lde_cells() as f64,
+            nanos_per_gate_row: stats.recursion_proving.as_nanos() as f64
+                / circuit.data.common.degree() as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+
+    use crate::cost_estimate::KeccakCostEstimate;
+    use crate::keccak256_circuit::bytes_to_words;
+    use crate::keccak_stark::KeccakStark;
+    use crate::multi_keccak256_circuit::multi_keccak256_with_byte_lens;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_estimate_matches_trace() {
+        let lens = vec![0, 135, 136, 300];
+        let estimate = KeccakCostEstimate::for_byte_lens(&lens);
+        assert_eq!(estimate.num_perms, 1 + 1 + 2 + 3);
+        assert_eq!(estimate.circuit_public_inputs, 8 + 42 + 42 + 83);
+
+        let inputs = lens
+            .iter()
+            .map(|&len| bytes_to_words(&vec![0u8; len]))
+            .collect_vec();
+        let (_outputs, statements) = multi_keccak256_with_byte_lens(inputs, &lens);
+        assert_eq!(statements.len(), estimate.num_perms);
+        assert_eq!(100 * statements.len(), estimate.stark_public_inputs);
+
+        let stark = KeccakStark::<F, D>::new(estimate.num_perms);
+        let inputs = statements.iter().map(|(input, _)| *input).collect_vec();
+        let trace = stark.generate_trace(inputs, 8);
+        assert_eq!(trace.len(), estimate.trace_width);
+        assert_eq!(trace[0].len(), estimate.trace_height);
+    }
+}
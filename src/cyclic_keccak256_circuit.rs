@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use anyhow::Result;
+use itertools::Itertools;
+use plonky2::{
+    field::types::Field,
+    hash::{hash_types::HashOut, poseidon::PoseidonHash},
+    iop::{
+        target::{BoolTarget, Target},
+        witness::{PartialWitness, WitnessWrite},
+    },
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CircuitData, CommonCircuitData, VerifierCircuitTarget},
+        config::{GenericConfig, Hasher, PoseidonGoldilocksConfig},
+        proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget},
+    },
+    recursion::{
+        cyclic_recursion::check_cyclic_proof_verifier_data, dummy_circuit::cyclic_base_proof,
+    },
+};
+use starky::{
+    proof::StarkProofWithPublicInputsTarget, recursive_verifier::set_stark_proof_with_pis_target,
+};
+
+use crate::{
+    keccak256_circuit::{prove_keccak_statements_with_stats, verify_keccak_statements_circuit},
+    multi_keccak256_circuit::{multi_keccak256, multi_keccak256_circuit_with_statements},
+    proving_stats::ProvingStats,
+    types::{Digest256, Digest256Target},
+};
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+/// Upper bound on the rounds needed to find the step circuit's common data.
+const MAX_COMMON_DATA_ROUNDS: usize = 8;
+
+/// Poseidon commitment to one batch of (message, digest) pairs.
+pub fn batch_commitment(inputs: &[Vec<u32>], outputs: &[Digest256]) -> HashOut<F> {
+    let elements = inputs
+        .iter()
+        .zip(outputs.iter())
+        .flat_map(|(input, output)| [input.as_slice(), &output.words()].concat())
+        .map(F::from_canonical_u32)
+        .collect_vec();
+    PoseidonHash::hash_no_pad(&elements)
+}
+
+/// The accumulator after absorbing one more batch. The accumulator of the empty stream is zero.
+pub fn next_accumulator(prev: HashOut<F>, inputs: &[Vec<u32>]) -> HashOut<F> {
+    let (outputs, _statements) = multi_keccak256(inputs.to_vec());
+    let commitment = batch_commitment(inputs, &outputs);
+    PoseidonHash::hash_no_pad(&[prev.elements, commitment.elements].concat())
+}
+
+/// A step of an IVC chain. Each step proves one batch of keccak256 statements with a
+/// `KeccakStark` proof, verifies the previous step (or a dummy proof for the first step) and
+/// folds the batch into a running accumulator.
+///
+/// The public inputs are `[accumulator (4), number of steps (1), verifier data...]`.
+pub struct CyclicKeccak256Circuit {
+    pub data: CircuitData<F, C, D>,
+    pub input_lens: Vec<usize>,
+    pub condition: BoolTarget,
+    pub inner_proof_t: ProofWithPublicInputsTarget<D>,
+    pub verifier_data_t: VerifierCircuitTarget,
+    pub stark_proof_t: StarkProofWithPublicInputsTarget<D>,
+    pub inputs_t: Vec<Vec<Target>>,
+    pub outputs_t: Vec<Digest256Target>,
+}
+
+impl CyclicKeccak256Circuit {
+    pub fn accumulator(proof: &ProofWithPublicInputs<F, C, D>) -> HashOut<F> {
+        HashOut::from_partial(&proof.public_inputs[0..4])
+    }
+
+    pub fn num_steps(proof: &ProofWithPublicInputs<F, C, D>) -> F {
+        proof.public_inputs[4]
+    }
+}
+
+/// A minimal recursive circuit, used as the starting point when searching for the common data of
+/// the step circuit.
+fn common_data_for_recursion() -> CommonCircuitData<F, D> {
+    let config = CircuitConfig::standard_recursion_config();
+    let builder = CircuitBuilder::<F, D>::new(config);
+    let data = builder.build::<C>();
+
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let proof = builder.add_virtual_proof_with_pis(&data.common);
+    let verifier_data = builder.add_virtual_verifier_data(data.common.config.fri_config.cap_height);
+    builder.verify_proof::<C>(&proof, &verifier_data, &data.common);
+    builder.build::<C>().common
+}
+
+/// Builds the step circuit against `inner_common_data`. When `cyclic` is false, the inner proof
+/// is verified as an ordinary recursive proof, which gives the same gates without requiring
+/// `inner_common_data` to match the resulting circuit.
+fn build_step_circuit(
+    input_lens: &[usize],
+    inner_common_data: &CommonCircuitData<F, D>,
+    cyclic: bool,
+) -> CyclicKeccak256Circuit {
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+
+    // The new batch.
+    let inputs_t = input_lens
+        .iter()
+        .map(|&input_len| builder.add_virtual_targets(input_len))
+        .collect_vec();
+    let (outputs_t, statements_t) =
+        multi_keccak256_circuit_with_statements(&mut builder, inputs_t.clone());
+    let stark_proof_t = verify_keccak_statements_circuit(&mut builder, &statements_t);
+    let inputs_and_outputs = inputs_t
+        .iter()
+        .zip(outputs_t.iter())
+        .flat_map(|(input_t, output_t)| [input_t.as_slice(), output_t.targets()].concat())
+        .collect_vec();
+    let commitment = builder.hash_n_to_hash_no_pad::<PoseidonHash>(inputs_and_outputs);
+
+    let accumulator_t = builder.add_virtual_hash();
+    builder.register_public_inputs(&accumulator_t.elements);
+    let num_steps_t = builder.add_virtual_public_input();
+    let verifier_data_t = builder.add_verifier_data_public_inputs();
+    let mut common_data = inner_common_data.clone();
+    common_data.num_public_inputs = builder.num_public_inputs();
+
+    // The previous step. For the first step there is no inner proof, and the previous accumulator
+    // and step count are zero.
+    let condition = builder.add_virtual_bool_target_safe();
+    let inner_proof_t = builder.add_virtual_proof_with_pis(&common_data);
+    let inner_pis = &inner_proof_t.public_inputs;
+    let prev_accumulator = (0..4)
+        .map(|i| builder.mul(condition.target, inner_pis[i]))
+        .collect_vec();
+    let new_accumulator = builder.hash_n_to_hash_no_pad::<PoseidonHash>(
+        [prev_accumulator, commitment.elements.to_vec()].concat(),
+    );
+    builder.connect_hashes(accumulator_t, new_accumulator);
+    let one = builder.one();
+    let new_num_steps = builder.mul_add(condition.target, inner_pis[4], one);
+    builder.connect(num_steps_t, new_num_steps);
+
+    if cyclic {
+        builder
+            .conditionally_verify_cyclic_proof_or_dummy::<C>(
+                condition,
+                &inner_proof_t,
+                &common_data,
+            )
+            .unwrap();
+    } else {
+        builder
+            .conditionally_verify_proof_or_dummy::<C>(
+                condition,
+                &inner_proof_t,
+                &verifier_data_t,
+                &common_data,
+            )
+            .unwrap();
+    }
+    let data = builder.build::<C>();
+
+    CyclicKeccak256Circuit {
+        data,
+        input_lens: input_lens.to_vec(),
+        condition,
+        inner_proof_t,
+        verifier_data_t,
+        stark_proof_t,
+        inputs_t,
+        outputs_t,
+    }
+}
+
+/// Builds the step circuit for batches of messages with the given lengths (in u32 words).
+///
+/// Cyclic recursion requires the step circuit to verify proofs with its own common data, so we
+/// rebuild it against the common data of the previous attempt until the two agree.
+pub fn build_cyclic_keccak256_circuit(input_lens: Vec<usize>) -> CyclicKeccak256Circuit {
+    let mut common_data = common_data_for_recursion();
+    for _ in 0..MAX_COMMON_DATA_ROUNDS {
+        let probe = build_step_circuit(&input_lens, &common_data, false);
+        common_data.num_public_inputs = probe.data.common.num_public_inputs;
+        if probe.data.common == common_data {
+            return build_step_circuit(&input_lens, &common_data, true);
+        }
+        common_data = probe.data.common;
+    }
+    panic!("the common data of the step circuit did not converge");
+}
+
+/// Proves one step. `prev_proof` is the proof of the previous step, or `None` for the first step.
+pub fn generate_cyclic_keccak256_proof(
+    inputs: Vec<Vec<u32>>,
+    prev_proof: Option<&ProofWithPublicInputs<F, C, D>>,
+    circuit: &CyclicKeccak256Circuit,
+) -> Result<ProofWithPublicInputs<F, C, D>> {
+    Ok(generate_cyclic_keccak256_proof_with_stats(inputs, prev_proof, circuit)?.0)
+}
+
+pub fn generate_cyclic_keccak256_proof_with_stats(
+    inputs: Vec<Vec<u32>>,
+    prev_proof: Option<&ProofWithPublicInputs<F, C, D>>,
+    circuit: &CyclicKeccak256Circuit,
+) -> Result<(ProofWithPublicInputs<F, C, D>, ProvingStats)> {
+    assert!(inputs.len() == circuit.input_lens.len());
+    assert!(inputs
+        .iter()
+        .zip(circuit.input_lens.iter())
+        .all(|(input, &input_len)| input.len() == input_len));
+    let (outputs, statements) = multi_keccak256(inputs.clone());
+    let (inner_stark_proof, mut stats) = prove_keccak_statements_with_stats(&statements);
+
+    let mut pw = PartialWitness::new();
+    set_stark_proof_with_pis_target(&mut pw, &circuit.stark_proof_t, &inner_stark_proof);
+    // set inputs
+    inputs
+        .iter()
+        .zip(circuit.inputs_t.iter())
+        .for_each(|(input, input_t)| {
+            input
+                .iter()
+                .zip(input_t.iter())
+                .for_each(|(w, t)| pw.set_target(*t, F::from_canonical_u32(*w)))
+        });
+    // set outputs
+    outputs
+        .iter()
+        .zip(circuit.outputs_t.iter())
+        .for_each(|(output, output_t)| output_t.set_witness(&mut pw, *output));
+
+    match prev_proof {
+        Some(prev_proof) => {
+            pw.set_bool_target(circuit.condition, true);
+            pw.set_proof_with_pis_target(&circuit.inner_proof_t, prev_proof);
+        }
+        None => {
+            pw.set_bool_target(circuit.condition, false);
+            let base_proof = cyclic_base_proof(
+                &circuit.data.common,
+                &circuit.data.verifier_only,
+                HashMap::new(),
+            );
+            pw.set_proof_with_pis_target(&circuit.inner_proof_t, &base_proof);
+        }
+    }
+    pw.set_verifier_data_target(&circuit.verifier_data_t, &circuit.data.verifier_only);
+
+    let now = Instant::now();
+    let proof = circuit.data.prove(pw)?;
+    stats.finish(now.elapsed(), proof.to_bytes().len());
+    Ok((proof, stats))
+}
+
+/// Verifies a step proof, including that it was produced by this step circuit all the way down.
+pub fn verify_cyclic_keccak256_proof(
+    proof: ProofWithPublicInputs<F, C, D>,
+    circuit: &CyclicKeccak256Circuit,
+) -> Result<()> {
+    check_cyclic_proof_verifier_data(&proof, &circuit.data.verifier_only, &circuit.data.common)?;
+    circuit.data.verify(proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use itertools::Itertools;
+    use plonky2::field::types::Field;
+    use plonky2::hash::hash_types::HashOut;
+    use rand::Rng;
+
+    use super::F;
+    use crate::cyclic_keccak256_circuit::{
+        build_cyclic_keccak256_circuit, generate_cyclic_keccak256_proof_with_stats,
+        next_accumulator, verify_cyclic_keccak256_proof, CyclicKeccak256Circuit,
+    };
+
+    #[test]
+    fn test_cyclic_keccak256_circuit() -> Result<()> {
+        let input_lens: Vec<usize> = vec![16, 40];
+        let circuit = build_cyclic_keccak256_circuit(input_lens.clone());
+        let mut rng = rand::thread_rng();
+
+        let mut accumulator = HashOut::<F>::ZERO;
+        let mut proof = None;
+        for step in 1..=3 {
+            let inputs: Vec<Vec<u32>> = input_lens
+                .iter()
+                .map(|&input_len| (0..input_len).map(|_| rng.gen()).collect())
+                .collect_vec();
+            let (new_proof, stats) = generate_cyclic_keccak256_proof_with_stats(
+                inputs.clone(),
+                proof.as_ref(),
+                &circuit,
+            )?;
+            assert_eq!(stats.proof_size, new_proof.to_bytes().len());
+            verify_cyclic_keccak256_proof(new_proof.clone(), &circuit)?;
+
+            accumulator = next_accumulator(accumulator, &inputs);
+            assert_eq!(CyclicKeccak256Circuit::accumulator(&new_proof), accumulator);
+            assert_eq!(
+                CyclicKeccak256Circuit::num_steps(&new_proof),
+                F::from_canonical_usize(step)
+            );
+            proof = Some(new_proof);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,904 @@
+//! EIP-712 typed structured data hashing, natively and in a circuit.
+//!
+//! An `Eip712Schema` holds the struct types, including `EIP712Domain`. Values are given already
+//! encoded: each atomic value (`uintN`, `intN`, `address`, `bool`, `bytesN`) is the 32-byte word
+//! of `encodeData`, and `string` values are their UTF-8 bytes. Type hashes depend only on the
+//! schema, so circuits take them as constants. Every other keccak256 call is a permutation
+//! statement; the circuit functions return them in the order the native functions do, so all of
+//! them are proven together by one `KeccakStark` proof through
+//! `verify_keccak_statements_circuit`.
+//!
+//! In circuits the lengths of `bytes`, `string` and dynamic arrays are fixed by the shape of the
+//! `Eip712ValueTarget`. Atomic words are checked to be valid encodings of their type.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use anyhow::{anyhow, bail, ensure, Result};
+use plonky2::{
+    field::{extension::Extendable, types::Field},
+    hash::hash_types::RichField,
+    iop::{
+        target::{BoolTarget, Target},
+        witness::WitnessWrite,
+    },
+    plonk::circuit_builder::CircuitBuilder,
+};
+use primitive_types::U256;
+
+use crate::keccak256_circuit::{bytes_to_words, keccak256_bytes, keccak256_with_byte_len};
+use crate::types::{Digest256, KeccakState, KeccakStateTarget};
+use crate::u32_keccak256_circuit::keccak256_circuit_checked_bytes;
+
+/// The name of the struct type of the domain separator.
+pub const EIP712_DOMAIN: &str = "EIP712Domain";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Eip712Type {
+    Uint(usize),
+    Int(usize),
+    Address,
+    Bool,
+    FixedBytes(usize),
+    Bytes,
+    String,
+    /// An array of the element type, of fixed length if given.
+    Array(Box<Eip712Type>, Option<usize>),
+    Struct(String),
+}
+
+impl Eip712Type {
+    /// Parses a Solidity type name, e.g. `uint256`, `bytes4`, `Person[]` or `uint8[3]`. Names
+    /// that are not atomic or dynamic types are struct names.
+    pub fn parse(s: &str) -> Result<Self> {
+        if let Some(inner) = s.strip_suffix(']') {
+            let open = inner
+                .rfind('[')
+                .ok_or_else(|| anyhow!("invalid type {}", s))?;
+            let len = &inner[open + 1..];
+            let len = if len.is_empty() {
+                None
+            } else {
+                Some(len.parse()?)
+            };
+            return Ok(Self::Array(Box::new(Self::parse(&inner[..open])?), len));
+        }
+        let ty = match s {
+            "address" => Self::Address,
+            "bool" => Self::Bool,
+            "bytes" => Self::Bytes,
+            "string" => Self::String,
+            _ => {
+                let number =
+                    |prefix: &str| -> Option<usize> { s.strip_prefix(prefix)?.parse().ok() };
+                if let Some(bits) = number("uint") {
+                    Self::Uint(bits)
+                } else if let Some(bits) = number("int") {
+                    Self::Int(bits)
+                } else if let Some(len) = number("bytes") {
+                    Self::FixedBytes(len)
+                } else {
+                    ensure!(
+                        s.chars().next().map_or(false, char::is_alphabetic)
+                            && s.chars().all(|c| c.is_alphanumeric() || c == '_'),
+                        "invalid type {}",
+                        s
+                    );
+                    Self::Struct(s.into())
+                }
+            }
+        };
+        match ty {
+            Self::Uint(bits) | Self::Int(bits) => ensure!(
+                bits % 8 == 0 && (8..=256).contains(&bits),
+                "invalid type {}",
+                s
+            ),
+            Self::FixedBytes(len) => ensure!((1..=32).contains(&len), "invalid type {}", s),
+            _ => {}
+        }
+        Ok(ty)
+    }
+
+    /// Whether values of this type are encoded in place as one word.
+    pub fn is_atomic(&self) -> bool {
+        matches!(
+            self,
+            Self::Uint(_) | Self::Int(_) | Self::Address | Self::Bool | Self::FixedBytes(_)
+        )
+    }
+
+    fn struct_name(&self) -> Option<&str> {
+        match self {
+            Self::Array(element, _) => element.struct_name(),
+            Self::Struct(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The number of leading bytes of an atomic word that must be zero, and the trailing ones.
+    fn zero_bytes(&self) -> (usize, usize) {
+        match self {
+            Self::Uint(bits) => (32 - bits / 8, 0),
+            Self::Address => (12, 0),
+            Self::Bool => (31, 0),
+            Self::FixedBytes(len) => (0, 32 - len),
+            _ => (0, 0),
+        }
+    }
+}
+
+impl fmt::Display for Eip712Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Uint(bits) => write!(f, "uint{}", bits),
+            Self::Int(bits) => write!(f, "int{}", bits),
+            Self::Address => f.write_str("address"),
+            Self::Bool => f.write_str("bool"),
+            Self::FixedBytes(len) => write!(f, "bytes{}", len),
+            Self::Bytes => f.write_str("bytes"),
+            Self::String => f.write_str("string"),
+            Self::Array(element, None) => write!(f, "{}[]", element),
+            Self::Array(element, Some(len)) => write!(f, "{}[{}]", element, len),
+            Self::Struct(name) => f.write_str(name),
+        }
+    }
+}
+
+/// A value of an EIP-712 type, with its atomic values already encoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Eip712Value {
+    Word([u8; 32]),
+    Bytes(Vec<u8>),
+    Array(Vec<Eip712Value>),
+    Struct(Vec<Eip712Value>),
+}
+
+impl Eip712Value {
+    pub fn uint(x: U256) -> Self {
+        let mut word = [0u8; 32];
+        x.to_big_endian(&mut word);
+        Self::Word(word)
+    }
+
+    /// A signed integer, sign-extended to 32 bytes.
+    pub fn int(x: i128) -> Self {
+        let mut word = [if x < 0 { 0xff } else { 0 }; 32];
+        word[16..].copy_from_slice(&x.to_be_bytes());
+        Self::Word(word)
+    }
+
+    pub fn address(address: [u8; 20]) -> Self {
+        let mut word = [0u8; 32];
+        word[12..].copy_from_slice(&address);
+        Self::Word(word)
+    }
+
+    pub fn bool(b: bool) -> Self {
+        Self::uint(U256::from(b as u8))
+    }
+
+    /// A `bytesN` value, padded with zeros on the right.
+    pub fn fixed_bytes(bytes: &[u8]) -> Self {
+        assert!(bytes.len() <= 32, "bytesN holds at most 32 bytes");
+        let mut word = [0u8; 32];
+        word[..bytes.len()].copy_from_slice(bytes);
+        Self::Word(word)
+    }
+
+    pub fn bytes(bytes: &[u8]) -> Self {
+        Self::Bytes(bytes.to_vec())
+    }
+
+    pub fn string(s: &str) -> Self {
+        Self::Bytes(s.as_bytes().to_vec())
+    }
+}
+
+/// The circuit counterpart of `Eip712Value`, with one target per byte.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Eip712ValueTarget {
+    Word([Target; 32]),
+    Bytes(Vec<Target>),
+    Array(Vec<Eip712ValueTarget>),
+    Struct(Vec<Eip712ValueTarget>),
+}
+
+impl Eip712ValueTarget {
+    /// Virtual targets of the same shape as `value`, to be set with `set_witness`.
+    pub fn new_virtual<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        value: &Eip712Value,
+    ) -> Self {
+        match value {
+            Eip712Value::Word(_) => Self::Word(builder.add_virtual_target_arr()),
+            Eip712Value::Bytes(bytes) => Self::Bytes(builder.add_virtual_targets(bytes.len())),
+            Eip712Value::Array(values) => Self::Array(
+                values
+                    .iter()
+                    .map(|value| Self::new_virtual(builder, value))
+                    .collect(),
+            ),
+            Eip712Value::Struct(values) => Self::Struct(
+                values
+                    .iter()
+                    .map(|value| Self::new_virtual(builder, value))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn constant<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        value: &Eip712Value,
+    ) -> Self {
+        match value {
+            Eip712Value::Word(word) => {
+                Self::Word(word.map(|byte| builder.constant(F::from_canonical_u8(byte))))
+            }
+            Eip712Value::Bytes(bytes) => Self::Bytes(
+                bytes
+                    .iter()
+                    .map(|&byte| builder.constant(F::from_canonical_u8(byte)))
+                    .collect(),
+            ),
+            Eip712Value::Array(values) => Self::Array(
+                values
+                    .iter()
+                    .map(|value| Self::constant(builder, value))
+                    .collect(),
+            ),
+            Eip712Value::Struct(values) => Self::Struct(
+                values
+                    .iter()
+                    .map(|value| Self::constant(builder, value))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn set_witness<F: Field>(
+        &self,
+        witness: &mut impl WitnessWrite<F>,
+        value: &Eip712Value,
+    ) -> Result<()> {
+        match (self, value) {
+            (Self::Word(targets), Eip712Value::Word(bytes)) => {
+                for (&t, &byte) in targets.iter().zip(bytes.iter()) {
+                    witness.set_target(t, F::from_canonical_u8(byte));
+                }
+            }
+            (Self::Bytes(targets), Eip712Value::Bytes(bytes)) => {
+                ensure!(
+                    targets.len() == bytes.len(),
+                    "expected {} bytes, got {}",
+                    targets.len(),
+                    bytes.len()
+                );
+                for (&t, &byte) in targets.iter().zip(bytes.iter()) {
+                    witness.set_target(t, F::from_canonical_u8(byte));
+                }
+            }
+            (Self::Array(targets), Eip712Value::Array(values))
+            | (Self::Struct(targets), Eip712Value::Struct(values)) => {
+                ensure!(
+                    targets.len() == values.len(),
+                    "expected {} values, got {}",
+                    targets.len(),
+                    values.len()
+                );
+                for (t, value) in targets.iter().zip(values.iter()) {
+                    t.set_witness(witness, value)?;
+                }
+            }
+            _ => bail!("the value does not have the shape of the targets"),
+        }
+        Ok(())
+    }
+}
+
+fn keccak256_with_statements(
+    input: &[u8],
+    statements: &mut Vec<(KeccakState, KeccakState)>,
+) -> [u8; 32] {
+    let (digest, perms) = keccak256_with_byte_len(bytes_to_words(input), input.len());
+    statements.extend(perms);
+    digest.0
+}
+
+fn check_word(ty: &Eip712Type, word: &[u8; 32]) -> Result<()> {
+    let (leading, trailing) = ty.zero_bytes();
+    let mut valid = word[..leading]
+        .iter()
+        .chain(&word[32 - trailing..])
+        .all(|&b| b == 0);
+    if let Eip712Type::Bool = ty {
+        valid &= word[31] <= 1;
+    }
+    if let Eip712Type::Int(bits) = ty {
+        let n = 32 - bits / 8;
+        let fill = if word[n] >= 0x80 { 0xff } else { 0 };
+        valid &= word[..n].iter().all(|&b| b == fill);
+    }
+    ensure!(valid, "invalid {} encoding", ty);
+    Ok(())
+}
+
+fn check_word_circuit<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    ty: &Eip712Type,
+    word: &[Target; 32],
+) {
+    let (leading, trailing) = ty.zero_bytes();
+    for &byte in word[..leading].iter().chain(&word[32 - trailing..]) {
+        builder.assert_zero(byte);
+    }
+    if let Eip712Type::Bool = ty {
+        builder.assert_bool(BoolTarget::new_unsafe(word[31]));
+    }
+    if let Eip712Type::Int(bits) = ty {
+        let n = 32 - bits / 8;
+        if n > 0 {
+            let sign = builder.split_le(word[n], 8)[7];
+            let fill = builder.mul_const(F::from_canonical_u8(0xff), sign.target);
+            for &byte in &word[..n] {
+                builder.connect(byte, fill);
+            }
+        }
+    }
+}
+
+/// The struct types of an EIP-712 message and its domain.
+#[derive(Clone, Debug, Default)]
+pub struct Eip712Schema {
+    structs: BTreeMap<String, Vec<(String, Eip712Type)>>,
+}
+
+impl Eip712Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a struct type with fields given as `(name, type)`, e.g. `("wallets", "address[]")`.
+    pub fn with_struct(mut self, name: &str, fields: &[(&str, &str)]) -> Result<Self> {
+        ensure!(
+            matches!(Eip712Type::parse(name)?, Eip712Type::Struct(_)),
+            "invalid struct name {}",
+            name
+        );
+        let fields = fields
+            .iter()
+            .map(|&(field, ty)| Ok((field.to_string(), Eip712Type::parse(ty)?)))
+            .collect::<Result<Vec<_>>>()?;
+        ensure!(
+            self.structs.insert(name.into(), fields).is_none(),
+            "struct {} is defined twice",
+            name
+        );
+        Ok(self)
+    }
+
+    fn fields(&self, name: &str) -> Result<&[(String, Eip712Type)]> {
+        self.structs
+            .get(name)
+            .map(Vec::as_slice)
+            .ok_or_else(|| anyhow!("unknown struct {}", name))
+    }
+
+    fn collect_dependencies(&self, name: &str, dependencies: &mut BTreeSet<String>) -> Result<()> {
+        for (_, ty) in self.fields(name)? {
+            if let Some(dependency) = ty.struct_name() {
+                if dependencies.insert(dependency.into()) {
+                    self.collect_dependencies(dependency, dependencies)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `encodeType`: the struct, then the structs it references sorted by name.
+    pub fn encode_type(&self, name: &str) -> Result<String> {
+        let mut dependencies = BTreeSet::new();
+        self.collect_dependencies(name, &mut dependencies)?;
+        dependencies.remove(name);
+        let mut encoded = String::new();
+        for name in [name]
+            .into_iter()
+            .chain(dependencies.iter().map(String::as_str))
+        {
+            let fields = self
+                .fields(name)?
+                .iter()
+                .map(|(field, ty)| format!("{} {}", ty, field))
+                .collect::<Vec<_>>();
+            encoded += &format!("{}({})", name, fields.join(","));
+        }
+        Ok(encoded)
+    }
+
+    pub fn type_hash(&self, name: &str) -> Result<Digest256> {
+        Ok(keccak256_bytes(self.encode_type(name)?.as_bytes()))
+    }
+
+    fn encode_value(
+        &self,
+        ty: &Eip712Type,
+        value: &Eip712Value,
+        statements: &mut Vec<(KeccakState, KeccakState)>,
+    ) -> Result<[u8; 32]> {
+        Ok(match (ty, value) {
+            (ty, Eip712Value::Word(word)) if ty.is_atomic() => {
+                check_word(ty, word)?;
+                *word
+            }
+            (Eip712Type::Bytes | Eip712Type::String, Eip712Value::Bytes(bytes)) => {
+                keccak256_with_statements(bytes, statements)
+            }
+            (Eip712Type::Array(element, len), Eip712Value::Array(values)) => {
+                if let Some(len) = len {
+                    ensure!(values.len() == *len, "expected {} elements", len);
+                }
+                let mut encoded = vec![];
+                for value in values {
+                    encoded.extend(self.encode_value(element, value, statements)?);
+                }
+                keccak256_with_statements(&encoded, statements)
+            }
+            (Eip712Type::Struct(name), Eip712Value::Struct(values)) => {
+                self.hash_struct_inner(name, values, statements)?
+            }
+            _ => bail!("the value does not have the type {}", ty),
+        })
+    }
+
+    fn hash_struct_inner(
+        &self,
+        name: &str,
+        values: &[Eip712Value],
+        statements: &mut Vec<(KeccakState, KeccakState)>,
+    ) -> Result<[u8; 32]> {
+        let fields = self.fields(name)?;
+        ensure!(
+            values.len() == fields.len(),
+            "{} has {} fields, got {}",
+            name,
+            fields.len(),
+            values.len()
+        );
+        let mut encoded = self.type_hash(name)?.0.to_vec();
+        for ((_, ty), value) in fields.iter().zip(values) {
+            encoded.extend(self.encode_value(ty, value, statements)?);
+        }
+        Ok(keccak256_with_statements(&encoded, statements))
+    }
+
+    /// `hashStruct` of a value of the struct type `name`, with the permutation statements of its
+    /// keccak256 calls.
+    pub fn hash_struct(
+        &self,
+        name: &str,
+        value: &Eip712Value,
+    ) -> Result<(Digest256, Vec<(KeccakState, KeccakState)>)> {
+        let mut statements = vec![];
+        let ty = Eip712Type::Struct(name.into());
+        let hash = self.encode_value(&ty, value, &mut statements)?;
+        Ok((Digest256(hash), statements))
+    }
+
+    /// The digest a wallet signs: `keccak256("\x19\x01" ‖ domainSeparator ‖ hashStruct(message))`.
+    pub fn signing_hash(
+        &self,
+        domain: &Eip712Value,
+        primary_type: &str,
+        message: &Eip712Value,
+    ) -> Result<(Digest256, Vec<(KeccakState, KeccakState)>)> {
+        let (domain_separator, mut statements) = self.hash_struct(EIP712_DOMAIN, domain)?;
+        let (hash, message_statements) = self.hash_struct(primary_type, message)?;
+        statements.extend(message_statements);
+        let input = [&[0x19, 0x01][..], &domain_separator.0, &hash.0].concat();
+        let digest = keccak256_with_statements(&input, &mut statements);
+        Ok((Digest256(digest), statements))
+    }
+
+    fn encode_value_circuit<F: RichField + Extendable<D>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        ty: &Eip712Type,
+        value: &Eip712ValueTarget,
+        statements: &mut Vec<(KeccakStateTarget, KeccakStateTarget)>,
+    ) -> Result<[Target; 32]> {
+        Ok(match (ty, value) {
+            (ty, Eip712ValueTarget::Word(word)) if ty.is_atomic() => {
+                for &byte in word {
+                    builder.range_check(byte, 8);
+                }
+                check_word_circuit(builder, ty, word);
+                *word
+            }
+            (Eip712Type::Bytes | Eip712Type::String, Eip712ValueTarget::Bytes(bytes)) => {
+                for &byte in bytes {
+                    builder.range_check(byte, 8);
+                }
+                keccak256_circuit_checked_bytes(builder, bytes, statements)
+            }
+            (Eip712Type::Array(element, len), Eip712ValueTarget::Array(values)) => {
+                if let Some(len) = len {
+                    ensure!(values.len() == *len, "expected {} elements", len);
+                }
+                let mut encoded = vec![];
+                for value in values {
+                    encoded.extend(self.encode_value_circuit(builder, element, value, statements)?);
+                }
+                keccak256_circuit_checked_bytes(builder, &encoded, statements)
+            }
+            (Eip712Type::Struct(name), Eip712ValueTarget::Struct(values)) => {
+                let fields = self.fields(name)?;
+                ensure!(
+                    values.len() == fields.len(),
+                    "{} has {} fields, got {}",
+                    name,
+                    fields.len(),
+                    values.len()
+                );
+                let type_hash = self.type_hash(name)?;
+                let mut encoded = type_hash
+                    .0
+                    .map(|byte| builder.constant(F::from_canonical_u8(byte)))
+                    .to_vec();
+                for ((_, ty), value) in fields.iter().zip(values) {
+                    encoded.extend(self.encode_value_circuit(builder, ty, value, statements)?);
+                }
+                keccak256_circuit_checked_bytes(builder, &encoded, statements)
+            }
+            _ => bail!("the value does not have the type {}", ty),
+        })
+    }
+
+    /// The circuit version of `hash_struct`. The hash is 32 byte targets in digest order.
+    pub fn hash_struct_circuit<F: RichField + Extendable<D>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        name: &str,
+        value: &Eip712ValueTarget,
+    ) -> Result<([Target; 32], Vec<(KeccakStateTarget, KeccakStateTarget)>)> {
+        let mut statements = vec![];
+        let ty = Eip712Type::Struct(name.into());
+        let hash = self.encode_value_circuit(builder, &ty, value, &mut statements)?;
+        Ok((hash, statements))
+    }
+
+    /// The circuit version of `signing_hash`.
+    pub fn signing_hash_circuit<F: RichField + Extendable<D>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        domain: &Eip712ValueTarget,
+        primary_type: &str,
+        message: &Eip712ValueTarget,
+    ) -> Result<([Target; 32], Vec<(KeccakStateTarget, KeccakStateTarget)>)> {
+        let (domain_separator, mut statements) =
+            self.hash_struct_circuit(builder, EIP712_DOMAIN, domain)?;
+        let (hash, message_statements) =
+            self.hash_struct_circuit(builder, primary_type, message)?;
+        statements.extend(message_statements);
+        let prefix = [0x19, 0x01].map(|byte| builder.constant(F::from_canonical_u8(byte)));
+        let input = [&prefix[..], &domain_separator, &hash].concat();
+        let digest = keccak256_circuit_checked_bytes(builder, &input, &mut statements);
+        Ok((digest, statements))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::CircuitConfig,
+        config::{GenericConfig, PoseidonGoldilocksConfig},
+    };
+    use primitive_types::U256;
+    use starky::recursive_verifier::set_stark_proof_with_pis_target;
+    use tiny_keccak::{Hasher, Keccak};
+
+    use crate::eip712::{Eip712Schema, Eip712Type, Eip712Value, Eip712ValueTarget, EIP712_DOMAIN};
+    use crate::keccak256_circuit::{prove_keccak_statements, verify_keccak_statements_circuit};
+    use crate::types::Digest256;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    fn address(s: &str) -> Eip712Value {
+        let mut bytes = [0u8; 20];
+        bytes.copy_from_slice(&Digest256::from_hex(&format!("{:0>64}", s)).unwrap().0[12..]);
+        Eip712Value::address(bytes)
+    }
+
+    /// The example of the EIP-712 specification.
+    fn mail() -> (Eip712Schema, Eip712Value, Eip712Value) {
+        let schema = Eip712Schema::new()
+            .with_struct(
+                EIP712_DOMAIN,
+                &[
+                    ("name", "string"),
+                    ("version", "string"),
+                    ("chainId", "uint256"),
+                    ("verifyingContract", "address"),
+                ],
+            )
+            .unwrap()
+            .with_struct("Person", &[("name", "string"), ("wallet", "address")])
+            .unwrap()
+            .with_struct(
+                "Mail",
+                &[("from", "Person"), ("to", "Person"), ("contents", "string")],
+            )
+            .unwrap();
+        let domain = Eip712Value::Struct(vec![
+            Eip712Value::string("Ether Mail"),
+            Eip712Value::string("1"),
+            Eip712Value::uint(U256::one()),
+            address("CcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"),
+        ]);
+        let message = Eip712Value::Struct(vec![
+            Eip712Value::Struct(vec![
+                Eip712Value::string("Cow"),
+                address("CD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"),
+            ]),
+            Eip712Value::Struct(vec![
+                Eip712Value::string("Bob"),
+                address("bBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"),
+            ]),
+            Eip712Value::string("Hello, Bob!"),
+        ]);
+        (schema, domain, message)
+    }
+
+    /// The `test_encode_custom_array_type` example of ethers-core 2.0.14
+    /// (`src/types/transaction/eip712.rs`), whose signing hash is
+    /// `0x80a3aeb51161cfc47884ddf8eac0d2343d6ae640efe78b6a69be65e3045c1321`.
+    fn mail_with_arrays() -> (Eip712Schema, Eip712Value, Eip712Value) {
+        let schema = Eip712Schema::new()
+            .with_struct(EIP712_DOMAIN, &[])
+            .unwrap()
+            .with_struct("Person", &[("name", "string"), ("wallet", "address[]")])
+            .unwrap()
+            .with_struct(
+                "Mail",
+                &[
+                    ("from", "Person"),
+                    ("to", "Person[]"),
+                    ("contents", "string"),
+                ],
+            )
+            .unwrap();
+        let domain = Eip712Value::Struct(vec![]);
+        let message = Eip712Value::Struct(vec![
+            Eip712Value::Struct(vec![
+                Eip712Value::string("Cow"),
+                Eip712Value::Array(vec![
+                    address("CD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"),
+                    address("DD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"),
+                ]),
+            ]),
+            Eip712Value::Array(vec![Eip712Value::Struct(vec![
+                Eip712Value::string("Bob"),
+                Eip712Value::Array(vec![address("bBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB")]),
+            ])]),
+            Eip712Value::string("Hello, Bob!"),
+        ]);
+        (schema, domain, message)
+    }
+
+    /// Arrays of structs and addresses, `bytes` and the atomic types.
+    fn group() -> (Eip712Schema, Eip712Value, Eip712Value) {
+        let schema = Eip712Schema::new()
+            .with_struct(EIP712_DOMAIN, &[("name", "string"), ("chainId", "uint256")])
+            .unwrap()
+            .with_struct("Person", &[("name", "string"), ("wallets", "address[]")])
+            .unwrap()
+            .with_struct(
+                "Group",
+                &[
+                    ("name", "string"),
+                    ("members", "Person[]"),
+                    ("data", "bytes"),
+                    ("delta", "int64"),
+                    ("active", "bool"),
+                    ("tag", "bytes4"),
+                    ("flags", "uint8[3]"),
+                ],
+            )
+            .unwrap();
+        let domain = Eip712Value::Struct(vec![
+            Eip712Value::string("Groups"),
+            Eip712Value::uint(U256::from(5)),
+        ]);
+        let message = Eip712Value::Struct(vec![
+            Eip712Value::string("Friends"),
+            Eip712Value::Array(vec![
+                Eip712Value::Struct(vec![
+                    Eip712Value::string("Cow"),
+                    Eip712Value::Array(vec![
+                        address("CD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"),
+                        address("DeaDbeefdEAdbeefdEadbEEFdeadbeEFdEaDbeeF"),
+                    ]),
+                ]),
+                Eip712Value::Struct(vec![
+                    Eip712Value::string("Bob"),
+                    Eip712Value::Array(vec![address("bBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB")]),
+                ]),
+            ]),
+            Eip712Value::bytes(&(0..40).collect::<Vec<u8>>()),
+            Eip712Value::int(-5),
+            Eip712Value::bool(true),
+            Eip712Value::fixed_bytes(&[0x12, 0x34, 0x56, 0x78]),
+            Eip712Value::Array((1u64..=3).map(|x| Eip712Value::uint(x.into())).collect()),
+        ]);
+        (schema, domain, message)
+    }
+
+    #[test]
+    fn test_eip712_mail() {
+        let (schema, domain, message) = mail();
+        assert_eq!(
+            schema.encode_type("Mail").unwrap(),
+            "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+        );
+        assert_eq!(
+            schema.type_hash("Mail").unwrap().to_hex(),
+            "0xa0cedeb2dc280ba39b857546d74f5549c3a1d7bdc2dd96bf881f76108e23dac2"
+        );
+        assert_eq!(
+            schema.hash_struct("Mail", &message).unwrap().0.to_hex(),
+            "0xc52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e"
+        );
+        assert_eq!(
+            schema
+                .hash_struct(EIP712_DOMAIN, &domain)
+                .unwrap()
+                .0
+                .to_hex(),
+            "0xf2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+        );
+        assert_eq!(
+            schema
+                .signing_hash(&domain, "Mail", &message)
+                .unwrap()
+                .0
+                .to_hex(),
+            "0xbe609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+    }
+
+    #[test]
+    fn test_eip712_arrays() {
+        let (schema, domain, message) = mail_with_arrays();
+        assert_eq!(
+            schema
+                .signing_hash(&domain, "Mail", &message)
+                .unwrap()
+                .0
+                .to_hex(),
+            "0x80a3aeb51161cfc47884ddf8eac0d2343d6ae640efe78b6a69be65e3045c1321"
+        );
+    }
+
+    #[test]
+    fn test_eip712_types() {
+        for s in [
+            "uint8",
+            "int256",
+            "bytes32",
+            "address",
+            "Person[]",
+            "uint8[3][]",
+        ] {
+            assert_eq!(Eip712Type::parse(s).unwrap().to_string(), s);
+        }
+        for s in ["uint7", "uint264", "bytes0", "bytes33", "uint8[x]", "a-b"] {
+            assert!(Eip712Type::parse(s).is_err());
+        }
+
+        let (schema, domain, _) = mail();
+        let mut message = mail().2;
+        if let Eip712Value::Struct(fields) = &mut message {
+            // An address word with a nonzero high byte.
+            fields[0] = Eip712Value::Struct(vec![
+                Eip712Value::string("Cow"),
+                Eip712Value::uint(U256::MAX),
+            ]);
+        }
+        assert!(schema.signing_hash(&domain, "Mail", &message).is_err());
+    }
+
+    #[test]
+    fn test_eip712_circuit() {
+        let (schema, domain, message) = group();
+        let (digest, statements) = schema.signing_hash(&domain, "Group", &message).unwrap();
+
+        // `encodeData` of the specification written out for `group`, hashed with tiny-keccak.
+        let keccak = |parts: &[&[u8]]| {
+            let mut hasher = Keccak::v256();
+            parts.iter().for_each(|part| hasher.update(part));
+            let mut output = [0u8; 32];
+            hasher.finalize(&mut output);
+            output
+        };
+        let word = |x: u64| {
+            let mut word = [0u8; 32];
+            word[24..].copy_from_slice(&x.to_be_bytes());
+            word
+        };
+        let address_word = |s: &str| Digest256::from_hex(&format!("{:0>64}", s)).unwrap().0;
+        let person_type = keccak(&[b"Person(string name,address[] wallets)"]);
+        let cow = keccak(&[
+            &person_type,
+            &keccak(&[b"Cow"]),
+            &keccak(&[
+                &address_word("CD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"),
+                &address_word("DeaDbeefdEAdbeefdEadbEEFdeadbeEFdEaDbeeF"),
+            ]),
+        ]);
+        let bob = keccak(&[
+            &person_type,
+            &keccak(&[b"Bob"]),
+            &keccak(&[&address_word("bBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB")]),
+        ]);
+        let mut minus_five = [0xff; 32];
+        minus_five[31] = 0xfb;
+        let mut tag = [0u8; 32];
+        tag[..4].copy_from_slice(&[0x12, 0x34, 0x56, 0x78]);
+        let group_hash = keccak(&[
+            &keccak(&[
+                b"Group(string name,Person[] members,bytes data,int64 delta,bool active,\
+                        bytes4 tag,uint8[3] flags)Person(string name,address[] wallets)",
+            ]),
+            &keccak(&[b"Friends"]),
+            &keccak(&[&cow, &bob]),
+            &keccak(&[&(0..40).collect::<Vec<u8>>()]),
+            &minus_five,
+            &word(1),
+            &tag,
+            &keccak(&[&word(1), &word(2), &word(3)]),
+        ]);
+        let domain_separator = keccak(&[
+            &keccak(&[b"EIP712Domain(string name,uint256 chainId)"]),
+            &keccak(&[b"Groups"]),
+            &word(5),
+        ]);
+        assert_eq!(
+            schema.hash_struct("Group", &message).unwrap().0 .0,
+            group_hash
+        );
+        assert_eq!(
+            digest.0,
+            keccak(&[&[0x19, 0x01], &domain_separator, &group_hash])
+        );
+
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let domain_t = Eip712ValueTarget::new_virtual(&mut builder, &domain);
+        let message_t = Eip712ValueTarget::new_virtual(&mut builder, &message);
+        let (digest_t, statements_t) = schema
+            .signing_hash_circuit(&mut builder, &domain_t, "Group", &message_t)
+            .unwrap();
+        assert_eq!(statements_t.len(), statements.len());
+        let stark_proof_t = verify_keccak_statements_circuit(&mut builder, &statements_t);
+        for (byte_t, byte) in digest_t.into_iter().zip(digest.0) {
+            let expected = builder.constant(F::from_canonical_u8(byte));
+            builder.connect(byte_t, expected);
+        }
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        domain_t.set_witness(&mut pw, &domain).unwrap();
+        message_t.set_witness(&mut pw, &message).unwrap();
+        set_stark_proof_with_pis_target(
+            &mut pw,
+            &stark_proof_t,
+            &prove_keccak_statements(&statements),
+        );
+        data.verify(data.prove(pw).unwrap()).unwrap();
+    }
+}
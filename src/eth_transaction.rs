@@ -0,0 +1,809 @@
+//! Hashes of Ethereum transactions, natively and in a circuit.
+//!
+//! The signing hash is what the sender signs with ECDSA, and the transaction hash identifies the
+//! signed transaction. Both are keccak256 of the RLP list of the fields, prefixed by the type
+//! byte for typed transactions (EIP-2718). A legacy transaction with a chain id signs the EIP-155
+//! encoding, which appends `chain_id, 0, 0` to its fields.
+//!
+//! `Transaction::decode` reads a signed transaction back from the bytes that were broadcast.
+//!
+//! A `TransactionTarget` has the RLP layout of the transaction it was created from, so its
+//! circuit proves transactions of the same type whose fields have the same byte lengths.
+
+use anyhow::{anyhow, bail, ensure, Result};
+use itertools::Itertools;
+use plonky2::{
+    field::{extension::Extendable, types::Field},
+    hash::hash_types::RichField,
+    iop::{
+        target::{BoolTarget, Target},
+        witness::WitnessWrite,
+    },
+    plonk::circuit_builder::CircuitBuilder,
+};
+use primitive_types::U256;
+
+use crate::keccak256_circuit::{bytes_to_words, keccak256_with_byte_len};
+use crate::rlp::{rlp_decode, rlp_encode_list, rlp_encode_list_circuit, RlpItem, RlpItemTarget};
+use crate::types::{Digest256, KeccakState, KeccakStateTarget};
+use crate::u32_keccak256_circuit::keccak256_circuit_checked_bytes;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccessListItem {
+    pub address: [u8; 20],
+    pub storage_keys: Vec<[u8; 32]>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Transaction {
+    /// A legacy transaction, replay-protected by EIP-155 if it has a chain id. `to` is `None`
+    /// for a contract creation.
+    Legacy {
+        chain_id: Option<u64>,
+        nonce: u64,
+        gas_price: U256,
+        gas_limit: u64,
+        to: Option<[u8; 20]>,
+        value: U256,
+        data: Vec<u8>,
+    },
+    /// An EIP-2930 transaction, of type 1.
+    Eip2930 {
+        chain_id: u64,
+        nonce: u64,
+        gas_price: U256,
+        gas_limit: u64,
+        to: Option<[u8; 20]>,
+        value: U256,
+        data: Vec<u8>,
+        access_list: Vec<AccessListItem>,
+    },
+    /// An EIP-1559 transaction, of type 2.
+    Eip1559 {
+        chain_id: u64,
+        nonce: u64,
+        max_priority_fee_per_gas: U256,
+        max_fee_per_gas: U256,
+        gas_limit: u64,
+        to: Option<[u8; 20]>,
+        value: U256,
+        data: Vec<u8>,
+        access_list: Vec<AccessListItem>,
+    },
+    /// An EIP-4844 blob transaction, of type 3. It cannot create a contract.
+    Eip4844 {
+        chain_id: u64,
+        nonce: u64,
+        max_priority_fee_per_gas: U256,
+        max_fee_per_gas: U256,
+        gas_limit: u64,
+        to: [u8; 20],
+        value: U256,
+        data: Vec<u8>,
+        access_list: Vec<AccessListItem>,
+        max_fee_per_blob_gas: U256,
+        blob_versioned_hashes: Vec<[u8; 32]>,
+    },
+}
+
+/// An ECDSA signature as encoded in a transaction. `v` is the y parity for typed transactions,
+/// and `27 + y_parity` or, with EIP-155, `2 * chain_id + 35 + y_parity` for legacy ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Signature {
+    pub v: u64,
+    pub r: U256,
+    pub s: U256,
+}
+
+fn to_item(to: Option<&[u8; 20]>) -> RlpItem {
+    RlpItem::bytes(to.map_or(&[][..], |to| &to[..]))
+}
+
+fn access_list_item(access_list: &[AccessListItem]) -> RlpItem {
+    RlpItem::List(
+        access_list
+            .iter()
+            .map(|item| {
+                RlpItem::List(vec![
+                    RlpItem::bytes(&item.address),
+                    RlpItem::List(
+                        item.storage_keys
+                            .iter()
+                            .map(|key| RlpItem::bytes(key))
+                            .collect(),
+                    ),
+                ])
+            })
+            .collect(),
+    )
+}
+
+fn parse_string(item: &RlpItem) -> Result<&[u8]> {
+    match item {
+        RlpItem::String { bytes, .. } => Ok(bytes),
+        RlpItem::List(_) => bail!("expected an RLP string, got a list"),
+    }
+}
+
+fn parse_list(item: &RlpItem) -> Result<&[RlpItem]> {
+    match item {
+        RlpItem::List(items) => Ok(items),
+        RlpItem::String { .. } => bail!("expected an RLP list, got a string"),
+    }
+}
+
+fn parse_uint(item: &RlpItem) -> Result<U256> {
+    let bytes = parse_string(item)?;
+    ensure!(bytes.len() <= 32, "integer of {} bytes", bytes.len());
+    Ok(U256::from_big_endian(bytes))
+}
+
+fn parse_u64(item: &RlpItem) -> Result<u64> {
+    let x = parse_uint(item)?;
+    ensure!(
+        x <= U256::from(u64::MAX),
+        "integer {} does not fit in 64 bits",
+        x
+    );
+    Ok(x.as_u64())
+}
+
+fn parse_fixed_bytes<const N: usize>(item: &RlpItem) -> Result<[u8; N]> {
+    let bytes = parse_string(item)?;
+    bytes
+        .try_into()
+        .map_err(|_| anyhow!("expected {} bytes, got {}", N, bytes.len()))
+}
+
+fn parse_to(item: &RlpItem) -> Result<Option<[u8; 20]>> {
+    if parse_string(item)?.is_empty() {
+        Ok(None)
+    } else {
+        parse_fixed_bytes(item).map(Some)
+    }
+}
+
+fn parse_access_list(item: &RlpItem) -> Result<Vec<AccessListItem>> {
+    parse_list(item)?
+        .iter()
+        .map(|entry| {
+            let entry = parse_list(entry)?;
+            ensure!(entry.len() == 2, "an access list entry has 2 fields");
+            Ok(AccessListItem {
+                address: parse_fixed_bytes(&entry[0])?,
+                storage_keys: parse_list(&entry[1])?
+                    .iter()
+                    .map(parse_fixed_bytes::<32>)
+                    .collect::<Result<_>>()?,
+            })
+        })
+        .collect()
+}
+
+fn keccak256_with_statements(input: &[u8]) -> (Digest256, Vec<(KeccakState, KeccakState)>) {
+    keccak256_with_byte_len(bytes_to_words(input), input.len())
+}
+
+impl Transaction {
+    /// The EIP-2718 type byte, `None` for a legacy transaction.
+    pub fn tx_type(&self) -> Option<u8> {
+        match self {
+            Self::Legacy { .. } => None,
+            Self::Eip2930 { .. } => Some(1),
+            Self::Eip1559 { .. } => Some(2),
+            Self::Eip4844 { .. } => Some(3),
+        }
+    }
+
+    /// The fields of both encodings, in order, before the signature.
+    pub fn fields(&self) -> Vec<RlpItem> {
+        match self {
+            Self::Legacy {
+                nonce,
+                gas_price,
+                gas_limit,
+                to,
+                value,
+                data,
+                ..
+            } => vec![
+                RlpItem::u64(*nonce),
+                RlpItem::uint(*gas_price),
+                RlpItem::u64(*gas_limit),
+                to_item(to.as_ref()),
+                RlpItem::uint(*value),
+                RlpItem::bytes(data),
+            ],
+            Self::Eip2930 {
+                chain_id,
+                nonce,
+                gas_price,
+                gas_limit,
+                to,
+                value,
+                data,
+                access_list,
+            } => vec![
+                RlpItem::u64(*chain_id),
+                RlpItem::u64(*nonce),
+                RlpItem::uint(*gas_price),
+                RlpItem::u64(*gas_limit),
+                to_item(to.as_ref()),
+                RlpItem::uint(*value),
+                RlpItem::bytes(data),
+                access_list_item(access_list),
+            ],
+            Self::Eip1559 {
+                chain_id,
+                nonce,
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+                gas_limit,
+                to,
+                value,
+                data,
+                access_list,
+            } => vec![
+                RlpItem::u64(*chain_id),
+                RlpItem::u64(*nonce),
+                RlpItem::uint(*max_priority_fee_per_gas),
+                RlpItem::uint(*max_fee_per_gas),
+                RlpItem::u64(*gas_limit),
+                to_item(to.as_ref()),
+                RlpItem::uint(*value),
+                RlpItem::bytes(data),
+                access_list_item(access_list),
+            ],
+            Self::Eip4844 {
+                chain_id,
+                nonce,
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+                gas_limit,
+                to,
+                value,
+                data,
+                access_list,
+                max_fee_per_blob_gas,
+                blob_versioned_hashes,
+            } => vec![
+                RlpItem::u64(*chain_id),
+                RlpItem::u64(*nonce),
+                RlpItem::uint(*max_priority_fee_per_gas),
+                RlpItem::uint(*max_fee_per_gas),
+                RlpItem::u64(*gas_limit),
+                to_item(Some(to)),
+                RlpItem::uint(*value),
+                RlpItem::bytes(data),
+                access_list_item(access_list),
+                RlpItem::uint(*max_fee_per_blob_gas),
+                RlpItem::List(
+                    blob_versioned_hashes
+                        .iter()
+                        .map(|hash| RlpItem::bytes(hash))
+                        .collect(),
+                ),
+            ],
+        }
+    }
+
+    /// The chain id a legacy transaction signs with EIP-155.
+    fn eip155_chain_id(&self) -> Option<u64> {
+        match self {
+            Self::Legacy { chain_id, .. } => *chain_id,
+            _ => None,
+        }
+    }
+
+    fn envelope(&self, items: &[RlpItem]) -> Vec<u8> {
+        let prefix = self
+            .tx_type()
+            .map(|tx_type| vec![tx_type])
+            .unwrap_or_default();
+        [prefix, rlp_encode_list(items)].concat()
+    }
+
+    /// The bytes the sender signs.
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let mut items = self.fields();
+        if let Some(chain_id) = self.eip155_chain_id() {
+            items.extend([RlpItem::u64(chain_id), RlpItem::u64(0), RlpItem::u64(0)]);
+        }
+        self.envelope(&items)
+    }
+
+    /// The bytes of the signed transaction, as broadcast.
+    pub fn signed_payload(&self, signature: &Signature) -> Vec<u8> {
+        let mut items = self.fields();
+        items.extend([
+            RlpItem::u64(signature.v),
+            RlpItem::uint(signature.r),
+            RlpItem::uint(signature.s),
+        ]);
+        self.envelope(&items)
+    }
+
+    /// Decodes a signed transaction from the bytes of `signed_payload`, which must be canonical.
+    pub fn decode(raw: &[u8]) -> Result<(Self, Signature)> {
+        let (tx_type, list) = match raw.first() {
+            Some(&tx_type @ 1..=3) => (Some(tx_type), &raw[1..]),
+            Some(0xc0..=0xff) => (None, raw),
+            _ => bail!("not a legacy transaction or one of type 1, 2 or 3"),
+        };
+        let items = rlp_decode(list)?;
+        let items = parse_list(&items)?;
+        let num_fields = match tx_type {
+            None => 6,
+            Some(1) => 8,
+            Some(2) => 9,
+            _ => 11,
+        };
+        ensure!(
+            items.len() == num_fields + 3,
+            "expected {} items, got {}",
+            num_fields + 3,
+            items.len()
+        );
+        let (f, sig) = items.split_at(num_fields);
+        let signature = Signature {
+            v: parse_u64(&sig[0])?,
+            r: parse_uint(&sig[1])?,
+            s: parse_uint(&sig[2])?,
+        };
+        ensure!(
+            tx_type.is_none() || signature.v <= 1,
+            "invalid y parity {}",
+            signature.v
+        );
+        let tx = match tx_type {
+            None => Self::Legacy {
+                chain_id: match signature.v {
+                    27 | 28 => None,
+                    v if v >= 35 => Some((v - 35) / 2),
+                    v => bail!("invalid legacy v {}", v),
+                },
+                nonce: parse_u64(&f[0])?,
+                gas_price: parse_uint(&f[1])?,
+                gas_limit: parse_u64(&f[2])?,
+                to: parse_to(&f[3])?,
+                value: parse_uint(&f[4])?,
+                data: parse_string(&f[5])?.to_vec(),
+            },
+            Some(1) => Self::Eip2930 {
+                chain_id: parse_u64(&f[0])?,
+                nonce: parse_u64(&f[1])?,
+                gas_price: parse_uint(&f[2])?,
+                gas_limit: parse_u64(&f[3])?,
+                to: parse_to(&f[4])?,
+                value: parse_uint(&f[5])?,
+                data: parse_string(&f[6])?.to_vec(),
+                access_list: parse_access_list(&f[7])?,
+            },
+            Some(2) => Self::Eip1559 {
+                chain_id: parse_u64(&f[0])?,
+                nonce: parse_u64(&f[1])?,
+                max_priority_fee_per_gas: parse_uint(&f[2])?,
+                max_fee_per_gas: parse_uint(&f[3])?,
+                gas_limit: parse_u64(&f[4])?,
+                to: parse_to(&f[5])?,
+                value: parse_uint(&f[6])?,
+                data: parse_string(&f[7])?.to_vec(),
+                access_list: parse_access_list(&f[8])?,
+            },
+            _ => Self::Eip4844 {
+                chain_id: parse_u64(&f[0])?,
+                nonce: parse_u64(&f[1])?,
+                max_priority_fee_per_gas: parse_uint(&f[2])?,
+                max_fee_per_gas: parse_uint(&f[3])?,
+                gas_limit: parse_u64(&f[4])?,
+                to: parse_fixed_bytes(&f[5])?,
+                value: parse_uint(&f[6])?,
+                data: parse_string(&f[7])?.to_vec(),
+                access_list: parse_access_list(&f[8])?,
+                max_fee_per_blob_gas: parse_uint(&f[9])?,
+                blob_versioned_hashes: parse_list(&f[10])?
+                    .iter()
+                    .map(parse_fixed_bytes::<32>)
+                    .collect::<Result<_>>()?,
+            },
+        };
+        // Integers with leading zeros decode, but do not encode back.
+        ensure!(
+            tx.signed_payload(&signature) == raw,
+            "non-canonical transaction encoding"
+        );
+        Ok((tx, signature))
+    }
+
+    /// The signing hash, with the permutation statements of its keccak256.
+    pub fn signing_hash(&self) -> (Digest256, Vec<(KeccakState, KeccakState)>) {
+        keccak256_with_statements(&self.signing_payload())
+    }
+
+    /// The transaction hash, with the permutation statements of its keccak256.
+    pub fn tx_hash(&self, signature: &Signature) -> (Digest256, Vec<(KeccakState, KeccakState)>) {
+        keccak256_with_statements(&self.signed_payload(signature))
+    }
+}
+
+/// The circuit counterpart of a signed `Transaction`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactionTarget {
+    pub tx_type: Option<u8>,
+    pub fields: Vec<RlpItemTarget>,
+    /// The EIP-155 chain id of a legacy transaction.
+    pub chain_id: Option<RlpItemTarget>,
+    /// `v`, `r` and `s`.
+    pub signature: [RlpItemTarget; 3],
+}
+
+/// The value of a scalar of at most 7 bytes, which cannot wrap around the field.
+fn small_scalar_value<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    item: &RlpItemTarget,
+) -> Target {
+    let bytes = match item {
+        RlpItemTarget::String {
+            bytes,
+            scalar: true,
+            ..
+        } => bytes,
+        _ => panic!("expected a scalar"),
+    };
+    assert!(bytes.len() <= 7, "the scalar is too long");
+    let zero = builder.zero();
+    let base = F::from_canonical_u32(1 << 8);
+    bytes
+        .iter()
+        .fold(zero, |acc, &byte| builder.mul_const_add(base, acc, byte))
+}
+
+impl TransactionTarget {
+    /// Virtual targets with the layout of `tx` signed with `signature`, to be set with
+    /// `set_witness`. `v` is constrained to encode a y parity, for the chain id of an EIP-155
+    /// transaction.
+    pub fn new_virtual<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        tx: &Transaction,
+        signature: &Signature,
+    ) -> Self {
+        let fields = tx
+            .fields()
+            .iter()
+            .map(|item| RlpItemTarget::new_virtual(builder, item))
+            .collect_vec();
+        let chain_id = tx
+            .eip155_chain_id()
+            .map(|chain_id| RlpItemTarget::new_virtual(builder, &RlpItem::u64(chain_id)));
+        let signature = [
+            RlpItem::u64(signature.v),
+            RlpItem::uint(signature.r),
+            RlpItem::uint(signature.s),
+        ]
+        .map(|item| RlpItemTarget::new_virtual(builder, &item));
+
+        let v = small_scalar_value(builder, &signature[0]);
+        let y_parity = match (tx.tx_type(), &chain_id) {
+            (Some(_), _) => v,
+            (None, Some(chain_id)) => {
+                let chain_id = small_scalar_value(builder, chain_id);
+                let offset = builder.mul_const(F::TWO, chain_id);
+                let offset = builder.add_const(offset, F::from_canonical_u32(35));
+                builder.sub(v, offset)
+            }
+            (None, None) => builder.add_const(v, -F::from_canonical_u32(27)),
+        };
+        builder.assert_bool(BoolTarget::new_unsafe(y_parity));
+
+        Self {
+            tx_type: tx.tx_type(),
+            fields,
+            chain_id,
+            signature,
+        }
+    }
+
+    pub fn set_witness<F: Field>(
+        &self,
+        witness: &mut impl WitnessWrite<F>,
+        tx: &Transaction,
+        signature: &Signature,
+    ) -> Result<()> {
+        ensure!(
+            tx.tx_type() == self.tx_type
+                && tx.eip155_chain_id().is_some() == self.chain_id.is_some(),
+            "the transaction does not have the type of the targets"
+        );
+        for (t, item) in self.fields.iter().zip(tx.fields().iter()) {
+            t.set_witness(witness, item)?;
+        }
+        if let (Some(t), Some(chain_id)) = (&self.chain_id, tx.eip155_chain_id()) {
+            t.set_witness(witness, &RlpItem::u64(chain_id))?;
+        }
+        let items = [
+            RlpItem::u64(signature.v),
+            RlpItem::uint(signature.r),
+            RlpItem::uint(signature.s),
+        ];
+        for (t, item) in self.signature.iter().zip(items.iter()) {
+            t.set_witness(witness, item)?;
+        }
+        Ok(())
+    }
+
+    fn hash_circuit<F: RichField + Extendable<D>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        items: &[RlpItemTarget],
+    ) -> ([Target; 32], Vec<(KeccakStateTarget, KeccakStateTarget)>) {
+        let mut payload = self
+            .tx_type
+            .map(|tx_type| vec![builder.constant(F::from_canonical_u8(tx_type))])
+            .unwrap_or_default();
+        payload.extend(rlp_encode_list_circuit(builder, items));
+        let mut statements = vec![];
+        let hash = keccak256_circuit_checked_bytes(builder, &payload, &mut statements);
+        (hash, statements)
+    }
+
+    /// The circuit version of `Transaction::signing_hash`. The hash is 32 byte targets in digest
+    /// order, and the statements must be verified with `verify_keccak_statements_circuit`.
+    pub fn signing_hash_circuit<F: RichField + Extendable<D>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> ([Target; 32], Vec<(KeccakStateTarget, KeccakStateTarget)>) {
+        let mut items = self.fields.clone();
+        if let Some(chain_id) = &self.chain_id {
+            let zero = RlpItemTarget::String {
+                bytes: vec![],
+                scalar: true,
+                compact: false,
+            };
+            items.extend([chain_id.clone(), zero.clone(), zero]);
+        }
+        self.hash_circuit(builder, &items)
+    }
+
+    /// The circuit version of `Transaction::tx_hash`.
+    pub fn tx_hash_circuit<F: RichField + Extendable<D>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> ([Target; 32], Vec<(KeccakStateTarget, KeccakStateTarget)>) {
+        let items = [&self.fields[..], &self.signature[..]].concat();
+        self.hash_circuit(builder, &items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::CircuitConfig,
+        config::{GenericConfig, PoseidonGoldilocksConfig},
+    };
+    use primitive_types::U256;
+    use starky::recursive_verifier::set_stark_proof_with_pis_target;
+
+    use crate::eth_transaction::{Signature, Transaction, TransactionTarget};
+    use crate::keccak256_circuit::{prove_keccak_statements, verify_keccak_statements_circuit};
+    use crate::types::Digest256;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    const GWEI: u64 = 1_000_000_000;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn u256(s: &str) -> U256 {
+        U256::from_str_radix(s, 16).unwrap()
+    }
+
+    fn ether() -> U256 {
+        U256::from(GWEI) * U256::from(GWEI)
+    }
+
+    /// The example of EIP-155, with its signature.
+    fn eip155_example() -> (Transaction, Signature) {
+        let tx = Transaction::Legacy {
+            chain_id: Some(1),
+            nonce: 9,
+            gas_price: (20 * GWEI).into(),
+            gas_limit: 21000,
+            to: Some([0x35; 20]),
+            value: ether(),
+            data: vec![],
+        };
+        let signature = Signature {
+            v: 37,
+            r: u256("28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276"),
+            s: u256("67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"),
+        };
+        (tx, signature)
+    }
+
+    /// Signed transactions of every type, legacy first, as broadcast on mainnet and Sepolia: the
+    /// raw bytes, the published transaction hash and the signing hash. Each raw transaction hashes
+    /// to its published hash. The signing hashes are not published, but the signatures recover the
+    /// published senders from them, except for the type 1 transaction whose sender is not quoted
+    /// by its source.
+    const SIGNED_TXS: [(&str, &str, &str); 4] = [
+        (
+            // https://etherscan.io/tx/0x280cde7cdefe4b188750e76c888f13bd05ce9a4d7767730feefe8a0e50ca6fc4
+            // as in alloy-consensus 1.8.3, `test_decode_live_legacy_tx` in
+            // src/transaction/envelope.rs. The signing hash recovers its sender,
+            // 0xa12e1462d0ced572f396f58b6e2d03894cd7c8a4.
+            concat!(
+                "f9015482078b8505d21dba0083022ef1947a250d5630b4cf539739df2c5dacb4c659f2488d880c46549a",
+                "521b13d8b8e47ff36ab50000000000000000000000000000000000000000000066ab5a608bd00a23f2fe",
+                "000000000000000000000000000000000000000000000000000000000000008000000000000000000000",
+                "000048c04ed5691981c42154c6167398f95e8f38a7ff0000000000000000000000000000000000000000",
+                "0000000000000000632ceac7000000000000000000000000000000000000000000000000000000000000",
+                "0002000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc20000000000000000",
+                "000000006c6ee5e31d828de241282b9606c8e98ea48526e225a0c9077369501641a92ef7399ff81c2163",
+                "9ed4fd8fc69cb793cfa1dbfab342e10aa0615facb2f1bcf3274a354cfe384a38d0cc008a11c2dd23a691",
+                "11bc6930ba27a8",
+            ),
+            "280cde7cdefe4b188750e76c888f13bd05ce9a4d7767730feefe8a0e50ca6fc4",
+            "379ff32b417de419215242f8c5c2f7fe533948b45f0dbe842f7300f889b263ef",
+        ),
+        (
+            // https://etherscan.io/tx/0x6d38fc8aee934858815ed41273cece3b676c368e9c6e39f172313a0685e1f175
+            // as in ethers-core 2.0.14, `test_rlp_decoding_issue_1848_second` in
+            // src/types/transaction/response.rs. The signing hash recovers
+            // 0x2360f8fedf7d0e2a121fae5bd83e8ff55e8e9745.
+            concat!(
+                "01f8ee0182034c853d9f1b88158307a120940087bb802d9c0e343f00510000729031ce00bf2780b8841e",
+                "1326a300000000000000000000000088e6a0c2ddd26feeb64f039a2c41296fcb3f564000000000000000",
+                "00000000000000000000000000000000000000001d3b3e73000000000000000000000000000000000000",
+                "0000000000000596b93e5369674000000000000000000000000000000000000000000000000000000000",
+                "0000000001c001a0bbfd754ed51b34d0a8577f69b4c42ce6b47fee6ecf49114bb135e7e8eadbb336a043",
+                "3692134eb7e7686e9aefafa9f69c601aa977c00cc85c827782f5fb1f1cff0f",
+            ),
+            "6d38fc8aee934858815ed41273cece3b676c368e9c6e39f172313a0685e1f175",
+            "06ad0de969ee4e9f9940a5052540550f74bb3bf967c7cf7664640a684c0d2409",
+        ),
+        (
+            // https://etherscan.io/tx/0xce4dc6d7a7549a98ee3b071b67e970879ff51b5b95d1c340bacd80fa1e1aab31
+            // as in alloy-consensus 1.8.3, `test_decode_live_1559_tx`. The signing hash recovers
+            // its sender, 0x001e2b7de757ba469a57bf6b23d982458a07efce.
+            concat!(
+                "02f86f0102843b9aca0085029e7822d68298f094d9e1459a7a482635700cbc20bbaf52d495ab9c968084",
+                "1b55ba3ac080a0c199674fcb29f353693dd779c017823b954b3c69dffa3cd6b2a6ff7888798039a028ca",
+                "912de909e7e6cdef9cdcaf24c54dd8c1032946dfa1d85c206b32a9064fe8",
+            ),
+            "ce4dc6d7a7549a98ee3b071b67e970879ff51b5b95d1c340bacd80fa1e1aab31",
+            "fb09c91c47c2717aadedeae95931a545a3d0d183c8c584325af02db1a85bfae8",
+        ),
+        (
+            // https://sepolia.etherscan.io/tx/0x9a22ccb0029bc8b0ddd073be1a1d923b7ae2b2ea52100bae0db4424f9107e9c0
+            // as in alloy-consensus 1.8.3, `test_decode_live_4844_tx`. The signing hash recovers
+            // its sender, 0xa83c816d4f9b2783761a22ba6fadb0eb0606d7b2.
+            concat!(
+                "03f9011d83aa36a7820fa28477359400852e90edd0008252089411e9ca82a3a762b4b5bd264d4173a242",
+                "e7a770648080c08504a817c800f8a5a0012ec3d6f66766bedb002a190126b3549fce0047de0d4c25cffc",
+                "e0dc1c57921aa00152d8e24762ff22b1cfd9f8c0683786a7ca63ba49973818b3d1e9512cd2cec4a0013b",
+                "98c6c83e066d5b14af2b85199e3d4fc7d1e778dd53130d180f5077e2d1c7a001148b495d6e859114e670",
+                "ca54fb6e2657f0cbae5b08063605093a4b3dc9f8f1a0011ac212f13c5dff2b2c6b600a79635103d6f580",
+                "a4221079951181b25c7e654901a0c8de4cced43169f9aa3d36506363b2d2c44f6c49fc1fd91ea114c86f",
+                "3757077ea01e11fdd0d1934eda0492606ee0bb80a7bf8f35cc5f86ec60fe5031ba48bfd544",
+            ),
+            "9a22ccb0029bc8b0ddd073be1a1d923b7ae2b2ea52100bae0db4424f9107e9c0",
+            "1acf60247481a7d9b8bea78b62af1db72d9034c50b9f3bf99daec97a98075e87",
+        ),
+    ];
+
+    #[test]
+    fn test_eip155_example() {
+        let (tx, signature) = eip155_example();
+        assert_eq!(
+            tx.signing_payload(),
+            hex("ec098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a764000080018080")
+        );
+        assert_eq!(
+            tx.signing_hash().0,
+            Digest256::from_hex("daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53")
+                .unwrap()
+        );
+        assert_eq!(
+            tx.signed_payload(&signature),
+            hex(concat!(
+                "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7640000",
+                "8025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f",
+                "761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+            ))
+        );
+        assert_eq!(
+            tx.tx_hash(&signature).0,
+            Digest256::from_hex("33469b22e9f636356c4160a87eb19df52b7412e8eac32a4a55ffe88ea8350788")
+                .unwrap()
+        );
+    }
+
+    fn signed_txs() -> Vec<(Transaction, Signature)> {
+        SIGNED_TXS
+            .iter()
+            .map(|(raw, ..)| Transaction::decode(&hex(raw)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_typed_transactions() {
+        for (raw, tx_hash, signing_hash) in SIGNED_TXS {
+            let raw = hex(raw);
+            let (tx, signature) = Transaction::decode(&raw).unwrap();
+            assert_eq!(tx.signed_payload(&signature), raw);
+            assert_eq!(
+                tx.tx_hash(&signature).0,
+                Digest256::from_hex(tx_hash).unwrap()
+            );
+            assert_eq!(
+                tx.signing_hash().0,
+                Digest256::from_hex(signing_hash).unwrap()
+            );
+        }
+        assert_eq!(
+            signed_txs()
+                .iter()
+                .map(|(tx, _)| tx.tx_type())
+                .collect::<Vec<_>>(),
+            [None, Some(1), Some(2), Some(3)]
+        );
+
+        let (tx, signature) = eip155_example();
+        let raw = tx.signed_payload(&signature);
+        assert_eq!(Transaction::decode(&raw).unwrap(), (tx, signature));
+        // A truncated transaction, an unknown type and a nonce with a leading zero.
+        let padded_nonce = [&[0xf8, 0x6e, 0x82, 0x00, 0x09][..], &raw[3..]].concat();
+        for bytes in [&raw[..raw.len() - 1], &[0x04, 0xc0], &padded_nonce[..]] {
+            assert!(Transaction::decode(bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn test_transaction_circuit() {
+        let examples = [vec![eip155_example()], signed_txs()].concat();
+
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let mut statements = vec![];
+        let mut statements_t = vec![];
+        let mut txs_t = vec![];
+        for (tx, signature) in examples.iter() {
+            let tx_t = TransactionTarget::new_virtual(&mut builder, tx, signature);
+            for ((hash_t, perms_t), (hash, perms)) in [
+                (tx_t.signing_hash_circuit(&mut builder), tx.signing_hash()),
+                (tx_t.tx_hash_circuit(&mut builder), tx.tx_hash(signature)),
+            ] {
+                for (byte_t, byte) in hash_t.into_iter().zip(hash.0) {
+                    let expected = builder.constant(F::from_canonical_u8(byte));
+                    builder.connect(byte_t, expected);
+                }
+                statements_t.extend(perms_t);
+                statements.extend(perms);
+            }
+            txs_t.push(tx_t);
+        }
+        let stark_proof_t = verify_keccak_statements_circuit(&mut builder, &statements_t);
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        for (tx_t, (tx, signature)) in txs_t.iter().zip(examples.iter()) {
+            tx_t.set_witness(&mut pw, tx, signature).unwrap();
+        }
+        set_stark_proof_with_pis_target(
+            &mut pw,
+            &stark_proof_t,
+            &prove_keccak_statements(&statements),
+        );
+        data.verify(data.prove(pw).unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::Instant;
+
+use plonky2::{
+    field::types::Field,
+    iop::witness::PartialWitness,
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CircuitData},
+        config::{GenericConfig, PoseidonGoldilocksConfig},
+        proof::ProofWithPublicInputs,
+    },
+    util::timing::TimingTree,
+};
+use starky::{
+    proof::StarkProofWithPublicInputsTarget,
+    prover::prove,
+    recursive_verifier::{
+        add_virtual_stark_proof_with_pis, set_stark_proof_with_pis_target,
+        verify_stark_proof_circuit,
+    },
+    verifier::verify_stark_proof,
+};
+
+use crate::{
+    keccak256_circuit::keccak256,
+    keccak_chain_stark::{chain_input, KeccakChainStark},
+    keccak_stark::{NUM_INPUTS, NUM_ROUNDS},
+    proving_stats::ProvingStats,
+    types::{Digest256, Digest256Target, KeccakStateTarget},
+};
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+type S = KeccakChainStark<F, D>;
+
+/// Computes `H^n(x)` where `H` is keccak256 on 32-byte messages.
+pub fn hash_chain(x: Digest256, n: usize) -> Digest256 {
+    (0..n).fold(x, |acc, _| keccak256(acc.words().to_vec()).0)
+}
+
+pub struct HashChainCircuit {
+    pub data: CircuitData<F, C, D>,
+    pub n: usize,
+    pub stark_proof_t: StarkProofWithPublicInputsTarget<D>,
+    pub input_t: Digest256Target,
+    pub output_t: Digest256Target,
+}
+
+/// Builds a circuit proving `y = H^n(x)` for 32-byte `x` and `y`, which are its only public
+/// inputs. All `n` permutations are chained inside a single `KeccakChainStark` proof.
+pub fn build_hash_chain_circuit(n: usize) -> HashChainCircuit {
+    let stark = S::new(n);
+    let inner_config = stark.config();
+    let degree_bits = (NUM_ROUNDS * n).next_power_of_two().trailing_zeros() as usize;
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let stark_proof_t =
+        add_virtual_stark_proof_with_pis(&mut builder, stark, &inner_config, degree_bits);
+    verify_stark_proof_circuit::<F, C, S, D>(&mut builder, stark, &stark_proof_t, &inner_config);
+
+    // The first permutation absorbs x with the keccak256 padding.
+    let pi_t = &stark_proof_t.public_inputs;
+    let padding = chain_input(Digest256::default()).limbs();
+    for i in 8..2 * NUM_INPUTS {
+        let c = builder.constant(F::from_canonical_u32(padding[i]));
+        builder.connect(pi_t[i], c);
+    }
+    let input_t = KeccakStateTarget::from_targets(&pi_t[..2 * NUM_INPUTS]).digest();
+    let output_t = KeccakStateTarget::from_targets(&pi_t[2 * NUM_INPUTS..]).digest();
+
+    input_t.register_public_inputs(&mut builder);
+    output_t.register_public_inputs(&mut builder);
+    let data = builder.build::<C>();
+
+    HashChainCircuit {
+        data,
+        n,
+        stark_proof_t,
+        input_t,
+        output_t,
+    }
+}
+
+pub fn generate_hash_chain_proof(
+    x: Digest256,
+    circuit: &HashChainCircuit,
+) -> ProofWithPublicInputs<F, C, D> {
+    generate_hash_chain_proof_with_stats(x, circuit).0
+}
+
+pub fn generate_hash_chain_proof_with_stats(
+    x: Digest256,
+    circuit: &HashChainCircuit,
+) -> (ProofWithPublicInputs<F, C, D>, ProvingStats) {
+    let stark = S::new(circuit.n);
+    let inner_config = stark.config();
+    let input = chain_input(x);
+    let now = Instant::now();
+    let (trace, output) = stark.generate_trace(input, 8);
+    let trace_generation = now.elapsed();
+    let (trace_height, trace_width) = (trace[0].len(), trace.len());
+    let pi = stark.generate_public_inputs(input, output);
+    let now = Instant::now();
+    let inner_proof =
+        prove::<F, C, S, D>(stark, &inner_config, trace, pi, &mut TimingTree::default()).unwrap();
+    let mut stats = ProvingStats::stark(
+        circuit.n,
+        trace_height,
+        trace_width,
+        inner_config.fri_config.rate_bits,
+        trace_generation,
+        now.elapsed(),
+    );
+    verify_stark_proof(stark, inner_proof.clone(), &inner_config).unwrap();
+
+    let mut pw = PartialWitness::new();
+    set_stark_proof_with_pis_target(&mut pw, &circuit.stark_proof_t, &inner_proof);
+    let now = Instant::now();
+    let proof = circuit.data.prove(pw).unwrap();
+    stats.finish(now.elapsed(), proof.to_bytes().len());
+    (proof, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+
+    use crate::hash_chain_circuit::{
+        build_hash_chain_circuit, generate_hash_chain_proof_with_stats, hash_chain,
+    };
+    use crate::types::Digest256;
+
+    #[test]
+    fn test_hash_chain_circuit() {
+        let n = 10;
+        let x = Digest256(rand::random());
+
+        let circuit = build_hash_chain_circuit(n);
+        let (proof, stats) = generate_hash_chain_proof_with_stats(x, &circuit);
+        assert_eq!(stats.num_perms, n);
+        circuit.data.verify(proof.clone()).unwrap();
+
+        let y = hash_chain(x, n);
+        let input_and_output = [
+            x.to_field_elements::<GoldilocksField>(),
+            y.to_field_elements(),
+        ]
+        .concat();
+        assert!(proof.public_inputs == input_and_output);
+    }
+}
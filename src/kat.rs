@@ -0,0 +1,267 @@
+//! Known-answer tests for the permutation, keccak256 and the keccak256 circuits.
+//!
+//! The Keccak-f[1600] vectors are the intermediate values published by the Keccak team for the
+//! all-zero state. The keccak256 vectors are entries of the Keccak team's `ShortMsgKAT_256.txt`
+//! and a mainnet transaction with its published hash. Messages around the 136-byte rate are checked
+//! through a byte-level sponge that reproduces the SHA3-256 entries of those lengths.
+
+use itertools::Itertools;
+use plonky2::field::types::Field;
+use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+use tiny_keccak::{Hasher, Keccak};
+
+use crate::keccak256_circuit::{
+    build_keccak256_bytes_circuit, bytes_to_words, generate_keccak256_proof, keccak256,
+    keccak256_bytes, keccak256_with_byte_len,
+};
+use crate::multi_keccak256_circuit::{
+    build_multi_keccak256_bytes_circuit, generate_multi_keccak256_proof,
+};
+use crate::types::{Digest256, KeccakState};
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+/// Keccak-f[1600] applied once to the all-zero state.
+const KECCAKF_ZERO_1: [u64; 25] = [
+    0xf1258f7940e1dde7,
+    0x84d5ccf933c0478a,
+    0xd598261ea65aa9ee,
+    0xbd1547306f80494d,
+    0x8b284e056253d057,
+    0xff97a42d7f8e6fd4,
+    0x90fee5a0a44647c4,
+    0x8c5bda0cd6192e76,
+    0xad30a6f71b19059c,
+    0x30935ab7d08ffc64,
+    0xeb5aa93f2317d635,
+    0xa9a6e6260d712103,
+    0x81a57c16dbcf555f,
+    0x43b831cd0347c826,
+    0x01f22f1a11a5569f,
+    0x05e5635a21d9ae61,
+    0x64befef28cc970f2,
+    0x613670957bc46611,
+    0xb87c5a554fd00ecb,
+    0x8c3ee88a1ccf32c8,
+    0x940c7922ae3a2614,
+    0x1841f924a2c509e4,
+    0x16f53526e70465c2,
+    0x75f644e97f30a13b,
+    0xeaf1ff7b5ceca249,
+];
+
+/// Keccak-f[1600] applied twice to the all-zero state.
+const KECCAKF_ZERO_2: [u64; 25] = [
+    0x2d5c954df96ecb3c,
+    0x6a332cd07057b56d,
+    0x093d8d1270d76b6c,
+    0x8a20d9b25569d094,
+    0x4f9c4f99e5e7f156,
+    0xf957b9a2da65fb38,
+    0x85773dae1275af0d,
+    0xfaf4f247c3d810f7,
+    0x1f1b9ee6f79a8759,
+    0xe4fecc0fee98b425,
+    0x68ce61b6b9ce68a1,
+    0xdeea66c4ba8f974f,
+    0x33c43d836eafb1f5,
+    0xe00654042719dbd9,
+    0x7cf8a9f009831265,
+    0xfd5449a6bf174743,
+    0x97ddad33d8994b40,
+    0x48ead5fc5d0be774,
+    0xe3b8c8ee55b7b03c,
+    0x91a0226e649e42e9,
+    0x900e3129e7badd7b,
+    0x202a9ec5faa3cce8,
+    0x5b3402464e1c3db6,
+    0x609f4e62a44c1059,
+    0x20d06cd26a8fbf5c,
+];
+
+/// `(Msg, MD)` of the entries `Len = 0`, `Len = 8` and `Len = 512` of `ShortMsgKAT_256.txt` in the
+/// Keccak team's KeccakKAT package, the known-answer tests of their SHA-3 submission. The same
+/// entries ship in RustCrypto's sha3 crate as `tests/data/keccak_256.blb`.
+const SHORT_MSG_KATS: [(&str, &str); 3] = [
+    (
+        "",
+        "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470",
+    ),
+    (
+        "cc",
+        "eead6dbfc7340a56caedc044696a168870549a6a7f6f56961e84a54bd9970b8a",
+    ),
+    (
+        "e926ae8b0af6e53176dbffcc2a6b88c6bd765f939d3d178a9bde9ef3aa131c61\
+         e31c1e42cdfaf4b4dcde579a37e150efbef5555b4c1cb40439d835a724e2fae7",
+        "574271cd13959e8ddeae5bfbdb02a3fdf54f2babfd0cbeb893082a974957d0c1",
+    ),
+];
+
+/// `(Msg, MD)` of the entries `Len = 1080`, `Len = 1088` and `Len = 1096` of the Keccak team's
+/// `ShortMsgKAT_SHA3-256.txt`, the FIPS 202 release of their known-answer tests, as in
+/// rust-crypto 0.2.36's `test_sha3_256`. The messages are those of `ShortMsgKAT_256.txt`: one byte
+/// short of the 136-byte rate, where the two padding bytes coincide, a full block, which needs an
+/// extra padding block, and one byte over.
+const SHA3_BOUNDARY_KATS: [(&str, &str); 3] = [
+    (
+        "b771d5cef5d1a41a93d15643d7181d2a2ef0a8e84d91812f20ed21f147bef732bf3a\
+         60ef4067c3734b85bc8cd471780f10dc9e8291b58339a677b960218f71e793f2797a\
+         ea349406512829065d37bb55ea796fa4f56fd8896b49b2cd19b43215ad967c712b24\
+         e5032d065232e02c127409d2ed4146b9d75d763d52db98d949d3b0fed6a8052fbb",
+        "a19eee92bb2097b64e823d597798aa18be9b7c736b8059abfd6779ac35ac81b5",
+    ),
+    (
+        "b32d95b0b9aad2a8816de6d06d1f86008505bd8c14124f6e9a163b5a2ade55f835d0\
+         ec3880ef50700d3b25e42cc0af050ccd1be5e555b23087e04d7bf9813622780c7313\
+         a1954f8740b6ee2d3f71f768dd417f520482bd3a08d4f222b4ee9dbd015447b33507\
+         dd50f3ab4247c5de9a8abd62a8decea01e3b87c8b927f5b08beb37674c6f8e380c04",
+        "df673f4105379ff6b755eeab20ceb0dc77b5286364fe16c59cc8a907aff07732",
+    ),
+    (
+        "04410e31082a47584b406f051398a6abe74e4da59bb6f85e6b49e8a1f7f2ca00dfba\
+         5462c2cd2bfde8b64fb21d70c083f11318b56a52d03b81cac5eec29eb31bd0078b61\
+         56786da3d6d8c33098c5c47bb67ac64db14165af65b44544d806dde5f487d5373c7f\
+         9792c299e9686b7e5821e7c8e2458315b996b5677d926dac57b3f22da873c601016a\
+         0d",
+        "d52432cf3b6b4b949aa848e058dcd62d735e0177279222e7ac0af8504762faa0",
+    ),
+];
+
+/// The raw bytes of mainnet transaction
+/// 0x6d38fc8aee934858815ed41273cece3b676c368e9c6e39f172313a0685e1f175, as quoted by ethers-core
+/// 2.0.14, and the keccak256 hash Etherscan publishes for it: a 241-byte, two-block message.
+const TWO_BLOCK_KAT: (&str, &str) = (
+    "01f8ee0182034c853d9f1b88158307a120940087bb802d9c0e343f00510000729031ce00bf2780b8841e1326a3\
+     00000000000000000000000088e6a0c2ddd26feeb64f039a2c41296fcb3f564000000000000000000000000000\
+     00000000000000000000000000001d3b3e730000000000000000000000000000000000000000000000000596b9\
+     3e53696740000000000000000000000000000000000000000000000000000000000000000001c001a0bbfd754e\
+     d51b34d0a8577f69b4c42ce6b47fee6ecf49114bb135e7e8eadbb336a0433692134eb7e7686e9aefafa9f69c60\
+     1aa977c00cc85c827782f5fb1f1cff0f",
+    "6d38fc8aee934858815ed41273cece3b676c368e9c6e39f172313a0685e1f175",
+);
+
+/// The first padding byte of keccak256 and of SHA3-256, which otherwise share their sponge.
+const KECCAK_DOMAIN: u8 = 0x01;
+const SHA3_DOMAIN: u8 = 0x06;
+
+/// The official keccak256 messages and digests, the boundary messages with their digests under
+/// `sponge_256`, and the two-block transaction.
+fn kats() -> Vec<(Vec<u8>, Digest256)> {
+    let official = SHORT_MSG_KATS
+        .iter()
+        .chain([&TWO_BLOCK_KAT])
+        .map(|&(message, digest)| (decode_hex(message), decode_digest(digest)));
+    let boundary = SHA3_BOUNDARY_KATS.iter().map(|&(message, _)| {
+        let message = decode_hex(message);
+        let digest = sponge_256(&message, KECCAK_DOMAIN);
+        (message, digest)
+    });
+    official.chain(boundary).collect()
+}
+
+/// The sponge of keccak256 and SHA3-256 over bytes, on `KeccakState`'s permutation and byte
+/// layout, with `domain` as the first padding byte.
+fn sponge_256(message: &[u8], domain: u8) -> Digest256 {
+    let mut padded = message.to_vec();
+    padded.resize((message.len() / 136 + 1) * 136, 0);
+    padded[message.len()] ^= domain;
+    *padded.last_mut().unwrap() ^= 0x80;
+    let mut state = KeccakState::ZERO;
+    for block in padded.chunks(136) {
+        let mut bytes = state.to_bytes();
+        bytes.iter_mut().zip(block).for_each(|(x, y)| *x ^= y);
+        state = KeccakState::from_bytes(bytes).permute();
+    }
+    state.digest()
+}
+
+fn decode_hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn decode_digest(s: &str) -> Digest256 {
+    Digest256::from_hex(s).unwrap()
+}
+
+#[test]
+fn test_keccakf_kat() {
+    let state = KeccakState::ZERO.permute();
+    assert_eq!(state, KeccakState(KECCAKF_ZERO_1));
+    let state = state.permute();
+    assert_eq!(state, KeccakState(KECCAKF_ZERO_2));
+}
+
+#[test]
+fn test_boundary_sponge_kat() {
+    for (message, digest) in SHA3_BOUNDARY_KATS {
+        let message = decode_hex(message);
+        assert_eq!(sponge_256(&message, SHA3_DOMAIN), decode_digest(digest));
+
+        // tiny-keccak as a cross-check of the keccak256 padding.
+        let mut hasher = Keccak::v256();
+        hasher.update(&message);
+        let mut output = [0u8; 32];
+        hasher.finalize(&mut output);
+        assert_eq!(sponge_256(&message, KECCAK_DOMAIN), Digest256(output));
+    }
+}
+
+#[test]
+fn test_keccak256_kat() {
+    for (message, digest) in kats() {
+        let len = message.len();
+        assert_eq!(keccak256_bytes(&message), digest, "length {}", len);
+
+        let (output, statements) = keccak256_with_byte_len(bytes_to_words(&message), len);
+        assert_eq!(output, digest, "length {}", len);
+        assert_eq!(statements.len(), len / 136 + 1);
+        if len % 4 == 0 {
+            assert_eq!(keccak256(bytes_to_words(&message)).0, output);
+        }
+    }
+}
+
+#[test]
+fn test_keccak256_circuit_kat() {
+    for (message, digest) in kats() {
+        let len = message.len();
+        let circuit = build_keccak256_bytes_circuit(len);
+        let input = bytes_to_words(&message);
+        let proof = generate_keccak256_proof(input.clone(), &circuit);
+        circuit.data.verify(proof.clone()).unwrap();
+
+        let expected = [input, digest.words().to_vec()]
+            .concat()
+            .into_iter()
+            .map(F::from_canonical_u32)
+            .collect_vec();
+        assert!(proof.public_inputs == expected, "length {}", len);
+    }
+}
+
+#[test]
+fn test_multi_keccak256_circuit_kat() {
+    let kats = kats();
+    let lens = kats.iter().map(|(message, _)| message.len()).collect_vec();
+    let circuit = build_multi_keccak256_bytes_circuit(lens);
+    let inputs = kats
+        .iter()
+        .map(|(message, _)| bytes_to_words(message))
+        .collect_vec();
+    let proof = generate_multi_keccak256_proof(inputs.clone(), &circuit);
+    circuit.data.verify(proof.clone()).unwrap();
+
+    let expected = inputs
+        .into_iter()
+        .zip(kats.iter())
+        .flat_map(|(input, (_, digest))| [input, digest.words().to_vec()].concat())
+        .map(F::from_canonical_u32)
+        .collect_vec();
+    assert!(proof.public_inputs == expected);
+}
@@ -1,23 +1,35 @@
-use crate::keccak_stark::{KeccakStark, NUM_ROUNDS};
+use std::time::Instant;
+
+use crate::keccak_stark::{KeccakStark, NUM_INPUTS, NUM_ROUNDS};
+use crate::proving_stats::ProvingStats;
+use crate::serialization::{circuit_data_from_bytes, circuit_data_to_bytes};
+use crate::types::{
+    statements_to_field_elements, Digest256, Digest256Target, KeccakState, KeccakStateTarget,
+};
+use crate::xor_gate::xor_u32_circuit;
+use anyhow::Result;
 use itertools::Itertools;
 use plonky2::{
     field::extension::Extendable,
     field::types::Field,
-    hash::hash_types::RichField,
+    hash::{
+        hash_types::{HashOut, HashOutTarget, RichField},
+        poseidon::PoseidonHash,
+    },
     iop::{
-        target::{BoolTarget, Target},
+        target::Target,
         witness::{PartialWitness, WitnessWrite},
     },
     plonk::{
         circuit_builder::CircuitBuilder,
         circuit_data::{CircuitConfig, CircuitData},
-        config::{GenericConfig, PoseidonGoldilocksConfig},
+        config::{GenericConfig, Hasher, PoseidonGoldilocksConfig},
         proof::ProofWithPublicInputs,
     },
     util::timing::TimingTree,
 };
 use starky::{
-    proof::StarkProofWithPublicInputsTarget,
+    proof::{StarkProofWithPublicInputs, StarkProofWithPublicInputsTarget},
     prover::prove,
     recursive_verifier::{
         add_virtual_stark_proof_with_pis, set_stark_proof_with_pis_target,
@@ -25,177 +37,416 @@ use starky::{
     },
     verifier::verify_stark_proof,
 };
-use tiny_keccak::keccakf;
-
-pub fn keccakf_u32(input: [u32; 50]) -> [u32; 50] {
-    let mut state = input
-        .chunks(2)
-        .map(|chunk| chunk[0] as u64 + ((chunk[1] as u64) << 32))
-        .collect_vec()
-        .try_into()
-        .unwrap();
-    keccakf(&mut state);
-    let output = state
-        .iter()
-        .flat_map(|&x| vec![x as u32, (x >> 32) as u32])
-        .collect_vec();
-    output.try_into().unwrap()
+
+/// keccak256 of the bytes of `input`, with the (input, output) state of every permutation it
+/// takes. The statements are what `prove_keccak_statements` proves.
+pub fn keccak256(input: Vec<u32>) -> (Digest256, Vec<(KeccakState, KeccakState)>) {
+    let num_bytes = 4 * input.len();
+    keccak256_with_byte_len(input, num_bytes)
 }
 
-pub fn keccak256(input: Vec<u32>) -> ([u32; 8], Vec<u32>) {
-    let mut pi = vec![];
+/// Packs bytes into little-endian u32 words. The last word is zero-extended.
+pub fn bytes_to_words(input: &[u8]) -> Vec<u32> {
+    input
+        .chunks(4)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |acc, (i, &b)| acc | ((b as u32) << (8 * i)))
+        })
+        .collect()
+}
+
+pub fn words_to_bytes(input: &[u32]) -> Vec<u8> {
+    input.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+/// The number of permutations keccak256 takes on a message of `num_bytes` bytes, counting the
+/// padding.
+pub fn keccak256_num_perms(num_bytes: usize) -> usize {
+    num_bytes / 136 + 1
+}
+
+/// keccak256 of a message of `num_bytes` bytes, given as `bytes_to_words` of the message.
+pub fn keccak256_with_byte_len(
+    input: Vec<u32>,
+    num_bytes: usize,
+) -> (Digest256, Vec<(KeccakState, KeccakState)>) {
+    assert!(input.len() == (num_bytes + 3) / 4);
+    let mut statements = vec![];
     let block_size = 136 / 4;
-    let num_blocks = input.len() / block_size + 1;
+    let num_blocks = keccak256_num_perms(num_bytes);
     let mut padded = vec![0u32; block_size * num_blocks];
     padded[0..input.len()].copy_from_slice(&input);
-    padded[input.len()] = 0x01;
+    padded[num_bytes / 4] ^= 0x01 << (8 * (num_bytes % 4));
     *padded.last_mut().unwrap() ^= 0x80 << 24;
-    let mut state = [0u32; 50];
+    let mut state = KeccakState::ZERO;
     for i in 0..num_blocks {
+        let mut limbs = state.limbs();
         for j in 0..block_size {
-            state[j] ^= padded[i * block_size + j];
+            limbs[j] ^= padded[i * block_size + j];
         }
-        let input = state;
-        let output = keccakf_u32(input);
-        pi.extend(input);
-        pi.extend(output);
+        let input = KeccakState::from_limbs(limbs);
+        let output = input.permute();
+        statements.push((input, output));
         state = output;
     }
-    (state[0..8].try_into().unwrap(), pi)
+    (state.digest(), statements)
 }
 
+pub fn keccak256_bytes(input: &[u8]) -> Digest256 {
+    keccak256_with_byte_len(bytes_to_words(input), input.len()).0
+}
+
+/// `x ^ y` for u32 values, in a `U32XorGate`. Both operands are range-checked to 32 bits.
 pub fn xor_circuit<F: RichField + Extendable<D>, const D: usize>(
     builder: &mut CircuitBuilder<F, D>,
     x: Target,
     y: Target,
 ) -> Target {
-    let x_bit = builder.split_le(x, 32);
-    let y_bit = builder.split_le(y, 32);
-    let z_bit = x_bit
-        .iter()
-        .zip(y_bit.iter())
-        .map(|(&x, &y)| {
-            let sum = builder.add(x.target, y.target);
-            let z = builder.arithmetic(-F::TWO, F::ONE, x.target, y.target, sum);
-            BoolTarget::new_unsafe(z)
-        })
-        .collect_vec();
-    builder.le_sum(z_bit.into_iter())
+    xor_u32_circuit(builder, x, y)
 }
 
 pub fn keccak256_circuit_with_statements<F: RichField + Extendable<D>, const D: usize>(
     builder: &mut CircuitBuilder<F, D>,
     input: Vec<Target>,
-) -> ([Target; 8], Vec<Target>) {
-    let mut pi: Vec<Target> = vec![];
+) -> (Digest256Target, Vec<(KeccakStateTarget, KeccakStateTarget)>) {
+    let num_bytes = 4 * input.len();
+    keccak256_circuit_with_byte_len(builder, input, num_bytes)
+}
+
+/// The circuit version of `keccak256_with_byte_len`. When `num_bytes` is not a multiple of 4, the
+/// last word of `input` is range-checked to hold only `num_bytes % 4` bytes.
+pub fn keccak256_circuit_with_byte_len<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    input: Vec<Target>,
+    num_bytes: usize,
+) -> (Digest256Target, Vec<(KeccakStateTarget, KeccakStateTarget)>) {
+    keccak256_circuit_inner(builder, input, num_bytes, true)
+}
+
+/// `keccak256_circuit_with_byte_len`, skipping the range check of a partial last word when the
+/// caller already guarantees its unused bytes are zero, e.g. because it packed range-checked
+/// bytes.
+pub(crate) fn keccak256_circuit_inner<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    input: Vec<Target>,
+    num_bytes: usize,
+    check_last_word: bool,
+) -> (Digest256Target, Vec<(KeccakStateTarget, KeccakStateTarget)>) {
+    assert!(input.len() == (num_bytes + 3) / 4);
+    let mut statements = vec![];
     let zero = builder.zero();
     let one = builder.one();
     let c = builder.constant(F::from_canonical_u32(0x80 << 24));
     let block_size = 136 / 4;
-    let num_blocks = input.len() / block_size + 1;
+    let num_blocks = keccak256_num_perms(num_bytes);
     let mut padded = vec![zero; block_size * num_blocks];
     padded[0..input.len()].copy_from_slice(&input);
-    padded[input.len()] = one;
+    let rem_bytes = num_bytes % 4;
+    padded[num_bytes / 4] = if rem_bytes == 0 {
+        one
+    } else {
+        // The padding byte lands above the message bytes of the last word, so xor is addition.
+        let last = padded[num_bytes / 4];
+        if check_last_word {
+            builder.range_check(last, 8 * rem_bytes);
+        }
+        builder.add_const(last, F::from_canonical_u32(0x01 << (8 * rem_bytes)))
+    };
     *padded.last_mut().unwrap() = xor_circuit(builder, *padded.last().unwrap(), c);
     let mut state = [zero; 50];
     for i in 0..num_blocks {
         for j in 0..block_size {
             state[j] = xor_circuit(builder, state[j], padded[i * block_size + j]);
         }
-        let input = state;
-        let output = [(); 50].map(|_| builder.add_virtual_target());
-        pi.extend(input);
-        pi.extend(output);
-        state = output;
+        let input = KeccakStateTarget(state);
+        let output = KeccakStateTarget::new_virtual(builder);
+        statements.push((input, output));
+        state = output.0;
     }
-    (state[0..8].try_into().unwrap(), pi)
+    (KeccakStateTarget(state).digest(), statements)
 }
 
 const D: usize = 2;
 type C = PoseidonGoldilocksConfig;
 type F = <C as GenericConfig<D>>::F;
 
+/// Selects which parts of a keccak256 statement the outer circuit registers as public inputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PublicInputMode {
+    /// The message words followed by the digest words.
+    Full,
+    /// Only the digest words.
+    DigestOnly,
+    /// A salted Poseidon commitment to the message, `Poseidon(salt || message)`, followed by the
+    /// digest words.
+    MessageCommitment,
+}
+
+impl PublicInputMode {
+    /// Whether the message is kept out of the public inputs. Such circuits are built with
+    /// zero-knowledge enabled, since the `KeccakStark` proof they verify is part of the witness
+    /// and its public inputs contain every absorbed message word.
+    pub fn is_private(&self) -> bool {
+        *self != PublicInputMode::Full
+    }
+}
+
 pub struct Keccak256Circuit {
     pub data: CircuitData<F, C, D>,
+    pub mode: PublicInputMode,
+    pub input_num_bytes: usize,
     pub stark_proof_t: StarkProofWithPublicInputsTarget<D>,
     pub input_t: Vec<Target>,
-    pub output_t: [Target; 8],
+    pub output_t: Digest256Target,
+    pub salt_t: Option<HashOutTarget>,
 }
 
-pub fn build_keccak256_circuit(input_len: usize) -> Keccak256Circuit {
-    let block_size = 136 / 4;
-    let num_perms = input_len / block_size + 1;
+/// The commitment exposed by `PublicInputMode::MessageCommitment`.
+pub fn message_commitment(input: &[u32], salt: HashOut<F>) -> HashOut<F> {
+    let elements = salt
+        .elements
+        .into_iter()
+        .chain(input.iter().map(|&x| F::from_canonical_u32(x)))
+        .collect_vec();
+    PoseidonHash::hash_no_pad(&elements)
+}
+
+/// The public inputs a proof for `input` has under the given mode. `salt` is only used by
+/// `PublicInputMode::MessageCommitment`.
+pub fn keccak256_public_inputs(input: &[u32], mode: PublicInputMode, salt: HashOut<F>) -> Vec<F> {
+    let (output, _statements) = keccak256(input.to_vec());
+    let output = output.to_field_elements::<F>();
+    match mode {
+        PublicInputMode::Full => input
+            .iter()
+            .map(|&x| F::from_canonical_u32(x))
+            .chain(output)
+            .collect(),
+        PublicInputMode::DigestOnly => output.to_vec(),
+        PublicInputMode::MessageCommitment => {
+            [message_commitment(input, salt).elements.as_slice(), &output].concat()
+        }
+    }
+}
+
+/// Adds a `KeccakStark` proof target whose public inputs are connected to `statements_t`, the
+/// permutation statements returned by `keccak256_circuit_with_statements`, and verifies it.
+pub fn verify_keccak_statements_circuit(
+    builder: &mut CircuitBuilder<F, D>,
+    statements_t: &[(KeccakStateTarget, KeccakStateTarget)],
+) -> StarkProofWithPublicInputsTarget<D> {
+    let num_perms = statements_t.len();
     let degree_bits = (NUM_ROUNDS * num_perms)
         .next_power_of_two()
         .trailing_zeros() as usize;
-    type C = PoseidonGoldilocksConfig;
-    type F = <C as GenericConfig<D>>::F;
     type S = KeccakStark<F, D>;
-    let stark = S {
-        num_io: num_perms,
-        f: Default::default(),
-    };
+    let stark = S::new(num_perms);
     let inner_config = stark.config();
-    let config = CircuitConfig::standard_recursion_config();
-    let mut builder = CircuitBuilder::<F, D>::new(config);
-    let input_t = builder.add_virtual_targets(input_len);
-    let (output_t, pi_t) = keccak256_circuit_with_statements(&mut builder, input_t.clone());
     let stark_proof_t =
-        add_virtual_stark_proof_with_pis(&mut builder, stark, &inner_config, degree_bits);
-    verify_stark_proof_circuit::<F, C, S, D>(&mut builder, stark, &stark_proof_t, &inner_config);
-    pi_t.iter()
-        .zip(stark_proof_t.public_inputs.iter())
-        .for_each(|(x, y)| {
-            builder.connect(*x, *y);
-        });
-
-    let input_and_output = [input_t.as_slice(), &output_t].concat();
-    builder.register_public_inputs(&input_and_output);
-    let data = builder.build::<C>();
-
-    Keccak256Circuit {
-        data,
+        add_virtual_stark_proof_with_pis(builder, stark, &inner_config, degree_bits);
+    verify_stark_proof_circuit::<F, C, S, D>(builder, stark, &stark_proof_t, &inner_config);
+    for ((input_t, output_t), pi_t) in statements_t
+        .iter()
+        .zip(stark_proof_t.public_inputs.chunks(4 * NUM_INPUTS))
+    {
+        let (input_pi_t, output_pi_t) = pi_t.split_at(2 * NUM_INPUTS);
+        KeccakStateTarget::connect(
+            builder,
+            *input_t,
+            KeccakStateTarget::from_targets(input_pi_t),
+        );
+        KeccakStateTarget::connect(
+            builder,
+            *output_t,
+            KeccakStateTarget::from_targets(output_pi_t),
+        );
+    }
+    stark_proof_t
+}
+
+/// The `min_rows` the traces of `prove_keccak_statements` are generated with.
+pub(crate) const MIN_TRACE_ROWS: usize = 8;
+
+/// Proves the permutation statements returned by `keccak256` with a `KeccakStark` proof.
+pub fn prove_keccak_statements(
+    statements: &[(KeccakState, KeccakState)],
+) -> StarkProofWithPublicInputs<F, C, D> {
+    prove_keccak_statements_with_stats(statements).0
+}
+
+pub fn prove_keccak_statements_with_stats(
+    statements: &[(KeccakState, KeccakState)],
+) -> (StarkProofWithPublicInputs<F, C, D>, ProvingStats) {
+    let inputs = statements.iter().map(|(input, _)| *input).collect_vec();
+    let num_perms = inputs.len();
+
+    type S = KeccakStark<F, D>;
+    let stark = S::new(num_perms);
+    let inner_config = stark.config();
+    let now = Instant::now();
+    let trace = stark.generate_trace(inputs, MIN_TRACE_ROWS);
+    let trace_generation = now.elapsed();
+    let (trace_height, trace_width) = (trace[0].len(), trace.len());
+    let pi = statements_to_field_elements(statements);
+    let now = Instant::now();
+    let inner_proof =
+        prove::<F, C, S, D>(stark, &inner_config, trace, pi, &mut TimingTree::default()).unwrap();
+    let stats = ProvingStats::stark(
+        num_perms,
+        trace_height,
+        trace_width,
+        inner_config.fri_config.rate_bits,
+        trace_generation,
+        now.elapsed(),
+    );
+    verify_stark_proof(stark, inner_proof.clone(), &inner_config).unwrap();
+    (inner_proof, stats)
+}
+
+pub fn build_keccak256_circuit(input_len: usize) -> Keccak256Circuit {
+    build_keccak256_circuit_with_mode(input_len, PublicInputMode::Full)
+}
+
+/// Builds a keccak256 circuit exposing the public inputs selected by `mode`. In the private modes
+/// the message only appears in the witness: the `KeccakStark` public inputs are virtual targets of
+/// the outer circuit, and the outer proof is zero-knowledge.
+pub fn build_keccak256_circuit_with_mode(
+    input_len: usize,
+    mode: PublicInputMode,
+) -> Keccak256Circuit {
+    build_keccak256_circuit_inner(4 * input_len, mode)
+}
+
+/// Builds a keccak256 circuit for messages of `num_bytes` bytes, which need not be a multiple of
+/// 4. The message is given to the prover as `bytes_to_words` of its bytes.
+pub fn build_keccak256_bytes_circuit(num_bytes: usize) -> Keccak256Circuit {
+    build_keccak256_circuit_inner(num_bytes, PublicInputMode::Full)
+}
+
+fn build_keccak256_circuit_inner(num_bytes: usize, mode: PublicInputMode) -> Keccak256Circuit {
+    keccak256_circuit_with_data(num_bytes, mode, |builder| Ok(builder.build::<C>())).unwrap()
+}
+
+impl Keccak256Circuit {
+    /// Serializes the prover data with `KeccakGateSerializer` and `KeccakGeneratorSerializer`,
+    /// after a header with the message length, the mode and the circuit digest. The targets are
+    /// not stored, see `from_bytes`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        circuit_data_to_bytes(&self.data, &Self::params(self.input_num_bytes, self.mode))
+    }
+
+    /// Deserializes a circuit written by `to_bytes` for messages of `num_bytes` bytes under
+    /// `mode`, failing if it was written for another length or mode, or by another version of the
+    /// crate. The targets are recovered by constructing the circuit again without building it,
+    /// which allocates them in the same order.
+    pub fn from_bytes(bytes: &[u8], num_bytes: usize, mode: PublicInputMode) -> Result<Self> {
+        keccak256_circuit_with_data(num_bytes, mode, |_| {
+            circuit_data_from_bytes(bytes, &Self::params(num_bytes, mode))
+        })
+    }
+
+    fn params(num_bytes: usize, mode: PublicInputMode) -> [u64; 2] {
+        let mode = match mode {
+            PublicInputMode::Full => 0,
+            PublicInputMode::DigestOnly => 1,
+            PublicInputMode::MessageCommitment => 2,
+        };
+        [num_bytes as u64, mode]
+    }
+}
+
+/// Constructs the circuit of `build_keccak256_circuit_inner` and gets its data from `data`, given
+/// the finished builder.
+fn keccak256_circuit_with_data(
+    num_bytes: usize,
+    mode: PublicInputMode,
+    data: impl FnOnce(CircuitBuilder<F, D>) -> Result<CircuitData<F, C, D>>,
+) -> Result<Keccak256Circuit> {
+    let config = if mode.is_private() {
+        CircuitConfig::standard_recursion_zk_config()
+    } else {
+        CircuitConfig::standard_recursion_config()
+    };
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let input_t = builder.add_virtual_targets((num_bytes + 3) / 4);
+    let (output_t, statements_t) =
+        keccak256_circuit_with_byte_len(&mut builder, input_t.clone(), num_bytes);
+    let stark_proof_t = verify_keccak_statements_circuit(&mut builder, &statements_t);
+
+    let mut salt_t = None;
+    match mode {
+        PublicInputMode::Full => {
+            builder.register_public_inputs(&input_t);
+            output_t.register_public_inputs(&mut builder);
+        }
+        PublicInputMode::DigestOnly => {
+            output_t.register_public_inputs(&mut builder);
+        }
+        PublicInputMode::MessageCommitment => {
+            let salt = builder.add_virtual_hash();
+            let commitment = builder.hash_n_to_hash_no_pad::<PoseidonHash>(
+                [salt.elements.as_slice(), &input_t].concat(),
+            );
+            builder.register_public_inputs(&commitment.elements);
+            output_t.register_public_inputs(&mut builder);
+            salt_t = Some(salt);
+        }
+    }
+
+    Ok(Keccak256Circuit {
+        data: data(builder)?,
+        mode,
+        input_num_bytes: num_bytes,
         stark_proof_t,
         input_t,
         output_t,
-    }
+        salt_t,
+    })
 }
 
 pub fn generate_keccak256_proof(
     input: Vec<u32>,
     circuit: &Keccak256Circuit,
 ) -> ProofWithPublicInputs<F, C, D> {
-    let block_size = 136 / 4;
-    let num_perms = input.len() / block_size + 1;
-
-    let (output, pi) = keccak256(input.clone());
-    let mut inputs = vec![];
-    for i in 0..num_perms {
-        let input = pi[i * 100..i * 100 + 50].to_vec();
-        let input = input
-            .chunks(2)
-            .map(|chunk| chunk[0] as u64 + ((chunk[1] as u64) << 32))
-            .collect_vec();
-        inputs.push(input.try_into().unwrap());
-    }
+    assert!(
+        circuit.mode != PublicInputMode::MessageCommitment,
+        "use generate_keccak256_proof_with_salt for commitment circuits"
+    );
+    generate_keccak256_proof_inner(input, None, circuit).0
+}
 
-    type S = KeccakStark<F, D>;
-    let stark = S {
-        num_io: num_perms,
-        f: Default::default(),
-    };
-    let inner_config = stark.config();
-    let trace = stark.generate_trace(inputs.try_into().unwrap(), 8);
-    let pi = pi.iter().map(|x| F::from_canonical_u32(*x)).collect_vec();
-    let inner_proof = prove::<F, C, S, D>(
-        stark,
-        &inner_config,
-        trace,
-        pi.try_into().unwrap(),
-        &mut TimingTree::default(),
-    )
-    .unwrap();
-    verify_stark_proof(stark, inner_proof.clone(), &inner_config).unwrap();
+pub fn generate_keccak256_proof_with_stats(
+    input: Vec<u32>,
+    circuit: &Keccak256Circuit,
+) -> (ProofWithPublicInputs<F, C, D>, ProvingStats) {
+    assert!(
+        circuit.mode != PublicInputMode::MessageCommitment,
+        "use generate_keccak256_proof_with_salt for commitment circuits"
+    );
+    generate_keccak256_proof_inner(input, None, circuit)
+}
+
+/// Proves `input` with a circuit built with `PublicInputMode::MessageCommitment`. The salt must be
+/// sampled uniformly and kept secret for the commitment to hide the message.
+pub fn generate_keccak256_proof_with_salt(
+    input: Vec<u32>,
+    salt: HashOut<F>,
+    circuit: &Keccak256Circuit,
+) -> ProofWithPublicInputs<F, C, D> {
+    assert!(circuit.mode == PublicInputMode::MessageCommitment);
+    generate_keccak256_proof_inner(input, Some(salt), circuit).0
+}
+
+fn generate_keccak256_proof_inner(
+    input: Vec<u32>,
+    salt: Option<HashOut<F>>,
+    circuit: &Keccak256Circuit,
+) -> (ProofWithPublicInputs<F, C, D>, ProvingStats) {
+    let (output, statements) = keccak256_with_byte_len(input.clone(), circuit.input_num_bytes);
+    let (inner_proof, mut stats) = prove_keccak_statements_with_stats(&statements);
 
     let mut pw = PartialWitness::new();
     set_stark_proof_with_pis_target(&mut pw, &circuit.stark_proof_t, &inner_proof);
@@ -205,22 +456,27 @@ pub fn generate_keccak256_proof(
         .zip(circuit.input_t.iter())
         .for_each(|(w, t)| pw.set_target(*t, F::from_canonical_u32(*w)));
     // set outputs
-    output
-        .iter()
-        .zip(circuit.output_t.iter())
-        .for_each(|(w, t)| pw.set_target(*t, F::from_canonical_u32(*w)));
+    circuit.output_t.set_witness(&mut pw, output);
+    if let (Some(salt_t), Some(salt)) = (circuit.salt_t, salt) {
+        pw.set_hash_target(salt_t, salt);
+    }
+    let now = Instant::now();
     let proof = circuit.data.prove(pw).unwrap();
-    proof
+    stats.finish(now.elapsed(), proof.to_bytes().len());
+    (proof, stats)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::time::Instant;
-
-    use super::{build_keccak256_circuit, generate_keccak256_proof, keccak256};
+    use super::{
+        build_keccak256_circuit, build_keccak256_circuit_with_mode, generate_keccak256_proof,
+        generate_keccak256_proof_with_salt, generate_keccak256_proof_with_stats, keccak256,
+        keccak256_public_inputs, Keccak256Circuit, PublicInputMode,
+    };
     use crate::keccak256_circuit::{keccak256_circuit_with_statements, xor_circuit};
     use itertools::Itertools;
-    use plonky2::field::types::Field;
+    use plonky2::field::types::{Field, Sample};
+    use plonky2::hash::hash_types::HashOut;
     use plonky2::iop::witness::{PartialWitness, WitnessWrite};
     use plonky2::plonk::{
         circuit_builder::CircuitBuilder,
@@ -230,12 +486,14 @@ mod tests {
     use rand::Rng;
     use tiny_keccak::{Hasher, Keccak};
 
+    use crate::types::Digest256;
+
     #[test]
     fn test_keccak256() {
         let mut rng = rand::thread_rng();
-        let input = vec![rng.gen(); 33];
-        let (output, _pi) = keccak256(input.clone());
-        let output_expected: [u32; 8] = {
+        let input: Vec<u32> = (0..33).map(|_| rng.gen()).collect();
+        let (output, _statements) = keccak256(input.clone());
+        let output_expected = {
             let mut hasher = Keccak::v256();
             let mut output = [0u8; 32];
             let input = input
@@ -244,17 +502,7 @@ mod tests {
                 .collect_vec();
             hasher.update(&input);
             hasher.finalize(&mut output);
-            let output = output
-                .chunks(4)
-                .map(|chunk| {
-                    let mut res = 0u32;
-                    for i in 0..4 {
-                        res += (chunk[i] as u32) << (8 * i);
-                    }
-                    res
-                })
-                .collect_vec();
-            output.try_into().unwrap()
+            Digest256(output)
         };
         assert!(output == output_expected);
     }
@@ -285,8 +533,8 @@ mod tests {
         type F = <C as GenericConfig<D>>::F;
 
         let mut rng = rand::thread_rng();
-        let input = vec![rng.gen(); 3545];
-        let (output, pi) = keccak256(input.clone());
+        let input: Vec<u32> = (0..3545).map(|_| rng.gen()).collect();
+        let (output, statements) = keccak256(input.clone());
 
         let config = CircuitConfig::standard_recursion_config();
         let mut builder = CircuitBuilder::<F, D>::new(config);
@@ -295,15 +543,14 @@ mod tests {
             .iter()
             .map(|x| builder.constant(F::from_canonical_u32(*x)))
             .collect_vec();
-        let (output_t, pi_t) = keccak256_circuit_with_statements(&mut builder, input_t.clone());
+        let (output_t, statements_t) =
+            keccak256_circuit_with_statements(&mut builder, input_t.clone());
         let mut pw = PartialWitness::<F>::new();
-        output
-            .iter()
-            .zip(output_t.iter())
-            .for_each(|(w, t)| pw.set_target(*t, F::from_canonical_u32(*w)));
-        pi.iter()
-            .zip(pi_t.iter())
-            .for_each(|(w, t)| pw.set_target(*t, F::from_canonical_u32(*w)));
+        output_t.set_witness(&mut pw, output);
+        for ((input, output), (input_t, output_t)) in statements.iter().zip(statements_t.iter()) {
+            input_t.set_witness(&mut pw, *input);
+            output_t.set_witness(&mut pw, *output);
+        }
 
         let data = builder.build::<C>();
         let _proof = data.prove(pw).unwrap();
@@ -318,16 +565,15 @@ mod tests {
 
         let circuit = build_keccak256_circuit(input_len);
         let mut rng = rand::thread_rng();
-        let input: Vec<u32> = vec![rng.gen(); input_len];
+        let input: Vec<u32> = (0..input_len).map(|_| rng.gen()).collect();
 
-        let now = Instant::now();
-        let proof = generate_keccak256_proof(input.clone(), &circuit);
-        println!("proof generation took {:?}", now.elapsed());
+        let (proof, stats) = generate_keccak256_proof_with_stats(input.clone(), &circuit);
+        assert_eq!(stats.num_perms, input_len * 4 / 136 + 1);
 
         // assertion
         {
-            let (output, _pi) = keccak256(input.clone());
-            let input_and_output = [input.as_slice(), &output]
+            let (output, _statements) = keccak256(input.clone());
+            let input_and_output = [input.as_slice(), &output.words()]
                 .concat()
                 .iter()
                 .map(|x| F::from_canonical_u32(*x))
@@ -335,4 +581,58 @@ mod tests {
             assert!(proof.public_inputs == input_and_output);
         }
     }
+
+    #[test]
+    fn test_private_keccak256_circuit() {
+        let input_len: usize = 50;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<2>>::F;
+
+        let mut rng = rand::thread_rng();
+        let input: Vec<u32> = (0..input_len).map(|_| rng.gen()).collect();
+
+        let circuit = build_keccak256_circuit_with_mode(input_len, PublicInputMode::DigestOnly);
+        let proof = generate_keccak256_proof(input.clone(), &circuit);
+        circuit.data.verify(proof.clone()).unwrap();
+        let (output, _statements) = keccak256(input.clone());
+        let output = output.to_field_elements::<F>().to_vec();
+        assert!(proof.public_inputs == output);
+        assert!(
+            proof.public_inputs
+                == keccak256_public_inputs(&input, PublicInputMode::DigestOnly, HashOut::ZERO)
+        );
+
+        let circuit =
+            build_keccak256_circuit_with_mode(input_len, PublicInputMode::MessageCommitment);
+        let salt = HashOut::<F>::rand();
+        let proof = generate_keccak256_proof_with_salt(input.clone(), salt, &circuit);
+        circuit.data.verify(proof.clone()).unwrap();
+        assert!(proof.public_inputs.len() == 4 + 8);
+        assert!(
+            proof.public_inputs
+                == keccak256_public_inputs(&input, PublicInputMode::MessageCommitment, salt)
+        );
+
+        // A deserialized circuit recovers the salt and the other targets.
+        let bytes = circuit.to_bytes().unwrap();
+        let loaded =
+            Keccak256Circuit::from_bytes(&bytes, 4 * input_len, PublicInputMode::MessageCommitment)
+                .unwrap();
+        let proof = generate_keccak256_proof_with_salt(input.clone(), salt, &loaded);
+        circuit.data.verify(proof.clone()).unwrap();
+        assert!(
+            proof.public_inputs
+                == keccak256_public_inputs(&input, PublicInputMode::MessageCommitment, salt)
+        );
+
+        // It does not load for another length or mode, or with another circuit digest.
+        let num_bytes = 4 * input_len;
+        assert!(Keccak256Circuit::from_bytes(&bytes, num_bytes + 1, circuit.mode).is_err());
+        assert!(
+            Keccak256Circuit::from_bytes(&bytes, num_bytes, PublicInputMode::DigestOnly).is_err()
+        );
+        let mut tampered = bytes;
+        tampered[1 + env!("CARGO_PKG_VERSION").len() + 4 + 8 * 2] ^= 1;
+        assert!(Keccak256Circuit::from_bytes(&tampered, num_bytes, circuit.mode).is_err());
+    }
 }
@@ -0,0 +1,99 @@
+use anyhow::{anyhow, ensure, Result};
+use plonky2::plonk::{
+    circuit_data::VerifierCircuitData,
+    config::{GenericConfig, PoseidonGoldilocksConfig},
+    proof::ProofWithPublicInputs,
+};
+
+use crate::public_inputs::decode_keccak256_public_inputs;
+use crate::serialization::KeccakGateSerializer;
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+/// Verifies a serialized keccak256 proof of `digest = keccak256(message)`, without any of the
+/// prover. `verifier_data` is `VerifierCircuitData::to_bytes` with `KeccakGateSerializer`,
+/// and `proof` is `ProofWithPublicInputs::to_bytes`, as stored in a `ProofContainer`.
+///
+/// The verifier data is trusted: the caller must pin it, e.g. by its circuit digest, to a circuit
+/// built with `build_keccak256_bytes_circuit(message.len())`.
+pub fn verify_keccak256_proof_bytes(
+    verifier_data: &[u8],
+    proof: &[u8],
+    message: &[u8],
+    digest: &[u8],
+) -> Result<()> {
+    let verifier_data =
+        VerifierCircuitData::<F, C, D>::from_bytes(verifier_data.to_vec(), &KeccakGateSerializer)
+            .map_err(|_| anyhow!("invalid verifier data"))?;
+    let proof =
+        ProofWithPublicInputs::<F, C, D>::from_bytes(proof.to_vec(), &verifier_data.common)?;
+    let pairs = decode_keccak256_public_inputs(&proof.public_inputs, &[message.len()])?;
+    ensure!(
+        pairs[0].message == message,
+        "the proof is for a different message"
+    );
+    ensure!(
+        pairs[0].digest.0[..] == *digest,
+        "the proof is for a different digest"
+    );
+    verifier_data.verify(proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::keccak256_circuit::{
+        build_keccak256_bytes_circuit, bytes_to_words, generate_keccak256_proof, keccak256_bytes,
+    };
+    use crate::keccak256_verifier::verify_keccak256_proof_bytes;
+    use crate::serialization::KeccakGateSerializer;
+
+    /// (verifier data, proof, message, digest) for the message "abc".
+    fn abc_fixture() -> (Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>) {
+        let message = b"abc".to_vec();
+        let circuit = build_keccak256_bytes_circuit(message.len());
+        let proof = generate_keccak256_proof(bytes_to_words(&message), &circuit);
+        let verifier_data = circuit
+            .data
+            .verifier_data()
+            .to_bytes(&KeccakGateSerializer)
+            .unwrap();
+        let digest = keccak256_bytes(&message).to_bytes().to_vec();
+        (verifier_data, proof.to_bytes(), message, digest)
+    }
+
+    #[test]
+    fn test_verify_keccak256_proof_bytes() {
+        let (verifier_data, proof, message, digest) = abc_fixture();
+        verify_keccak256_proof_bytes(&verifier_data, &proof, &message, &digest).unwrap();
+
+        let mut wrong_digest = digest.clone();
+        wrong_digest[0] ^= 1;
+        assert!(
+            verify_keccak256_proof_bytes(&verifier_data, &proof, &message, &wrong_digest).is_err()
+        );
+        assert!(verify_keccak256_proof_bytes(&verifier_data, &proof, b"abd", &digest).is_err());
+        let mut wrong_proof = proof.clone();
+        *wrong_proof.last_mut().unwrap() ^= 1;
+        assert!(
+            verify_keccak256_proof_bytes(&verifier_data, &wrong_proof, &message, &digest).is_err()
+        );
+    }
+
+    /// Writes the fixtures of the wasm tests in `tests/wasm.rs`. Run by `scripts/wasm-test.sh`.
+    #[test]
+    #[ignore]
+    fn write_wasm_fixtures() {
+        let (verifier_data, proof, message, digest) = abc_fixture();
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/wasm-fixtures");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("abc.verifier_data"), verifier_data).unwrap();
+        fs::write(dir.join("abc.proof"), proof).unwrap();
+        fs::write(dir.join("abc.message"), message).unwrap();
+        fs::write(dir.join("abc.digest"), digest).unwrap();
+    }
+}
@@ -0,0 +1,335 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use itertools::Itertools;
+use plonky2::field::extension::{Extendable, FieldExtension};
+use plonky2::field::packed::PackedField;
+use plonky2::field::polynomial::PolynomialValues;
+use plonky2::field::types::Field;
+use plonky2::hash::hash_types::RichField;
+use plonky2::util::transpose;
+use starky::config::StarkConfig;
+use starky::constraint_consumer::{ConstraintConsumer, RecursiveConstraintConsumer};
+use starky::stark::Stark;
+use starky::vars::{StarkEvaluationTargets, StarkEvaluationVars};
+
+use crate::columns::{reg_step, NUM_COLUMNS};
+use crate::keccak_constraints::{eval_keccak_round, eval_keccak_round_circuit};
+use crate::keccak_stark::{KeccakStark, NUM_INPUTS, NUM_ROUNDS};
+use crate::pulse::{eval_pulse, eval_pulse_circuit, generate_pulse, get_pulse_col};
+use crate::round_flags::{eval_round_flags, eval_round_flags_recursively};
+use crate::types::{statements_to_field_elements, Digest256, KeccakState};
+use crate::utils::{
+    read_input, read_input_target, read_output, read_output_target, read_state, state_eq,
+    state_eq_circuit,
+};
+
+// NUM_COLUMNS: number of chained permutations still to come
+// NUM_COLUMNS + 1: inverse of the above, or 0
+// NUM_COLUMNS + 2: 1 if the current permutation feeds the next one
+// NUM_COLUMNS + 3..: pulse columns for the final output
+const REG_REMAINING: usize = NUM_COLUMNS;
+const REG_REMAINING_INV: usize = NUM_COLUMNS + 1;
+const REG_IS_CHAINED: usize = NUM_COLUMNS + 2;
+const START_PULSE_COL: usize = NUM_COLUMNS + 3;
+
+/// Index of the u32 word of a keccak256 state that receives the first padding bit when absorbing
+/// a 32-byte message.
+const PAD_START_WORD: usize = 8;
+/// Index of the u32 word that receives the final padding bit.
+const PAD_END_WORD: usize = 136 / 4 - 1;
+
+/// The sponge state absorbing a 32-byte message, i.e. the permutation input of
+/// `keccak256(digest)`.
+pub fn chain_input(digest: Digest256) -> KeccakState {
+    let mut state = KeccakState::ZERO;
+    state.0[0..4].copy_from_slice(&digest.lanes());
+    state.0[PAD_START_WORD / 2] = 0x01;
+    state.0[PAD_END_WORD / 2] = 0x80 << 56;
+    state
+}
+
+/// The u32 words of `chain_input`, other than the digest words, as field elements.
+fn chain_padding_word<F: Field>(i: usize) -> F {
+    debug_assert!(i >= 8);
+    match i {
+        PAD_START_WORD => F::ONE,
+        PAD_END_WORD => F::from_canonical_u32(0x80 << 24),
+        _ => F::ZERO,
+    }
+}
+
+/// A STARK proving `num_perms` chained keccak256 calls on 32-byte messages, i.e. `y = H^n(x)`.
+/// The first 4 lanes of each permutation's output, padded with `chain_input`, are constrained to
+/// be the input of the next permutation, so the intermediate digests never appear in the public
+/// inputs.
+///
+/// The public inputs are the input state of the first permutation (50 u32 limbs) followed by the
+/// output state of the last one (50 u32 limbs).
+#[derive(Copy, Clone, Default)]
+pub struct KeccakChainStark<F, const D: usize> {
+    pub num_perms: usize,
+    pub(crate) f: PhantomData<F>,
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> KeccakChainStark<F, D> {
+    pub fn new(num_perms: usize) -> Self {
+        assert!(num_perms > 0);
+        Self {
+            num_perms,
+            f: PhantomData,
+        }
+    }
+
+    pub fn config(&self) -> StarkConfig {
+        let num_columns = START_PULSE_COL + 1 + 2;
+        let num_public_inputs = 2 * 2 * NUM_INPUTS;
+        StarkConfig::standard_fast_config(num_columns, num_public_inputs)
+    }
+
+    fn output_row(&self) -> usize {
+        NUM_ROUNDS * self.num_perms - 1
+    }
+
+    /// Returns the trace and the output state of the last permutation.
+    pub fn generate_trace(
+        &self,
+        input: KeccakState,
+        min_rows: usize,
+    ) -> (Vec<PolynomialValues<F>>, KeccakState) {
+        let keccak_stark = KeccakStark::<F, D>::new(self.num_perms);
+        let num_rows = (self.num_perms * NUM_ROUNDS)
+            .max(min_rows)
+            .next_power_of_two();
+        let mut rows = Vec::with_capacity(num_rows);
+        let mut chain_cols = Vec::with_capacity(num_rows);
+        let mut state = input;
+        let mut output = input;
+        for i in 0..self.num_perms {
+            rows.extend(keccak_stark.generate_trace_rows_for_perm(state));
+            output = state.permute();
+            state = chain_input(output.digest());
+
+            let remaining = F::from_canonical_usize(self.num_perms - 1 - i);
+            let is_chained = if remaining.is_zero() { F::ZERO } else { F::ONE };
+            let remaining_inv = remaining.try_inverse().unwrap_or(F::ZERO);
+            chain_cols.extend(vec![[remaining, remaining_inv, is_chained]; NUM_ROUNDS]);
+        }
+        let pad_rows = keccak_stark.generate_trace_rows_for_perm(KeccakState::ZERO);
+        while rows.len() < num_rows {
+            rows.extend(&pad_rows);
+        }
+        rows.drain(num_rows..);
+        chain_cols.resize(num_rows, [F::ZERO; 3]);
+
+        let mut trace_cols = transpose(
+            &rows
+                .iter()
+                .zip(chain_cols.iter())
+                .map(|(row, chain)| [row.as_slice(), chain.as_slice()].concat())
+                .collect_vec(),
+        );
+        generate_pulse(&mut trace_cols, vec![self.output_row()]);
+        let trace = trace_cols
+            .into_iter()
+            .map(|column| PolynomialValues::new(column))
+            .collect();
+        (trace, output)
+    }
+
+    pub fn generate_public_inputs(&self, input: KeccakState, output: KeccakState) -> Vec<F> {
+        statements_to_field_elements(&[(input, output)])
+    }
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for KeccakChainStark<F, D> {
+    fn eval_packed_generic<FE, P, const D2: usize>(
+        &self,
+        vars: StarkEvaluationVars<FE, P>,
+        yield_constr: &mut ConstraintConsumer<P>,
+    ) where
+        FE: FieldExtension<D2, BaseField = F>,
+        P: PackedField<Scalar = FE>,
+    {
+        eval_round_flags(vars, yield_constr);
+
+        // The remaining counter starts at num_perms - 1 and decreases after every chained
+        // permutation, and is_chained is 1 exactly when it is nonzero.
+        let remaining = vars.local_values[REG_REMAINING];
+        let remaining_inv = vars.local_values[REG_REMAINING_INV];
+        let is_chained = vars.local_values[REG_IS_CHAINED];
+        let next_remaining = vars.next_values[REG_REMAINING];
+        let last_round = vars.local_values[reg_step(NUM_ROUNDS - 1)];
+        yield_constr.constraint_first_row(remaining - FE::from_canonical_usize(self.num_perms - 1));
+        yield_constr.constraint(is_chained - remaining * remaining_inv);
+        yield_constr.constraint(remaining * (P::ONES - is_chained));
+        yield_constr.constraint_transition(next_remaining - remaining + last_round * is_chained);
+
+        // A chained permutation's output digest, padded, is the next permutation's input.
+        let output = read_output(vars.local_values);
+        let next_input = read_input(vars.next_values);
+        let chain_filter = last_round * is_chained;
+        for i in 0..2 * NUM_INPUTS {
+            let expected = if i < 8 {
+                output[i]
+            } else {
+                P::from(chain_padding_word::<FE>(i))
+            };
+            yield_constr.constraint_transition(chain_filter * (next_input[i] - expected));
+        }
+
+        // eval pulse
+        let output_row = self.output_row();
+        eval_pulse(
+            yield_constr,
+            vars.local_values,
+            vars.next_values,
+            START_PULSE_COL,
+            vec![output_row],
+        );
+        let output_flag = vars.local_values[get_pulse_col(START_PULSE_COL, 0)];
+
+        // public inputs and outputs
+        let pi: &[P] = &vars.public_inputs.iter().map(|&x| x.into()).collect_vec();
+        let mut cur_col = 0;
+        let input_pi = read_state(pi, &mut cur_col);
+        let output_pi = read_state(pi, &mut cur_col);
+        let input = read_input(vars.local_values);
+        for i in 0..2 * NUM_INPUTS {
+            yield_constr.constraint_first_row(input[i] - input_pi[i]);
+        }
+        state_eq(yield_constr, output_flag, output, output_pi);
+
+        eval_keccak_round::<FE, P, D>(yield_constr, vars);
+    }
+
+    fn eval_ext_circuit(
+        &self,
+        builder: &mut plonky2::plonk::circuit_builder::CircuitBuilder<F, D>,
+        vars: StarkEvaluationTargets<D>,
+        yield_constr: &mut RecursiveConstraintConsumer<F, D>,
+    ) {
+        let one_ext = builder.one_extension();
+        eval_round_flags_recursively(builder, vars, yield_constr);
+
+        // The remaining counter starts at num_perms - 1 and decreases after every chained
+        // permutation, and is_chained is 1 exactly when it is nonzero.
+        let remaining = vars.local_values[REG_REMAINING];
+        let remaining_inv = vars.local_values[REG_REMAINING_INV];
+        let is_chained = vars.local_values[REG_IS_CHAINED];
+        let next_remaining = vars.next_values[REG_REMAINING];
+        let last_round = vars.local_values[reg_step(NUM_ROUNDS - 1)];
+        {
+            let initial =
+                builder.constant_extension(F::Extension::from_canonical_usize(self.num_perms - 1));
+            let diff = builder.sub_extension(remaining, initial);
+            yield_constr.constraint_first_row(builder, diff);
+        }
+        {
+            let product = builder.mul_extension(remaining, remaining_inv);
+            let diff = builder.sub_extension(is_chained, product);
+            yield_constr.constraint(builder, diff);
+        }
+        {
+            let not_chained = builder.sub_extension(one_ext, is_chained);
+            let t = builder.mul_extension(remaining, not_chained);
+            yield_constr.constraint(builder, t);
+        }
+        {
+            let diff = builder.sub_extension(next_remaining, remaining);
+            let t = builder.mul_add_extension(last_round, is_chained, diff);
+            yield_constr.constraint_transition(builder, t);
+        }
+
+        // A chained permutation's output digest, padded, is the next permutation's input.
+        let output = read_output_target(builder, vars.local_values);
+        let next_input = read_input_target(builder, vars.next_values);
+        let chain_filter = builder.mul_extension(last_round, is_chained);
+        for i in 0..2 * NUM_INPUTS {
+            let expected = if i < 8 {
+                output[i]
+            } else {
+                builder.constant_extension(chain_padding_word::<F::Extension>(i))
+            };
+            let diff = builder.sub_extension(next_input[i], expected);
+            let t = builder.mul_extension(chain_filter, diff);
+            yield_constr.constraint_transition(builder, t);
+        }
+
+        // eval pulse
+        let output_row = self.output_row();
+        eval_pulse_circuit(
+            builder,
+            yield_constr,
+            vars.local_values,
+            vars.next_values,
+            START_PULSE_COL,
+            vec![output_row],
+        );
+        let output_flag = vars.local_values[get_pulse_col(START_PULSE_COL, 0)];
+
+        // public inputs and outputs
+        let pi = vars.public_inputs;
+        let mut cur_col = 0;
+        let input_pi = read_state(pi, &mut cur_col);
+        let output_pi = read_state(pi, &mut cur_col);
+        let input = read_input_target(builder, vars.local_values);
+        for i in 0..2 * NUM_INPUTS {
+            let diff = builder.sub_extension(input[i], input_pi[i]);
+            yield_constr.constraint_first_row(builder, diff);
+        }
+        state_eq_circuit(builder, yield_constr, output_flag, output, output_pi);
+
+        eval_keccak_round_circuit::<F, D>(builder, yield_constr, vars);
+    }
+
+    fn constraint_degree(&self) -> usize {
+        3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+    use plonky2::util::timing::TimingTree;
+    use starky::prover::prove;
+    use starky::verifier::verify_stark_proof;
+
+    use crate::keccak_chain_stark::{chain_input, KeccakChainStark};
+    use crate::types::Digest256;
+
+    #[test]
+    fn test_keccak_chain_stark() -> Result<()> {
+        const D: usize = 2;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+        type S = KeccakChainStark<F, D>;
+
+        let num_perms = 5;
+        let digest = Digest256(rand::random());
+        let input = chain_input(digest);
+        let mut expected = input;
+        for i in 0..num_perms {
+            expected = expected.permute();
+            if i + 1 < num_perms {
+                expected = chain_input(expected.digest());
+            }
+        }
+
+        let stark = S::new(num_perms);
+        let config = stark.config();
+        let (trace, output) = stark.generate_trace(input, 8);
+        assert_eq!(output, expected);
+        let public_inputs = stark.generate_public_inputs(input, output);
+        let proof = prove::<F, C, S, D>(
+            stark,
+            &config,
+            trace,
+            public_inputs,
+            &mut TimingTree::default(),
+        )?;
+        verify_stark_proof(stark, proof, &config)
+    }
+}
@@ -1,3 +1,5 @@
+use alloc::vec::Vec;
+
 use itertools::Itertools;
 use plonky2::{
     field::{extension::Extendable, packed::PackedField, types::Field},
@@ -24,7 +26,15 @@ use super::{
 
 pub fn generate_keccak_trace_row_for_round<F: RichField>(row: &mut [F; NUM_COLUMNS], round: usize) {
     row[reg_step(round)] = F::ONE;
+    generate_c(row);
+    generate_c_prime(row);
+    generate_a_prime(row);
+    generate_a_prime_prime(row);
+    generate_a_prime_prime_0_0_bits(row);
+    generate_a_prime_prime_prime_0_0(row, round);
+}
 
+pub(crate) fn generate_c<F: RichField>(row: &mut [F; NUM_COLUMNS]) {
     // Populate C[x] = xor(A[x, 0], A[x, 1], A[x, 2], A[x, 3], A[x, 4]).
     for x in 0..5 {
         for z in 0..64 {
@@ -38,7 +48,9 @@ pub fn generate_keccak_trace_row_for_round<F: RichField>(row: &mut [F; NUM_COLUM
             row[reg_c(x, z)] = xor(a);
         }
     }
+}
 
+pub(crate) fn generate_c_prime<F: RichField>(row: &mut [F; NUM_COLUMNS]) {
     // Populate C'[x, z] = xor(C[x, z], C[x - 1, z], C[x + 1, z - 1]).
     for x in 0..5 {
         for z in 0..64 {
@@ -49,7 +61,9 @@ pub fn generate_keccak_trace_row_for_round<F: RichField>(row: &mut [F; NUM_COLUM
             ]);
         }
     }
+}
 
+pub(crate) fn generate_a_prime<F: RichField>(row: &mut [F; NUM_COLUMNS]) {
     // Populate A'. To avoid shifting indices, we rewrite
     //     A'[x, y, z] = xor(A[x, y, z], C[x - 1, z], C[x + 1, z - 1])
     // as
@@ -66,7 +80,9 @@ pub fn generate_keccak_trace_row_for_round<F: RichField>(row: &mut [F; NUM_COLUM
             }
         }
     }
+}
 
+pub(crate) fn generate_a_prime_prime<F: RichField>(row: &mut [F; NUM_COLUMNS]) {
     // Populate A''.
     // A''[x, y] = xor(B[x, y], andn(B[x + 1, y], B[x + 2, y])).
     for x in 0..5 {
@@ -91,7 +107,9 @@ pub fn generate_keccak_trace_row_for_round<F: RichField>(row: &mut [F; NUM_COLUM
             row[reg_hi] = hi;
         }
     }
+}
 
+pub(crate) fn generate_a_prime_prime_0_0_bits<F: RichField>(row: &mut [F; NUM_COLUMNS]) {
     // For the XOR, we split A''[0, 0] to bits.
     let val_lo = row[reg_a_prime_prime(0, 0)].to_canonical_u64();
     let val_hi = row[reg_a_prime_prime(0, 0) + 1].to_canonical_u64();
@@ -106,7 +124,12 @@ pub fn generate_keccak_trace_row_for_round<F: RichField>(row: &mut [F; NUM_COLUM
     for i in 0..64 {
         row[reg_a_prime_prime_0_0_bit(i)] = F::from_canonical_u64(bit_values[i]);
     }
+}
 
+pub(crate) fn generate_a_prime_prime_prime_0_0<F: RichField>(
+    row: &mut [F; NUM_COLUMNS],
+    round: usize,
+) {
     // A''[0, 0] is additionally xor'd with RC.
     let in_reg_lo = reg_a_prime_prime(0, 0);
     let in_reg_hi = in_reg_lo + 1;
@@ -122,87 +145,147 @@ pub fn eval_keccak_round<F: Field, P: PackedField<Scalar = F>, const D: usize>(
     yield_constr: &mut ConstraintConsumer<P>,
     vars: StarkEvaluationVars<F, P>,
 ) {
-    // C'[x, z] = xor(C[x, z], C[x - 1, z], C[x + 1, z - 1]).
     for x in 0..5 {
         for z in 0..64 {
-            let xor = xor3_gen(
-                vars.local_values[reg_c(x, z)],
-                vars.local_values[reg_c((x + 4) % 5, z)],
-                vars.local_values[reg_c((x + 1) % 5, (z + 63) % 64)],
-            );
-            let c_prime = vars.local_values[reg_c_prime(x, z)];
-            yield_constr.constraint(c_prime - xor);
+            eval_theta_c_prime(yield_constr, vars, x, z);
         }
     }
-    // Check that the input limbs are consistent with A' and D.
-    // A[x, y, z] = xor(A'[x, y, z], D[x, y, z])
-    //            = xor(A'[x, y, z], C[x - 1, z], C[x + 1, z - 1])
-    //            = xor(A'[x, y, z], C[x, z], C'[x, z]).
-    // The last step is valid based on the identity we checked above.
-    // It isn't required, but makes this check a bit cleaner.
     for x in 0..5 {
         for y in 0..5 {
-            let a_lo = vars.local_values[reg_a(x, y)];
-            let a_hi = vars.local_values[reg_a(x, y) + 1];
-            let get_bit = |z| {
-                let a_prime = vars.local_values[reg_a_prime(x, y, z)];
-                let c = vars.local_values[reg_c(x, z)];
-                let c_prime = vars.local_values[reg_c_prime(x, z)];
-                xor3_gen(a_prime, c, c_prime)
-            };
-            let computed_lo = (0..32)
-                .rev()
-                .fold(P::ZEROS, |acc, z| acc.doubles() + get_bit(z));
-            let computed_hi = (32..64)
-                .rev()
-                .fold(P::ZEROS, |acc, z| acc.doubles() + get_bit(z));
-            yield_constr.constraint(computed_lo - a_lo);
-            yield_constr.constraint(computed_hi - a_hi);
+            eval_a_prime_consistency(yield_constr, vars, x, y);
         }
     }
-
-    // xor_{i=0}^4 A'[x, i, z] = C'[x, z], so for each x, z,
-    // diff * (diff - 2) * (diff - 4) = 0, where
-    // diff = sum_{i=0}^4 A'[x, i, z] - C'[x, z]
     for x in 0..5 {
         for z in 0..64 {
-            let sum: P = [0, 1, 2, 3, 4]
-                .map(|i| vars.local_values[reg_a_prime(x, i, z)])
-                .into_iter()
-                .sum();
-            let diff = sum - vars.local_values[reg_c_prime(x, z)];
-            yield_constr.constraint(diff * (diff - F::TWO) * (diff - F::from_canonical_u8(4)));
+            eval_a_prime_parity(yield_constr, vars, x, z);
         }
     }
-
-    // A''[x, y] = xor(B[x, y], andn(B[x + 1, y], B[x + 2, y])).
     for x in 0..5 {
         for y in 0..5 {
-            let get_bit = |z| {
-                xor_gen(
-                    vars.local_values[reg_b(x, y, z)],
-                    andn_gen(
-                        vars.local_values[reg_b((x + 1) % 5, y, z)],
-                        vars.local_values[reg_b((x + 2) % 5, y, z)],
-                    ),
-                )
-            };
-            let reg_lo = reg_a_prime_prime(x, y);
-            let reg_hi = reg_lo + 1;
-            let lo = vars.local_values[reg_lo];
-            let hi = vars.local_values[reg_hi];
-            let computed_lo = (0..32)
-                .rev()
-                .fold(P::ZEROS, |acc, z| acc.doubles() + get_bit(z));
-            let computed_hi = (32..64)
-                .rev()
-                .fold(P::ZEROS, |acc, z| acc.doubles() + get_bit(z));
-            yield_constr.constraint(computed_lo - lo);
-            yield_constr.constraint(computed_hi - hi);
+            eval_chi(yield_constr, vars, x, y);
+        }
+    }
+    eval_iota(yield_constr, vars);
+    for x in 0..5 {
+        for y in 0..5 {
+            eval_round_transition(yield_constr, vars, x, y);
+        }
+    }
+    for x in 0..5 {
+        for z in 0..64 {
+            eval_c_booleanity(yield_constr, vars, x, z);
+        }
+    }
+    for x in 0..5 {
+        for y in 0..5 {
+            for z in 0..64 {
+                eval_a_prime_booleanity(yield_constr, vars, x, y, z);
+            }
         }
     }
+    for i in 0..64 {
+        eval_a_prime_prime_0_0_bit_booleanity(yield_constr, vars, i);
+    }
+}
 
-    // A'''[0, 0] = A''[0, 0] XOR RC
+/// C'[x, z] = xor(C[x, z], C[x - 1, z], C[x + 1, z - 1]).
+pub(crate) fn eval_theta_c_prime<F: Field, P: PackedField<Scalar = F>>(
+    yield_constr: &mut ConstraintConsumer<P>,
+    vars: StarkEvaluationVars<F, P>,
+    x: usize,
+    z: usize,
+) {
+    let xor = xor3_gen(
+        vars.local_values[reg_c(x, z)],
+        vars.local_values[reg_c((x + 4) % 5, z)],
+        vars.local_values[reg_c((x + 1) % 5, (z + 63) % 64)],
+    );
+    let c_prime = vars.local_values[reg_c_prime(x, z)];
+    yield_constr.constraint(c_prime - xor);
+}
+
+/// Check that the input limbs are consistent with A' and D.
+/// A[x, y, z] = xor(A'[x, y, z], D[x, y, z])
+///            = xor(A'[x, y, z], C[x - 1, z], C[x + 1, z - 1])
+///            = xor(A'[x, y, z], C[x, z], C'[x, z]).
+/// The last step is valid based on the identity checked by `eval_theta_c_prime`.
+/// It isn't required, but makes this check a bit cleaner.
+pub(crate) fn eval_a_prime_consistency<F: Field, P: PackedField<Scalar = F>>(
+    yield_constr: &mut ConstraintConsumer<P>,
+    vars: StarkEvaluationVars<F, P>,
+    x: usize,
+    y: usize,
+) {
+    let a_lo = vars.local_values[reg_a(x, y)];
+    let a_hi = vars.local_values[reg_a(x, y) + 1];
+    let get_bit = |z| {
+        let a_prime = vars.local_values[reg_a_prime(x, y, z)];
+        let c = vars.local_values[reg_c(x, z)];
+        let c_prime = vars.local_values[reg_c_prime(x, z)];
+        xor3_gen(a_prime, c, c_prime)
+    };
+    let computed_lo = (0..32)
+        .rev()
+        .fold(P::ZEROS, |acc, z| acc.doubles() + get_bit(z));
+    let computed_hi = (32..64)
+        .rev()
+        .fold(P::ZEROS, |acc, z| acc.doubles() + get_bit(z));
+    yield_constr.constraint(computed_lo - a_lo);
+    yield_constr.constraint(computed_hi - a_hi);
+}
+
+/// xor_{i=0}^4 A'[x, i, z] = C'[x, z], so for each x, z,
+/// diff * (diff - 2) * (diff - 4) = 0, where
+/// diff = sum_{i=0}^4 A'[x, i, z] - C'[x, z]
+pub(crate) fn eval_a_prime_parity<F: Field, P: PackedField<Scalar = F>>(
+    yield_constr: &mut ConstraintConsumer<P>,
+    vars: StarkEvaluationVars<F, P>,
+    x: usize,
+    z: usize,
+) {
+    let sum: P = [0, 1, 2, 3, 4]
+        .map(|i| vars.local_values[reg_a_prime(x, i, z)])
+        .into_iter()
+        .sum();
+    let diff = sum - vars.local_values[reg_c_prime(x, z)];
+    yield_constr.constraint(diff * (diff - F::TWO) * (diff - F::from_canonical_u8(4)));
+}
+
+/// A''[x, y] = xor(B[x, y], andn(B[x + 1, y], B[x + 2, y])).
+pub(crate) fn eval_chi<F: Field, P: PackedField<Scalar = F>>(
+    yield_constr: &mut ConstraintConsumer<P>,
+    vars: StarkEvaluationVars<F, P>,
+    x: usize,
+    y: usize,
+) {
+    let get_bit = |z| {
+        xor_gen(
+            vars.local_values[reg_b(x, y, z)],
+            andn_gen(
+                vars.local_values[reg_b((x + 1) % 5, y, z)],
+                vars.local_values[reg_b((x + 2) % 5, y, z)],
+            ),
+        )
+    };
+    let reg_lo = reg_a_prime_prime(x, y);
+    let reg_hi = reg_lo + 1;
+    let lo = vars.local_values[reg_lo];
+    let hi = vars.local_values[reg_hi];
+    let computed_lo = (0..32)
+        .rev()
+        .fold(P::ZEROS, |acc, z| acc.doubles() + get_bit(z));
+    let computed_hi = (32..64)
+        .rev()
+        .fold(P::ZEROS, |acc, z| acc.doubles() + get_bit(z));
+    yield_constr.constraint(computed_lo - lo);
+    yield_constr.constraint(computed_hi - hi);
+}
+
+/// A'''[0, 0] = A''[0, 0] XOR RC
+pub(crate) fn eval_iota<F: Field, P: PackedField<Scalar = F>>(
+    yield_constr: &mut ConstraintConsumer<P>,
+    vars: StarkEvaluationVars<F, P>,
+) {
     let a_prime_prime_0_0_bits = (0..64)
         .map(|i| vars.local_values[reg_a_prime_prime_0_0_bit(i)])
         .collect_vec();
@@ -238,20 +321,58 @@ pub fn eval_keccak_round<F: Field, P: PackedField<Scalar = F>, const D: usize>(
         .fold(P::ZEROS, |acc, z| acc.doubles() + get_xored_bit(z));
     yield_constr.constraint(computed_a_prime_prime_prime_0_0_lo - a_prime_prime_prime_0_0_lo);
     yield_constr.constraint(computed_a_prime_prime_prime_0_0_hi - a_prime_prime_prime_0_0_hi);
+}
 
-    // Enforce that this round's output equals the next round's input.
-    for x in 0..5 {
-        for y in 0..5 {
-            let output_lo = vars.local_values[reg_a_prime_prime_prime(x, y)];
-            let output_hi = vars.local_values[reg_a_prime_prime_prime(x, y) + 1];
-            let input_lo = vars.next_values[reg_a(x, y)];
-            let input_hi = vars.next_values[reg_a(x, y) + 1];
-            let is_last_round = vars.local_values[reg_step(NUM_ROUNDS - 1)];
-            let not_last_round = P::ONES - is_last_round;
-            yield_constr.constraint_transition(not_last_round * (output_lo - input_lo));
-            yield_constr.constraint_transition(not_last_round * (output_hi - input_hi));
-        }
-    }
+/// Enforce that this round's output equals the next round's input.
+pub(crate) fn eval_round_transition<F: Field, P: PackedField<Scalar = F>>(
+    yield_constr: &mut ConstraintConsumer<P>,
+    vars: StarkEvaluationVars<F, P>,
+    x: usize,
+    y: usize,
+) {
+    let output_lo = vars.local_values[reg_a_prime_prime_prime(x, y)];
+    let output_hi = vars.local_values[reg_a_prime_prime_prime(x, y) + 1];
+    let input_lo = vars.next_values[reg_a(x, y)];
+    let input_hi = vars.next_values[reg_a(x, y) + 1];
+    let is_last_round = vars.local_values[reg_step(NUM_ROUNDS - 1)];
+    let not_last_round = P::ONES - is_last_round;
+    yield_constr.constraint_transition(not_last_round * (output_lo - input_lo));
+    yield_constr.constraint_transition(not_last_round * (output_hi - input_hi));
+}
+
+/// C[x, z] and C'[x, z] are bits. The xor identities above only hold for bits.
+pub(crate) fn eval_c_booleanity<F: Field, P: PackedField<Scalar = F>>(
+    yield_constr: &mut ConstraintConsumer<P>,
+    vars: StarkEvaluationVars<F, P>,
+    x: usize,
+    z: usize,
+) {
+    let c = vars.local_values[reg_c(x, z)];
+    let c_prime = vars.local_values[reg_c_prime(x, z)];
+    yield_constr.constraint(c * (c - P::ONES));
+    yield_constr.constraint(c_prime * (c_prime - P::ONES));
+}
+
+/// A'[x, y, z] is a bit.
+pub(crate) fn eval_a_prime_booleanity<F: Field, P: PackedField<Scalar = F>>(
+    yield_constr: &mut ConstraintConsumer<P>,
+    vars: StarkEvaluationVars<F, P>,
+    x: usize,
+    y: usize,
+    z: usize,
+) {
+    let a_prime = vars.local_values[reg_a_prime(x, y, z)];
+    yield_constr.constraint(a_prime * (a_prime - P::ONES));
+}
+
+/// Bit i of A''[0, 0] is a bit.
+pub(crate) fn eval_a_prime_prime_0_0_bit_booleanity<F: Field, P: PackedField<Scalar = F>>(
+    yield_constr: &mut ConstraintConsumer<P>,
+    vars: StarkEvaluationVars<F, P>,
+    i: usize,
+) {
+    let bit = vars.local_values[reg_a_prime_prime_0_0_bit(i)];
+    yield_constr.constraint(bit * (bit - P::ONES));
 }
 
 pub fn eval_keccak_round_circuit<F: RichField + Extendable<D>, const D: usize>(
@@ -409,4 +530,30 @@ pub fn eval_keccak_round_circuit<F: RichField + Extendable<D>, const D: usize>(
             yield_constr.constraint_transition(builder, filtered_diff);
         }
     }
+
+    // C, C', A' and the bits of A''[0, 0] are bits.
+    for x in 0..5 {
+        for z in 0..64 {
+            let c = vars.local_values[reg_c(x, z)];
+            let constraint = builder.mul_sub_extension(c, c, c);
+            yield_constr.constraint(builder, constraint);
+            let c_prime = vars.local_values[reg_c_prime(x, z)];
+            let constraint = builder.mul_sub_extension(c_prime, c_prime, c_prime);
+            yield_constr.constraint(builder, constraint);
+        }
+    }
+    for x in 0..5 {
+        for y in 0..5 {
+            for z in 0..64 {
+                let a_prime = vars.local_values[reg_a_prime(x, y, z)];
+                let constraint = builder.mul_sub_extension(a_prime, a_prime, a_prime);
+                yield_constr.constraint(builder, constraint);
+            }
+        }
+    }
+    for i in 0..64 {
+        let bit = vars.local_values[reg_a_prime_prime_0_0_bit(i)];
+        let constraint = builder.mul_sub_extension(bit, bit, bit);
+        yield_constr.constraint(builder, constraint);
+    }
 }
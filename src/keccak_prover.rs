@@ -0,0 +1,109 @@
+use anyhow::{ensure, Result};
+use itertools::Itertools;
+use plonky2::{
+    plonk::config::{GenericConfig, PoseidonGoldilocksConfig},
+    util::timing::TimingTree,
+};
+use starky::{proof::StarkProofWithPublicInputs, prover::prove, verifier::verify_stark_proof};
+
+use crate::keccak256_circuit::MIN_TRACE_ROWS;
+use crate::keccak_stark::KeccakStark;
+use crate::types::{statements_from_field_elements, KeccakState};
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+type S = KeccakStark<F, D>;
+
+/// A `KeccakStark` proof together with the permutations it proves.
+#[derive(Clone, Debug)]
+pub struct KeccakPermutationProof {
+    pub proof: StarkProofWithPublicInputs<F, C, D>,
+    /// The (input, output) pair of each permutation, decoded from the public inputs.
+    pub permutations: Vec<(KeccakState, KeccakState)>,
+}
+
+/// Proves batches of Keccak-f[1600] permutations with `KeccakStark`, keeping the stark, its
+/// config, the trace and the public inputs consistent with each other.
+#[derive(Clone, Copy, Debug)]
+pub struct KeccakProver {
+    min_rows: usize,
+}
+
+impl Default for KeccakProver {
+    fn default() -> Self {
+        Self {
+            min_rows: MIN_TRACE_ROWS,
+        }
+    }
+}
+
+impl KeccakProver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pads the trace to at least `min_rows` rows. The height is always a power of two of at least
+    /// 24 rows per permutation.
+    pub fn with_min_rows(mut self, min_rows: usize) -> Self {
+        self.min_rows = min_rows;
+        self
+    }
+
+    pub fn prove_permutations(&self, inputs: &[KeccakState]) -> Result<KeccakPermutationProof> {
+        ensure!(!inputs.is_empty(), "no permutations to prove");
+        let outputs = inputs.iter().map(|input| input.permute()).collect_vec();
+        let stark = S::new(inputs.len());
+        let config = stark.config();
+        let trace = stark.generate_trace(inputs.to_vec(), self.min_rows);
+        let public_inputs = stark.generate_public_inputs(inputs.to_vec(), outputs);
+        let proof = prove::<F, C, S, D>(
+            stark,
+            &config,
+            trace,
+            public_inputs,
+            &mut TimingTree::default(),
+        )?;
+        let permutations = statements_from_field_elements(&proof.public_inputs)?;
+        Ok(KeccakPermutationProof {
+            proof,
+            permutations,
+        })
+    }
+
+    /// Verifies the proof and that its public inputs are exactly `proof.permutations`.
+    pub fn verify(&self, proof: &KeccakPermutationProof) -> Result<()> {
+        ensure!(!proof.permutations.is_empty(), "no permutations");
+        let stark = S::new(proof.permutations.len());
+        let (inputs, outputs) = proof.permutations.iter().cloned().unzip();
+        ensure!(
+            proof.proof.public_inputs == stark.generate_public_inputs(inputs, outputs),
+            "the public inputs do not match the permutations"
+        );
+        verify_stark_proof(stark, proof.proof.clone(), &stark.config())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keccak_prover::KeccakProver;
+    use crate::types::KeccakState;
+
+    #[test]
+    fn test_keccak_prover() {
+        let inputs = (0..3)
+            .map(|_| KeccakState(rand::random()))
+            .collect::<Vec<_>>();
+        let prover = KeccakProver::new();
+        let proof = prover.prove_permutations(&inputs).unwrap();
+        prover.verify(&proof).unwrap();
+        for (input, (claimed_input, output)) in inputs.iter().zip(proof.permutations.iter()) {
+            assert_eq!(claimed_input, input);
+            assert_eq!(*output, input.permute());
+        }
+
+        let mut forged = proof.clone();
+        forged.permutations[1].1 .0[0] ^= 1;
+        assert!(prover.verify(&forged).is_err());
+    }
+}
@@ -1,10 +1,14 @@
-use std::marker::PhantomData;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
 
 use itertools::Itertools;
 use plonky2::field::extension::{Extendable, FieldExtension};
 use plonky2::field::packed::PackedField;
 use plonky2::field::polynomial::PolynomialValues;
+use plonky2::field::types::Field;
 use plonky2::hash::hash_types::RichField;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
 use plonky2::util::transpose;
 use starky::config::StarkConfig;
 
@@ -12,7 +16,7 @@ use crate::columns::{reg_a, reg_step, NUM_COLUMNS, REG_FILTER};
 use starky::constraint_consumer::{ConstraintConsumer, RecursiveConstraintConsumer};
 
 use crate::round_flags::{eval_round_flags, eval_round_flags_recursively};
-use crate::utils::{split_lo_and_hi, write_state};
+use crate::types::{statements_to_field_elements, KeccakState};
 use starky::stark::Stark;
 use starky::vars::{StarkEvaluationTargets, StarkEvaluationVars};
 
@@ -47,18 +51,31 @@ impl<F: RichField + Extendable<D>, const D: usize> KeccakStark<F, D> {
     }
 
     pub fn config(&self) -> StarkConfig {
-        let num_columns = NUM_COLUMNS + 1 + 4 * self.num_io;
-        let num_public_inputs = 4 * NUM_INPUTS * self.num_io;
-        StarkConfig::standard_fast_config(num_columns, num_public_inputs)
+        StarkConfig::standard_fast_config(self.num_columns(), self.num_public_inputs())
+    }
+
+    /// The main columns, then the pulse counter and a witness and pulse column per pulse.
+    pub fn num_columns(&self) -> usize {
+        NUM_COLUMNS + 1 + 2 * gen_keccak_pulse_positions(self.num_io).len()
+    }
+
+    /// The input and output state of each permutation, as 32-bit limbs.
+    pub fn num_public_inputs(&self) -> usize {
+        4 * NUM_INPUTS * self.num_io
+    }
+
+    /// The height of the trace generated with `min_rows`.
+    pub fn num_rows(&self, min_rows: usize) -> usize {
+        (self.num_io * NUM_ROUNDS).max(min_rows).next_power_of_two()
     }
 
     fn generate_trace_rows(
         &self,
-        inputs: Vec<[u64; NUM_INPUTS]>,
+        inputs: Vec<KeccakState>,
         min_rows: usize,
     ) -> Vec<[F; NUM_COLUMNS]> {
         assert!(inputs.len() == self.num_io);
-        let num_rows = (self.num_io * NUM_ROUNDS).max(min_rows).next_power_of_two();
+        let num_rows = self.num_rows(min_rows);
         let mut rows = Vec::with_capacity(num_rows);
         for input in inputs.iter() {
             let mut rows_for_perm = self.generate_trace_rows_for_perm(*input);
@@ -66,7 +83,7 @@ impl<F: RichField + Extendable<D>, const D: usize> KeccakStark<F, D> {
             rows_for_perm[NUM_ROUNDS - 1][REG_FILTER] = F::ONE;
             rows.extend(rows_for_perm);
         }
-        let pad_rows = self.generate_trace_rows_for_perm([0; NUM_INPUTS]);
+        let pad_rows = self.generate_trace_rows_for_perm(KeccakState::ZERO);
         while rows.len() < num_rows {
             rows.extend(&pad_rows);
         }
@@ -74,12 +91,12 @@ impl<F: RichField + Extendable<D>, const D: usize> KeccakStark<F, D> {
         rows
     }
 
-    fn generate_trace_rows_for_perm(&self, input: [u64; NUM_INPUTS]) -> Vec<[F; NUM_COLUMNS]> {
+    pub(crate) fn generate_trace_rows_for_perm(&self, input: KeccakState) -> Vec<[F; NUM_COLUMNS]> {
         let mut rows = vec![[F::ZERO; NUM_COLUMNS]; NUM_ROUNDS];
         // Populate the round input for the first round.
         for x in 0..5 {
             for y in 0..5 {
-                let input_xy = input[y * 5 + x];
+                let input_xy = input.0[y * 5 + x];
                 let reg_lo = reg_a(x, y);
                 let reg_hi = reg_lo + 1;
                 rows[0][reg_lo] = F::from_canonical_u64(input_xy & 0xFFFFFFFF);
@@ -110,7 +127,7 @@ impl<F: RichField + Extendable<D>, const D: usize> KeccakStark<F, D> {
 
     pub fn generate_trace(
         &self,
-        inputs: Vec<[u64; NUM_INPUTS]>,
+        inputs: Vec<KeccakState>,
         min_rows: usize,
     ) -> Vec<PolynomialValues<F>> {
         assert!(inputs.len() == self.num_io);
@@ -124,27 +141,16 @@ impl<F: RichField + Extendable<D>, const D: usize> KeccakStark<F, D> {
         trace
     }
 
+    /// The input and output state of each permutation, in order.
     pub fn generate_public_inputs(
         &self,
-        inputs: Vec<[u64; NUM_INPUTS]>,
-        outputs: Vec<[u64; NUM_INPUTS]>,
+        inputs: Vec<KeccakState>,
+        outputs: Vec<KeccakState>,
     ) -> Vec<F> {
         assert!(inputs.len() == self.num_io);
         assert!(outputs.len() == self.num_io);
-        let mut pi = vec![F::ZERO; 2 * 50 * self.num_io];
-        let mut cur_col = 0;
-        inputs
-            .iter()
-            .zip(outputs.iter())
-            .map(|(&input, &output)| {
-                let input = split_lo_and_hi(input).map(F::from_canonical_u32);
-                let output = split_lo_and_hi(output).map(F::from_canonical_u32);
-                write_state(&mut pi, &input, &mut cur_col);
-                write_state(&mut pi, &output, &mut cur_col);
-            })
-            .collect_vec();
-        assert!(cur_col == 2 * 50 * self.num_io);
-        pi
+        let statements = inputs.into_iter().zip(outputs).collect_vec();
+        statements_to_field_elements(&statements)
     }
 }
 
@@ -158,15 +164,7 @@ impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for KeccakStark<F
         P: PackedField<Scalar = FE>,
     {
         eval_round_flags(vars, yield_constr);
-
-        // The filter must be 0 or 1.
-        let filter = vars.local_values[REG_FILTER];
-        yield_constr.constraint(filter * (filter - P::ONES));
-
-        // If this is not the final step, the filter must be off.
-        let final_step = vars.local_values[reg_step(NUM_ROUNDS - 1)];
-        let not_final_step = P::ONES - final_step;
-        yield_constr.constraint(not_final_step * filter);
+        eval_filter(vars, yield_constr);
 
         // eval pulse
         eval_pulse(
@@ -207,18 +205,8 @@ impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for KeccakStark<F
         vars: StarkEvaluationTargets<D>,
         yield_constr: &mut RecursiveConstraintConsumer<F, D>,
     ) {
-        let one_ext = builder.one_extension();
         eval_round_flags_recursively(builder, vars, yield_constr);
-        // The filter must be 0 or 1.
-        let filter = vars.local_values[REG_FILTER];
-        let constraint = builder.mul_sub_extension(filter, filter, filter);
-        yield_constr.constraint(builder, constraint);
-
-        // If this is not the final step, the filter must be off.
-        let final_step = vars.local_values[reg_step(NUM_ROUNDS - 1)];
-        let not_final_step = builder.sub_extension(one_ext, final_step);
-        let constraint = builder.mul_extension(not_final_step, filter);
-        yield_constr.constraint(builder, constraint);
+        eval_filter_recursively(builder, vars, yield_constr);
 
         // eval pulse
         let pulse_positions = gen_keccak_pulse_positions(self.num_io);
@@ -260,10 +248,40 @@ impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for KeccakStark<F
     }
 }
 
+pub(crate) fn eval_filter<F: Field, P: PackedField<Scalar = F>>(
+    vars: StarkEvaluationVars<F, P>,
+    yield_constr: &mut ConstraintConsumer<P>,
+) {
+    // The filter must be 0 or 1.
+    let filter = vars.local_values[REG_FILTER];
+    yield_constr.constraint(filter * (filter - P::ONES));
+
+    // If this is not the final step, the filter must be off.
+    let final_step = vars.local_values[reg_step(NUM_ROUNDS - 1)];
+    let not_final_step = P::ONES - final_step;
+    yield_constr.constraint(not_final_step * filter);
+}
+
+pub(crate) fn eval_filter_recursively<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    vars: StarkEvaluationTargets<D>,
+    yield_constr: &mut RecursiveConstraintConsumer<F, D>,
+) {
+    let one_ext = builder.one_extension();
+    // The filter must be 0 or 1.
+    let filter = vars.local_values[REG_FILTER];
+    let constraint = builder.mul_sub_extension(filter, filter, filter);
+    yield_constr.constraint(builder, constraint);
+
+    // If this is not the final step, the filter must be off.
+    let final_step = vars.local_values[reg_step(NUM_ROUNDS - 1)];
+    let not_final_step = builder.sub_extension(one_ext, final_step);
+    let constraint = builder.mul_extension(not_final_step, filter);
+    yield_constr.constraint(builder, constraint);
+}
+
 #[cfg(test)]
 mod tests {
-    use std::time::Instant;
-
     use anyhow::Result;
     use itertools::Itertools;
     use plonky2::iop::witness::PartialWitness;
@@ -271,9 +289,9 @@ mod tests {
     use plonky2::plonk::circuit_data::CircuitConfig;
     use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
     use plonky2::util::timing::TimingTree;
-    use tiny_keccak::keccakf;
 
-    use crate::keccak_stark::{KeccakStark, NUM_INPUTS};
+    use crate::keccak_stark::KeccakStark;
+    use crate::types::KeccakState;
     use starky::prover::prove;
     use starky::recursive_verifier::{
         add_virtual_stark_proof_with_pis, set_stark_proof_with_pis_target,
@@ -286,20 +304,10 @@ mod tests {
         let num_io = 256;
 
         let inputs = (0..num_io)
-            .map(|_| {
-                let r: [u64; NUM_INPUTS] = rand::random();
-                r
-            })
+            .map(|_| KeccakState(rand::random()))
             .collect_vec();
 
-        let outputs = inputs
-            .iter()
-            .map(|&input| {
-                let mut state = input;
-                keccakf(&mut state);
-                state
-            })
-            .collect_vec();
+        let outputs = inputs.iter().map(|input| input.permute()).collect_vec();
 
         const D: usize = 2;
         type C = PoseidonGoldilocksConfig;
@@ -307,7 +315,6 @@ mod tests {
         type S = KeccakStark<F, D>;
 
         let stark = S::new(num_io);
-        let now = Instant::now();
         let inner_config = stark.config();
         let trace = stark.generate_trace(inputs.clone(), 8);
         let public_inputs = stark.generate_public_inputs(inputs, outputs);
@@ -318,7 +325,6 @@ mod tests {
             public_inputs,
             &mut TimingTree::default(),
         )?;
-        println!("Stark proving time: {:?}", now.elapsed());
         verify_stark_proof(stark, inner_proof.clone(), &inner_config)?;
 
         let circuit_config = CircuitConfig::standard_recursion_config();
@@ -329,9 +335,7 @@ mod tests {
         set_stark_proof_with_pis_target(&mut pw, &pt, &inner_proof);
         verify_stark_proof_circuit::<F, C, S, D>(&mut builder, stark, &pt, &inner_config);
         let data = builder.build::<C>();
-        let now = Instant::now();
         let proof = data.prove(pw)?;
-        println!("Circuit proving time: {:?}", now.elapsed());
         data.verify(proof)?;
 
         Ok(())
@@ -0,0 +1,235 @@
+//! A keccak256 Fiat–Shamir transcript, natively and in a circuit.
+//!
+//! The transcript is the hash chain Solidity verifiers use: each squeeze hashes the previous
+//! challenge digest, if any, followed by every byte absorbed since, and the digest becomes the
+//! new state. Field elements are absorbed as 8 big-endian bytes, as `abi.encodePacked(uint64)`,
+//! and a field challenge is the digest read as a big-endian `uint256`, reduced modulo the field
+//! order. `KeccakTranscript` and `KeccakTranscriptTarget` agree on every challenge; the
+//! permutations of the circuit transcript are proven by verifying its `statements` with
+//! `verify_keccak_statements_circuit`.
+
+use itertools::Itertools;
+use plonky2::{
+    field::{
+        extension::Extendable,
+        types::{Field, PrimeField64},
+    },
+    hash::hash_types::RichField,
+    iop::target::Target,
+    plonk::circuit_builder::CircuitBuilder,
+};
+use plonky2_u32::gadgets::arithmetic_u32::U32Target;
+use primitive_types::U256;
+
+use crate::keccak256_circuit::{bytes_to_words, keccak256_circuit_inner, keccak256_with_byte_len};
+use crate::types::{Digest256, Digest256Target, KeccakState, KeccakStateTarget};
+use crate::u32_keccak256_circuit::{bytes_to_u32_words, digest_to_u32_targets, u32_words_to_bytes};
+
+#[derive(Clone, Debug, Default)]
+pub struct KeccakTranscript {
+    state: Option<Digest256>,
+    pending: Vec<u8>,
+    statements: Vec<(KeccakState, KeccakState)>,
+}
+
+impl KeccakTranscript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn absorb_bytes(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+    }
+
+    pub fn absorb_field<F: PrimeField64>(&mut self, x: F) {
+        self.absorb_bytes(&x.to_canonical_u64().to_be_bytes());
+    }
+
+    pub fn squeeze_digest(&mut self) -> Digest256 {
+        let mut message = self.state.map(|state| state.0.to_vec()).unwrap_or_default();
+        message.append(&mut self.pending);
+        let (digest, statements) = keccak256_with_byte_len(bytes_to_words(&message), message.len());
+        self.statements.extend(statements);
+        self.state = Some(digest);
+        digest
+    }
+
+    pub fn squeeze_challenge<F: PrimeField64>(&mut self) -> F {
+        let digest = self.squeeze_digest();
+        let reduced = digest.to_u256() % U256::from(F::ORDER);
+        F::from_canonical_u64(reduced.low_u64())
+    }
+
+    /// The permutations of all squeezes so far, for `prove_keccak_statements`.
+    pub fn statements(&self) -> &[(KeccakState, KeccakState)] {
+        &self.statements
+    }
+}
+
+/// The circuit version of `KeccakTranscript`. Absorbed bytes are range-checked to 8 bits and
+/// absorbed field elements are decomposed canonically.
+#[derive(Clone, Debug, Default)]
+pub struct KeccakTranscriptTarget {
+    state: Option<[Target; 32]>,
+    pending: Vec<Target>,
+    statements: Vec<(KeccakStateTarget, KeccakStateTarget)>,
+}
+
+impl KeccakTranscriptTarget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn absorb_bytes<F: RichField + Extendable<D>, const D: usize>(
+        &mut self,
+        builder: &mut CircuitBuilder<F, D>,
+        bytes: &[Target],
+    ) {
+        for &byte in bytes {
+            builder.range_check(byte, 8);
+        }
+        self.pending.extend_from_slice(bytes);
+    }
+
+    pub fn absorb_field<F: RichField + Extendable<D>, const D: usize>(
+        &mut self,
+        builder: &mut CircuitBuilder<F, D>,
+        x: Target,
+    ) {
+        let (lo, hi) = builder.split_low_high(x, 32, 64);
+        // `lo + 2^32 hi` is canonical unless it is at least the order `2^64 - 2^32 + 1`, i.e.
+        // unless `hi = 2^32 - 1` and `lo > 0`.
+        let max = builder.constant(F::from_canonical_u32(u32::MAX));
+        let hi_is_max = builder.is_equal(hi, max);
+        let overflow = builder.mul(hi_is_max.target, lo);
+        builder.assert_zero(overflow);
+        let mut bytes = u32_words_to_bytes(builder, &[U32Target(lo), U32Target(hi)]);
+        bytes.reverse();
+        self.pending.extend(bytes);
+    }
+
+    pub fn squeeze_digest<F: RichField + Extendable<D>, const D: usize>(
+        &mut self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Digest256Target {
+        let mut message = self.state.map(|state| state.to_vec()).unwrap_or_default();
+        message.append(&mut self.pending);
+        let words = bytes_to_u32_words(builder, &message)
+            .into_iter()
+            .map(|word| word.0)
+            .collect_vec();
+        // All bytes are range-checked, so a partial last word has no stray high bytes.
+        let (digest, statements) = keccak256_circuit_inner(builder, words, message.len(), false);
+        self.statements.extend(statements);
+        let digest_bytes = u32_words_to_bytes(builder, &digest_to_u32_targets(digest));
+        self.state = Some(digest_bytes.try_into().unwrap());
+        digest
+    }
+
+    pub fn squeeze_challenge<F: RichField + Extendable<D>, const D: usize>(
+        &mut self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Target {
+        self.squeeze_digest(builder);
+        let zero = builder.zero();
+        let base = F::from_canonical_u32(1 << 8);
+        self.state
+            .unwrap()
+            .iter()
+            .fold(zero, |acc, &byte| builder.mul_const_add(base, acc, byte))
+    }
+
+    /// The permutations of all squeezes so far, for `verify_keccak_statements_circuit`.
+    pub fn statements(&self) -> &[(KeccakStateTarget, KeccakStateTarget)] {
+        &self.statements
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::{Field, PrimeField64};
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::CircuitConfig,
+        config::{GenericConfig, PoseidonGoldilocksConfig},
+    };
+    use starky::recursive_verifier::set_stark_proof_with_pis_target;
+
+    use crate::keccak256_circuit::{
+        keccak256_bytes, prove_keccak_statements, verify_keccak_statements_circuit,
+    };
+    use crate::keccak_transcript::{KeccakTranscript, KeccakTranscriptTarget};
+    use crate::types::Digest256Target;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_keccak_transcript() {
+        let mut transcript = KeccakTranscript::new();
+        transcript.absorb_bytes(b"abc");
+        let first = transcript.squeeze_digest();
+        assert_eq!(first, keccak256_bytes(b"abc"));
+        transcript.absorb_field(F::NEG_ONE);
+        let second = transcript.squeeze_digest();
+        let message = [&first.0[..], &F::NEG_ONE.to_canonical_u64().to_be_bytes()].concat();
+        assert_eq!(second, keccak256_bytes(&message));
+        let third = transcript.squeeze_challenge::<F>();
+        let digest = keccak256_bytes(&second.0);
+        let expected = (digest.to_u256() % F::ORDER).low_u64();
+        assert_eq!(third, F::from_canonical_u64(expected));
+    }
+
+    #[test]
+    fn test_keccak_transcript_circuit() {
+        let message: Vec<u8> = (0..150).map(|_| rand::random()).collect();
+        let x = F::from_canonical_u64(rand::random::<u64>() % F::ORDER);
+
+        let mut transcript = KeccakTranscript::new();
+        transcript.absorb_bytes(&message);
+        transcript.absorb_field(x);
+        let alpha = transcript.squeeze_challenge::<F>();
+        let beta = transcript.squeeze_challenge::<F>();
+        transcript.absorb_field(alpha);
+        let digest = transcript.squeeze_digest();
+
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let message_t = builder.add_virtual_targets(message.len());
+        let x_t = builder.add_virtual_target();
+        let mut transcript_t = KeccakTranscriptTarget::new();
+        transcript_t.absorb_bytes(&mut builder, &message_t);
+        transcript_t.absorb_field(&mut builder, x_t);
+        let alpha_t = transcript_t.squeeze_challenge(&mut builder);
+        let beta_t = transcript_t.squeeze_challenge(&mut builder);
+        transcript_t.absorb_field(&mut builder, alpha_t);
+        let digest_t = transcript_t.squeeze_digest(&mut builder);
+        assert_eq!(
+            transcript_t.statements().len(),
+            transcript.statements().len()
+        );
+        let stark_proof_t =
+            verify_keccak_statements_circuit(&mut builder, transcript_t.statements());
+        for (challenge_t, challenge) in [(alpha_t, alpha), (beta_t, beta)] {
+            let expected = builder.constant(challenge);
+            builder.connect(challenge_t, expected);
+        }
+        let expected = Digest256Target::constant(&mut builder, digest);
+        Digest256Target::connect(&mut builder, digest_t, expected);
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        for (&byte_t, &byte) in message_t.iter().zip(message.iter()) {
+            pw.set_target(byte_t, F::from_canonical_u8(byte));
+        }
+        pw.set_target(x_t, x);
+        set_stark_proof_with_pis_target(
+            &mut pw,
+            &stark_proof_t,
+            &prove_keccak_statements(transcript.statements()),
+        );
+        data.verify(data.prove(pw).unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,378 @@
+//! A public description of the columns of `KeccakStark`, and a CSV dumper for its traces.
+//!
+//! The register functions of `columns.rs` and `pulse.rs` remain the source of truth: the layout
+//! is built by calling them, so the two cannot drift apart.
+
+use std::io::Write;
+use std::ops::Range;
+
+use anyhow::{ensure, Result};
+use itertools::Itertools;
+use plonky2::field::{polynomial::PolynomialValues, types::PrimeField64};
+
+use crate::columns::{
+    reg_a, reg_a_prime, reg_a_prime_prime, reg_a_prime_prime_0_0_bit, reg_a_prime_prime_prime,
+    reg_c, reg_c_prime, reg_step, NUM_COLUMNS, REG_FILTER,
+};
+use crate::keccak_stark::NUM_ROUNDS;
+use crate::pulse::{get_pulse_col, get_witness_col};
+use crate::utils::gen_keccak_pulse_positions;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColumnGroup {
+    /// One flag per round, set on the rows of that round.
+    RoundFlags,
+    /// Set on the last round of each permutation.
+    Filter,
+    /// The input state `A` of the round, as 32-bit limbs.
+    A,
+    /// `C[x, z]`, the parity of column `x` at bit `z`.
+    C,
+    /// `C'[x, z] = C[x, z] ^ C[x - 1, z] ^ C[x + 1, z - 1]`.
+    CPrime,
+    /// `A'` after theta, as bits. `B` is a rotation of `A'` and has no columns of its own.
+    APrime,
+    /// `A''` after chi, as 32-bit limbs. Except at `(0, 0)` it is also the round output `A'''`.
+    APrimePrime,
+    /// The bits of `A''[0, 0]`.
+    APrimePrime00Bits,
+    /// `A'''[0, 0] = A''[0, 0] ^ RC`, as 32-bit limbs.
+    APrimePrimePrime00,
+    /// The row counter the pulses are computed from.
+    PulseCounter,
+    /// The inverse of `counter - position`, or 0 at the position.
+    PulseWitness,
+    /// 1 at the pulse position and 0 elsewhere.
+    Pulse,
+}
+
+impl ColumnGroup {
+    pub const ALL: [ColumnGroup; 12] = [
+        ColumnGroup::RoundFlags,
+        ColumnGroup::Filter,
+        ColumnGroup::A,
+        ColumnGroup::C,
+        ColumnGroup::CPrime,
+        ColumnGroup::APrime,
+        ColumnGroup::APrimePrime,
+        ColumnGroup::APrimePrime00Bits,
+        ColumnGroup::APrimePrimePrime00,
+        ColumnGroup::PulseCounter,
+        ColumnGroup::PulseWitness,
+        ColumnGroup::Pulse,
+    ];
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Column {
+    pub index: usize,
+    pub name: String,
+    pub group: ColumnGroup,
+    /// The lane the column belongs to, if any. For `C` and `C'` only `x` is set.
+    pub x: Option<usize>,
+    pub y: Option<usize>,
+    /// The bit of the lane held by a single-bit column.
+    pub z: Option<usize>,
+    /// For 32-bit limb columns, 0 for bits 0..32 of the lane and 1 for bits 32..64.
+    pub limb: Option<usize>,
+    pub meaning: String,
+}
+
+impl Column {
+    fn new(index: usize, name: String, group: ColumnGroup, meaning: String) -> Self {
+        Self {
+            index,
+            name,
+            group,
+            x: None,
+            y: None,
+            z: None,
+            limb: None,
+            meaning,
+        }
+    }
+
+    fn lane(mut self, x: usize, y: Option<usize>) -> Self {
+        self.x = Some(x);
+        self.y = y;
+        self
+    }
+
+    fn bit(mut self, z: usize) -> Self {
+        self.z = Some(z);
+        self
+    }
+
+    fn limb(mut self, limb: usize) -> Self {
+        self.limb = Some(limb);
+        self
+    }
+}
+
+const LIMB_NAMES: [&str; 2] = ["lo", "hi"];
+const LIMB_BITS: [&str; 2] = ["0..32", "32..64"];
+
+fn limb_columns(
+    columns: &mut Vec<Column>,
+    group: ColumnGroup,
+    prefix: &str,
+    label: &str,
+    x: usize,
+    y: usize,
+    start: usize,
+) {
+    for limb in 0..2 {
+        columns.push(
+            Column::new(
+                start + limb,
+                format!("{}[{}][{}].{}", prefix, x, y, LIMB_NAMES[limb]),
+                group,
+                format!("bits {} of {}[{}, {}]", LIMB_BITS[limb], label, x, y),
+            )
+            .lane(x, Some(y))
+            .limb(limb),
+        );
+    }
+}
+
+/// Every column of `KeccakStark::new(num_io)`, ordered by index.
+pub fn keccak_stark_layout(num_io: usize) -> Vec<Column> {
+    let mut columns = vec![];
+    for round in 0..NUM_ROUNDS {
+        columns.push(Column::new(
+            reg_step(round),
+            format!("round_flag[{}]", round),
+            ColumnGroup::RoundFlags,
+            format!("1 on the rows of round {}", round),
+        ));
+    }
+    columns.push(Column::new(
+        REG_FILTER,
+        "filter".to_string(),
+        ColumnGroup::Filter,
+        "1 on the last round of each permutation".to_string(),
+    ));
+    for x in 0..5 {
+        for y in 0..5 {
+            limb_columns(&mut columns, ColumnGroup::A, "a", "A", x, y, reg_a(x, y));
+        }
+    }
+    for x in 0..5 {
+        for z in 0..64 {
+            columns.push(
+                Column::new(
+                    reg_c(x, z),
+                    format!("c[{}][{}]", x, z),
+                    ColumnGroup::C,
+                    format!("bit {} of the xor of A[{}, 0..5]", z, x),
+                )
+                .lane(x, None)
+                .bit(z),
+            );
+        }
+    }
+    for x in 0..5 {
+        for z in 0..64 {
+            columns.push(
+                Column::new(
+                    reg_c_prime(x, z),
+                    format!("c_prime[{}][{}]", x, z),
+                    ColumnGroup::CPrime,
+                    format!(
+                        "C[{}, {}] ^ C[{}, {}] ^ C[{}, {}]",
+                        x,
+                        z,
+                        (x + 4) % 5,
+                        z,
+                        (x + 1) % 5,
+                        (z + 63) % 64
+                    ),
+                )
+                .lane(x, None)
+                .bit(z),
+            );
+        }
+    }
+    for x in 0..5 {
+        for y in 0..5 {
+            for z in 0..64 {
+                columns.push(
+                    Column::new(
+                        reg_a_prime(x, y, z),
+                        format!("a_prime[{}][{}][{}]", x, y, z),
+                        ColumnGroup::APrime,
+                        format!("bit {} of A[{}, {}] after theta", z, x, y),
+                    )
+                    .lane(x, Some(y))
+                    .bit(z),
+                );
+            }
+        }
+    }
+    for x in 0..5 {
+        for y in 0..5 {
+            let label = if (x, y) == (0, 0) {
+                "A''"
+            } else {
+                "A'' = A'''"
+            };
+            limb_columns(
+                &mut columns,
+                ColumnGroup::APrimePrime,
+                "a_prime_prime",
+                label,
+                x,
+                y,
+                reg_a_prime_prime(x, y),
+            );
+        }
+    }
+    for z in 0..64 {
+        columns.push(
+            Column::new(
+                reg_a_prime_prime_0_0_bit(z),
+                format!("a_prime_prime_0_0_bit[{}]", z),
+                ColumnGroup::APrimePrime00Bits,
+                format!("bit {} of A''[0, 0]", z),
+            )
+            .lane(0, Some(0))
+            .bit(z),
+        );
+    }
+    limb_columns(
+        &mut columns,
+        ColumnGroup::APrimePrimePrime00,
+        "a_prime_prime_prime",
+        "A'''",
+        0,
+        0,
+        reg_a_prime_prime_prime(0, 0),
+    );
+
+    columns.push(Column::new(
+        NUM_COLUMNS,
+        "pulse_counter".to_string(),
+        ColumnGroup::PulseCounter,
+        "the row index".to_string(),
+    ));
+    for (i, pos) in gen_keccak_pulse_positions(num_io).into_iter().enumerate() {
+        let what = if i % 2 == 0 { "input" } else { "output" };
+        columns.push(Column::new(
+            get_witness_col(NUM_COLUMNS, i),
+            format!("pulse_witness[{}]", i),
+            ColumnGroup::PulseWitness,
+            format!("1 / (counter - {}), or 0 on row {}", pos, pos),
+        ));
+        columns.push(Column::new(
+            get_pulse_col(NUM_COLUMNS, i),
+            format!("pulse[{}]", i),
+            ColumnGroup::Pulse,
+            format!(
+                "1 on row {}, where the {} of permutation {} is read",
+                pos,
+                what,
+                i / 2
+            ),
+        ));
+    }
+    columns.sort_by_key(|column| column.index);
+    columns
+}
+
+/// Writes rows `rows` of `trace`, restricted to the columns of `layout` in `groups`, as CSV. The
+/// header holds `row` and the column names; values are canonical integers.
+pub fn write_trace_csv<F: PrimeField64, W: Write>(
+    writer: &mut W,
+    trace: &[PolynomialValues<F>],
+    layout: &[Column],
+    rows: Range<usize>,
+    groups: &[ColumnGroup],
+) -> Result<()> {
+    ensure!(
+        trace.len() == layout.len(),
+        "the trace has {} columns but the layout {}",
+        trace.len(),
+        layout.len()
+    );
+    ensure!(
+        trace.iter().all(|col| rows.end <= col.len()),
+        "rows {:?} are out of the trace",
+        rows
+    );
+    let columns = layout
+        .iter()
+        .filter(|column| groups.contains(&column.group))
+        .collect_vec();
+    writeln!(
+        writer,
+        "row,{}",
+        columns.iter().map(|column| &column.name).join(",")
+    )?;
+    for row in rows {
+        writeln!(
+            writer,
+            "{},{}",
+            row,
+            columns
+                .iter()
+                .map(|column| trace[column.index].values[row].to_canonical_u64())
+                .join(",")
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+
+    use crate::columns::{reg_a_prime, reg_c_prime};
+    use crate::keccak_stark::KeccakStark;
+    use crate::layout::{keccak_stark_layout, write_trace_csv, ColumnGroup};
+    use crate::types::KeccakState;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_layout_covers_every_column() {
+        let num_io = 3;
+        let stark = KeccakStark::<F, D>::new(num_io);
+        let inputs = (0..num_io).map(|_| KeccakState(rand::random())).collect();
+        let trace = stark.generate_trace(inputs, 8);
+        let layout = keccak_stark_layout(num_io);
+        assert_eq!(layout.len(), trace.len());
+        for (i, column) in layout.iter().enumerate() {
+            assert_eq!(column.index, i);
+        }
+        let column = &layout[reg_a_prime(2, 3, 17)];
+        assert_eq!(column.group, ColumnGroup::APrime);
+        assert_eq!((column.x, column.y, column.z), (Some(2), Some(3), Some(17)));
+        assert_eq!(layout[reg_c_prime(4, 0)].name, "c_prime[4][0]");
+    }
+
+    #[test]
+    fn test_write_trace_csv() {
+        let stark = KeccakStark::<F, D>::new(1);
+        let input = KeccakState(rand::random());
+        let trace = stark.generate_trace(vec![input], 8);
+        let layout = keccak_stark_layout(1);
+
+        let mut csv = vec![];
+        write_trace_csv(
+            &mut csv,
+            &trace,
+            &layout,
+            0..2,
+            &[ColumnGroup::RoundFlags, ColumnGroup::Filter],
+        )
+        .unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("row,round_flag[0],round_flag[1],"));
+        assert!(lines[0].ends_with(",round_flag[23],filter"));
+        assert!(lines[2].starts_with("1,0,1,0,"));
+    }
+}
@@ -1,13 +1,67 @@
 #![allow(incomplete_features)]
 #![feature(generic_const_exprs)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+// Without `std` only the verifier path is built: the starks and their constraints, the decoding
+// of keccak256 public inputs and the keccak256 proof verifier.
+
+#[cfg(feature = "std")]
+pub mod aggregation_circuit;
 pub mod columns;
 pub mod constants;
+#[cfg(feature = "std")]
+pub mod constraint_checker;
+#[cfg(feature = "std")]
+pub mod cost_estimate;
+#[cfg(feature = "std")]
+pub mod cyclic_keccak256_circuit;
+#[cfg(feature = "std")]
+pub mod eip712;
+#[cfg(feature = "std")]
+pub mod eth_transaction;
+#[cfg(feature = "std")]
+pub mod hash_chain_circuit;
+#[cfg(test)]
+mod kat;
+#[cfg(feature = "std")]
 pub mod keccak256_circuit;
+pub mod keccak256_verifier;
+pub mod keccak_chain_stark;
 pub mod keccak_constraints;
+#[cfg(feature = "std")]
+pub mod keccak_prover;
 pub mod keccak_stark;
+#[cfg(feature = "std")]
+pub mod keccak_transcript;
+#[cfg(feature = "std")]
+pub mod layout;
 pub mod logic;
+#[cfg(feature = "std")]
 pub mod multi_keccak256_circuit;
+#[cfg(feature = "std")]
+pub mod proof_container;
+#[cfg(feature = "std")]
+pub mod proving_stats;
+pub mod public_inputs;
 pub mod pulse;
+#[cfg(feature = "std")]
+pub mod rlp;
 pub mod round_flags;
+pub mod serialization;
+#[cfg(feature = "std")]
+pub mod sharded_keccak256_circuit;
+#[cfg(test)]
+mod soundness_tests;
+#[cfg(feature = "std")]
+pub mod stark_testing;
+#[cfg(feature = "std")]
+pub mod storage_slot;
+pub mod types;
+#[cfg(feature = "std")]
+pub mod u32_keccak256_circuit;
 pub mod utils;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod xor_gate;
@@ -1,8 +1,11 @@
+use std::time::Instant;
+
+use anyhow::Result;
 use itertools::Itertools;
 use plonky2::{
     field::extension::Extendable,
     field::types::Field,
-    hash::hash_types::RichField,
+    hash::{hash_types::RichField, poseidon::PoseidonHash},
     iop::{
         target::Target,
         witness::{PartialWitness, WitnessWrite},
@@ -10,49 +13,77 @@ use plonky2::{
     plonk::{
         circuit_builder::CircuitBuilder,
         circuit_data::{CircuitConfig, CircuitData},
-        config::{GenericConfig, PoseidonGoldilocksConfig},
+        config::{GenericConfig, Hasher, PoseidonGoldilocksConfig},
         proof::ProofWithPublicInputs,
     },
-    util::timing::TimingTree,
 };
 use starky::{
-    proof::StarkProofWithPublicInputsTarget,
-    prover::prove,
-    recursive_verifier::{
-        add_virtual_stark_proof_with_pis, set_stark_proof_with_pis_target,
-        verify_stark_proof_circuit,
-    },
-    verifier::verify_stark_proof,
+    proof::StarkProofWithPublicInputsTarget, recursive_verifier::set_stark_proof_with_pis_target,
 };
 
-use crate::{
-    keccak256_circuit::{keccak256, keccak256_circuit_with_statements},
-    keccak_stark::{KeccakStark, NUM_INPUTS, NUM_ROUNDS},
+use crate::keccak256_circuit::{
+    keccak256, keccak256_circuit_with_byte_len, keccak256_circuit_with_statements,
+    keccak256_with_byte_len, prove_keccak_statements_with_stats, verify_keccak_statements_circuit,
 };
+use crate::proving_stats::ProvingStats;
+use crate::serialization::{circuit_data_from_bytes, circuit_data_to_bytes};
+use crate::types::{Digest256, Digest256Target, KeccakState, KeccakStateTarget};
 
-pub fn multi_keccak256(inputs: Vec<Vec<u32>>) -> (Vec<[u32; 8]>, Vec<u32>) {
+pub fn multi_keccak256(inputs: Vec<Vec<u32>>) -> (Vec<Digest256>, Vec<(KeccakState, KeccakState)>) {
+    let input_num_bytes = inputs.iter().map(|input| 4 * input.len()).collect_vec();
+    multi_keccak256_with_byte_lens(inputs, &input_num_bytes)
+}
+
+/// `multi_keccak256` for messages of the given byte lengths. See `keccak256_with_byte_len`.
+pub fn multi_keccak256_with_byte_lens(
+    inputs: Vec<Vec<u32>>,
+    input_num_bytes: &[usize],
+) -> (Vec<Digest256>, Vec<(KeccakState, KeccakState)>) {
+    assert!(inputs.len() == input_num_bytes.len());
     let mut outputs = vec![];
-    let mut pi = vec![];
-    for input in inputs {
-        let (output, input_pi) = keccak256(input);
+    let mut statements = vec![];
+    for (input, &num_bytes) in inputs.into_iter().zip(input_num_bytes.iter()) {
+        let (output, input_statements) = keccak256_with_byte_len(input, num_bytes);
         outputs.push(output);
-        pi.extend(input_pi);
+        statements.extend(input_statements);
     }
-    (outputs, pi)
+    (outputs, statements)
 }
 
 pub fn multi_keccak256_circuit_with_statements<F: RichField + Extendable<D>, const D: usize>(
     builder: &mut CircuitBuilder<F, D>,
     inputs: Vec<Vec<Target>>,
-) -> (Vec<[Target; 8]>, Vec<Target>) {
-    let mut pi: Vec<Target> = vec![];
+) -> (
+    Vec<Digest256Target>,
+    Vec<(KeccakStateTarget, KeccakStateTarget)>,
+) {
+    let mut statements = vec![];
     let mut outputs = vec![];
     for input in inputs {
-        let (output, pi_t) = keccak256_circuit_with_statements(builder, input);
+        let (output, statements_t) = keccak256_circuit_with_statements(builder, input);
         outputs.push(output);
-        pi.extend(pi_t);
+        statements.extend(statements_t);
     }
-    (outputs, pi)
+    (outputs, statements)
+}
+
+pub fn multi_keccak256_circuit_with_byte_lens<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    inputs: Vec<Vec<Target>>,
+    input_num_bytes: &[usize],
+) -> (
+    Vec<Digest256Target>,
+    Vec<(KeccakStateTarget, KeccakStateTarget)>,
+) {
+    assert!(inputs.len() == input_num_bytes.len());
+    let mut statements = vec![];
+    let mut outputs = vec![];
+    for (input, &num_bytes) in inputs.into_iter().zip(input_num_bytes.iter()) {
+        let (output, statements_t) = keccak256_circuit_with_byte_len(builder, input, num_bytes);
+        outputs.push(output);
+        statements.extend(statements_t);
+    }
+    (outputs, statements)
 }
 
 pub struct MultiKeccak256Circuit<F, C, const D: usize>
@@ -61,9 +92,46 @@ where
     C: GenericConfig<D, F = F>,
 {
     pub data: CircuitData<F, C, D>,
+    pub commitment: Option<Commitment>,
+    pub input_num_bytes: Vec<usize>,
     pub stark_proof_t: StarkProofWithPublicInputsTarget<D>,
     pub inputs_t: Vec<Vec<Target>>,
-    pub outputs_t: Vec<[Target; 8]>,
+    pub outputs_t: Vec<Digest256Target>,
+}
+
+/// A hash that replaces the message and digest words in the public inputs of a multi-message
+/// circuit. Both hash the words of every message followed by its digest, in order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Commitment {
+    /// `PoseidonHash::hash_no_pad` over the words as field elements, 4 public inputs.
+    Poseidon,
+    /// keccak256 over the words, 8 public inputs. The hash is proven by the same `KeccakStark`
+    /// proof as the messages.
+    Keccak,
+}
+
+/// Recomputes the public inputs of a proof with the given commitment from the messages and their
+/// digests. This is what a verifier holding the raw data compares against `proof.public_inputs`.
+pub fn inputs_outputs_commitment<F: RichField>(
+    inputs: &[Vec<u32>],
+    outputs: &[Digest256],
+    commitment: Commitment,
+) -> Vec<F> {
+    let words = inputs
+        .iter()
+        .zip(outputs.iter())
+        .flat_map(|(input, output)| [input.as_slice(), &output.words()].concat())
+        .collect_vec();
+    match commitment {
+        Commitment::Poseidon => {
+            let elements = words.into_iter().map(F::from_canonical_u32).collect_vec();
+            PoseidonHash::hash_no_pad(&elements).elements.to_vec()
+        }
+        Commitment::Keccak => {
+            let (output, _statements) = keccak256(words);
+            output.to_field_elements().to_vec()
+        }
+    }
 }
 
 const D: usize = 2;
@@ -71,84 +139,144 @@ type C = PoseidonGoldilocksConfig;
 type F = <C as GenericConfig<D>>::F;
 
 pub fn build_multi_keccak256_circuit(input_lens: Vec<usize>) -> MultiKeccak256Circuit<F, C, D> {
-    let block_size = 136 / 4;
-    let num_perms: usize = input_lens
-        .iter()
-        .map(|input_len| input_len / block_size + 1)
-        .sum();
-    let degree_bits = (NUM_ROUNDS * num_perms)
-        .next_power_of_two()
-        .trailing_zeros() as usize;
-    type S = KeccakStark<F, D>;
-    let stark = S::new(num_perms);
-    let inner_config = stark.config();
+    let input_num_bytes = input_lens.iter().map(|&len| 4 * len).collect();
+    build_multi_keccak256_circuit_inner(input_num_bytes, None)
+}
+
+/// Builds a multi-message circuit for messages of the given byte lengths. The messages are given
+/// to the prover as `bytes_to_words` of their bytes.
+pub fn build_multi_keccak256_bytes_circuit(
+    input_num_bytes: Vec<usize>,
+) -> MultiKeccak256Circuit<F, C, D> {
+    build_multi_keccak256_circuit_inner(input_num_bytes, None)
+}
+
+/// Builds a multi-message circuit whose only public inputs are the given commitment to the
+/// messages and digests. See `inputs_outputs_commitment`.
+pub fn build_multi_keccak256_circuit_with_commitment(
+    input_lens: Vec<usize>,
+    commitment: Commitment,
+) -> MultiKeccak256Circuit<F, C, D> {
+    let input_num_bytes = input_lens.iter().map(|&len| 4 * len).collect();
+    build_multi_keccak256_circuit_inner(input_num_bytes, Some(commitment))
+}
+
+fn build_multi_keccak256_circuit_inner(
+    input_num_bytes: Vec<usize>,
+    commitment: Option<Commitment>,
+) -> MultiKeccak256Circuit<F, C, D> {
+    multi_keccak256_circuit_with_data(input_num_bytes, commitment, |builder| {
+        Ok(builder.build::<C>())
+    })
+    .unwrap()
+}
+
+impl MultiKeccak256Circuit<F, C, D> {
+    /// Serializes the prover data with `KeccakGateSerializer` and `KeccakGeneratorSerializer`,
+    /// after a header with the message lengths, the commitment and the circuit digest. The
+    /// targets are not stored, see `from_bytes`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let params = Self::params(&self.input_num_bytes, self.commitment);
+        circuit_data_to_bytes(&self.data, &params)
+    }
+
+    /// Deserializes a circuit written by `to_bytes` for messages of the given byte lengths,
+    /// failing if it was written for other lengths or another commitment, or by another version
+    /// of the crate. The targets are recovered by constructing the circuit again without building
+    /// it, which allocates them in the same order.
+    pub fn from_bytes(
+        bytes: &[u8],
+        input_num_bytes: Vec<usize>,
+        commitment: Option<Commitment>,
+    ) -> Result<Self> {
+        let params = Self::params(&input_num_bytes, commitment);
+        multi_keccak256_circuit_with_data(input_num_bytes, commitment, |_| {
+            circuit_data_from_bytes(bytes, &params)
+        })
+    }
+
+    fn params(input_num_bytes: &[usize], commitment: Option<Commitment>) -> Vec<u64> {
+        let commitment = match commitment {
+            None => 0,
+            Some(Commitment::Poseidon) => 1,
+            Some(Commitment::Keccak) => 2,
+        };
+        [commitment]
+            .into_iter()
+            .chain(input_num_bytes.iter().map(|&num_bytes| num_bytes as u64))
+            .collect()
+    }
+}
+
+/// Constructs the circuit of `build_multi_keccak256_circuit_inner` and gets its data from `data`,
+/// given the finished builder.
+fn multi_keccak256_circuit_with_data(
+    input_num_bytes: Vec<usize>,
+    commitment: Option<Commitment>,
+    data: impl FnOnce(CircuitBuilder<F, D>) -> Result<CircuitData<F, C, D>>,
+) -> Result<MultiKeccak256Circuit<F, C, D>> {
     let config = CircuitConfig::standard_recursion_config();
     let mut builder = CircuitBuilder::<F, D>::new(config);
-    let inputs_t = input_lens
+    let inputs_t = input_num_bytes
         .iter()
-        .map(|&input_len| builder.add_virtual_targets(input_len))
+        .map(|&num_bytes| builder.add_virtual_targets((num_bytes + 3) / 4))
         .collect_vec();
-    let (outputs_t, pi_t) = multi_keccak256_circuit_with_statements(&mut builder, inputs_t.clone());
-    let stark_proof_t =
-        add_virtual_stark_proof_with_pis(&mut builder, stark, &inner_config, degree_bits);
-    verify_stark_proof_circuit::<F, C, S, D>(&mut builder, stark, &stark_proof_t, &inner_config);
-    pi_t.iter()
-        .zip(stark_proof_t.public_inputs.iter())
-        .for_each(|(x, y)| {
-            builder.connect(*x, *y);
-        });
-
+    let (outputs_t, mut statements_t) =
+        multi_keccak256_circuit_with_byte_lens(&mut builder, inputs_t.clone(), &input_num_bytes);
     let inputs_and_outputs = inputs_t
         .iter()
         .zip(outputs_t.iter())
-        .flat_map(|(input_t, output_t)| [input_t.as_slice(), output_t].concat())
+        .flat_map(|(input_t, output_t)| [input_t.as_slice(), output_t.targets()].concat())
         .collect_vec();
-    builder.register_public_inputs(&inputs_and_outputs);
-    let data = builder.build::<C>();
+    let public_inputs = match commitment {
+        None => inputs_and_outputs,
+        Some(Commitment::Poseidon) => builder
+            .hash_n_to_hash_no_pad::<PoseidonHash>(inputs_and_outputs)
+            .elements
+            .to_vec(),
+        Some(Commitment::Keccak) => {
+            let (output_t, commitment_statements_t) =
+                keccak256_circuit_with_statements(&mut builder, inputs_and_outputs);
+            statements_t.extend(commitment_statements_t);
+            output_t.0.to_vec()
+        }
+    };
+    let stark_proof_t = verify_keccak_statements_circuit(&mut builder, &statements_t);
+    builder.register_public_inputs(&public_inputs);
 
-    MultiKeccak256Circuit {
-        data,
+    Ok(MultiKeccak256Circuit {
+        data: data(builder)?,
+        commitment,
+        input_num_bytes,
         stark_proof_t,
         inputs_t,
         outputs_t,
-    }
+    })
 }
 
 pub fn generate_multi_keccak256_proof(
     inputs: Vec<Vec<u32>>,
     circuit: &MultiKeccak256Circuit<F, C, D>,
 ) -> ProofWithPublicInputs<F, C, D> {
-    let block_size = 136 / 4;
-    let num_perms: usize = inputs
-        .iter()
-        .map(|input| input.len() / block_size + 1)
-        .sum();
-
-    let (outputs, pi) = multi_keccak256(inputs.clone());
-    let mut perm_inputs: Vec<[u64; NUM_INPUTS]> = vec![];
-    for i in 0..num_perms {
-        let perm_input = pi[i * 100..i * 100 + 50].to_vec();
-        let perm_input = perm_input
-            .chunks(2)
-            .map(|chunk| chunk[0] as u64 + ((chunk[1] as u64) << 32))
+    generate_multi_keccak256_proof_with_stats(inputs, circuit).0
+}
+
+pub fn generate_multi_keccak256_proof_with_stats(
+    inputs: Vec<Vec<u32>>,
+    circuit: &MultiKeccak256Circuit<F, C, D>,
+) -> (ProofWithPublicInputs<F, C, D>, ProvingStats) {
+    let (outputs, mut statements) =
+        multi_keccak256_with_byte_lens(inputs.clone(), &circuit.input_num_bytes);
+    if circuit.commitment == Some(Commitment::Keccak) {
+        let words = inputs
+            .iter()
+            .zip(outputs.iter())
+            .flat_map(|(input, output)| [input.as_slice(), &output.words()].concat())
             .collect_vec();
-        perm_inputs.push(perm_input.try_into().unwrap());
+        let (_output, commitment_statements) = keccak256(words);
+        statements.extend(commitment_statements);
     }
-
-    type S = KeccakStark<F, D>;
-    let stark = S::new(num_perms);
-    let inner_config = stark.config();
-    let trace = stark.generate_trace(perm_inputs, 8);
-    let pi = pi.iter().map(|x| F::from_canonical_u32(*x)).collect_vec();
-    let inner_proof = prove::<F, C, S, D>(
-        stark,
-        &inner_config,
-        trace,
-        pi.try_into().unwrap(),
-        &mut TimingTree::default(),
-    )
-    .unwrap();
-    verify_stark_proof(stark, inner_proof.clone(), &inner_config).unwrap();
+    let (inner_proof, mut stats) = prove_keccak_statements_with_stats(&statements);
 
     let mut pw = PartialWitness::new();
     set_stark_proof_with_pis_target(&mut pw, &circuit.stark_proof_t, &inner_proof);
@@ -166,26 +294,23 @@ pub fn generate_multi_keccak256_proof(
     outputs
         .iter()
         .zip(circuit.outputs_t.iter())
-        .for_each(|(output, output_t)| {
-            output
-                .iter()
-                .zip(output_t.iter())
-                .for_each(|(w, t)| pw.set_target(*t, F::from_canonical_u32(*w)))
-        });
+        .for_each(|(output, output_t)| output_t.set_witness(&mut pw, *output));
+    let now = Instant::now();
     let proof = circuit.data.prove(pw).unwrap();
-    proof
+    stats.finish(now.elapsed(), proof.to_bytes().len());
+    (proof, stats)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::time::Instant;
-
     use itertools::Itertools;
     use plonky2::field::{goldilocks_field::GoldilocksField, types::Field};
     use rand::Rng;
 
     use crate::multi_keccak256_circuit::{
-        build_multi_keccak256_circuit, generate_multi_keccak256_proof, multi_keccak256,
+        build_multi_keccak256_circuit, build_multi_keccak256_circuit_with_commitment,
+        generate_multi_keccak256_proof, generate_multi_keccak256_proof_with_stats,
+        inputs_outputs_commitment, multi_keccak256, Commitment, MultiKeccak256Circuit,
     };
 
     #[test]
@@ -196,23 +321,57 @@ mod tests {
         let mut rng = rand::thread_rng();
         let inputs: Vec<Vec<u32>> = input_lens
             .iter()
-            .map(|&input_len| vec![rng.gen(); input_len])
+            .map(|&input_len| (0..input_len).map(|_| rng.gen()).collect())
             .collect_vec();
 
-        let now = Instant::now();
-        let proof = generate_multi_keccak256_proof(inputs.clone(), &circuit);
-        println!("proof generation took {:?}", now.elapsed());
+        let (proof, stats) = generate_multi_keccak256_proof_with_stats(inputs.clone(), &circuit);
+        assert_eq!(stats.proof_size, proof.to_bytes().len());
 
         // assertion
         {
-            let (outputs, _pi) = multi_keccak256(inputs.clone());
+            let (outputs, _statements) = multi_keccak256(inputs.clone());
             let inputs_and_outputs = inputs
                 .iter()
                 .zip(outputs.iter())
-                .flat_map(|(input, output)| [input.as_slice(), output].concat())
+                .flat_map(|(input, output)| [input.as_slice(), &output.words()].concat())
                 .map(GoldilocksField::from_canonical_u32)
                 .collect_vec();
             assert!(proof.public_inputs == inputs_and_outputs);
         }
     }
+
+    #[test]
+    fn test_multi_keccak256_circuit_with_commitment() {
+        let input_lens: Vec<usize> = vec![40, 1, 20];
+        let mut rng = rand::thread_rng();
+        let inputs: Vec<Vec<u32>> = input_lens
+            .iter()
+            .map(|&input_len| (0..input_len).map(|_| rng.gen()).collect())
+            .collect_vec();
+        let (outputs, _statements) = multi_keccak256(inputs.clone());
+
+        for commitment in [Commitment::Poseidon, Commitment::Keccak] {
+            let circuit =
+                build_multi_keccak256_circuit_with_commitment(input_lens.clone(), commitment);
+            let proof = generate_multi_keccak256_proof(inputs.clone(), &circuit);
+            circuit.data.verify(proof.clone()).unwrap();
+
+            let expected =
+                inputs_outputs_commitment::<GoldilocksField>(&inputs, &outputs, commitment);
+            assert!(proof.public_inputs == expected);
+
+            let bytes = circuit.to_bytes().unwrap();
+            let input_num_bytes = circuit.input_num_bytes.clone();
+            let loaded =
+                MultiKeccak256Circuit::from_bytes(&bytes, input_num_bytes, Some(commitment))
+                    .unwrap();
+            let proof = generate_multi_keccak256_proof(inputs.clone(), &loaded);
+            circuit.data.verify(proof.clone()).unwrap();
+            assert!(proof.public_inputs == expected);
+            // Without the commitment it is another circuit.
+            let uncommitted =
+                MultiKeccak256Circuit::from_bytes(&bytes, circuit.input_num_bytes.clone(), None);
+            assert!(uncommitted.is_err());
+        }
+    }
 }
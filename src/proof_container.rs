@@ -0,0 +1,293 @@
+use anyhow::{anyhow, ensure, Result};
+use plonky2::{
+    field::types::PrimeField64,
+    hash::hash_types::HashOut,
+    plonk::{
+        circuit_data::VerifierCircuitData,
+        config::{GenericConfig, PoseidonGoldilocksConfig},
+        proof::ProofWithPublicInputs,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+/// Version of the container layout. Bump it on any incompatible change to `ProofContainer` or its
+/// binary encoding.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Identifies the plonky2 configuration the proofs of this crate are generated with.
+pub const CONFIG_ID: &str = "plonky2-poseidon-goldilocks-d2";
+
+const MAGIC: &[u8; 4] = b"SKPC";
+
+/// The sponge whose digests a proof attests to, with its parameters.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sponge {
+    pub name: String,
+    pub rate_bytes: u32,
+    pub capacity_bytes: u32,
+    pub output_bytes: u32,
+    /// The first padding byte, which also separates the domain: 0x01 for Keccak, 0x06 for SHA3.
+    pub domain_byte: u8,
+}
+
+impl Sponge {
+    pub fn keccak256() -> Self {
+        Self {
+            name: "keccak256".to_string(),
+            rate_bytes: 136,
+            capacity_bytes: 64,
+            output_bytes: 32,
+            domain_byte: 0x01,
+        }
+    }
+}
+
+/// A self-describing proof file. The proof is stored with `ProofWithPublicInputs::to_bytes`, and
+/// can only be loaded against the verifier data of the circuit with digest `circuit_digest`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofContainer {
+    pub format_version: u32,
+    pub crate_version: String,
+    pub config: String,
+    pub sponge: Sponge,
+    /// The byte length of each message, in the order of the public inputs.
+    pub message_lens: Vec<u32>,
+    /// The circuit digest of the verifier data, as canonical field elements.
+    pub circuit_digest: [u64; 4],
+    #[serde(with = "hex_bytes")]
+    pub proof: Vec<u8>,
+}
+
+fn digest_to_u64s(digest: HashOut<F>) -> [u64; 4] {
+    digest.elements.map(|x| x.to_canonical_u64())
+}
+
+impl ProofContainer {
+    pub fn new(
+        proof: &ProofWithPublicInputs<F, C, D>,
+        verifier_data: &VerifierCircuitData<F, C, D>,
+        message_lens: &[usize],
+    ) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            config: CONFIG_ID.to_string(),
+            sponge: Sponge::keccak256(),
+            message_lens: message_lens.iter().map(|&len| len as u32).collect(),
+            circuit_digest: digest_to_u64s(verifier_data.verifier_only.circuit_digest),
+            proof: proof.to_bytes(),
+        }
+    }
+
+    pub fn message_lens(&self) -> Vec<usize> {
+        self.message_lens.iter().map(|&len| len as usize).collect()
+    }
+
+    /// Decodes the proof for the given verifier. Fails if the container was written by an
+    /// incompatible version or for a different circuit.
+    pub fn load_proof(
+        &self,
+        verifier_data: &VerifierCircuitData<F, C, D>,
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        ensure!(
+            self.format_version == FORMAT_VERSION,
+            "unsupported format version {}",
+            self.format_version
+        );
+        ensure!(
+            self.config == CONFIG_ID,
+            "unsupported config {}",
+            self.config
+        );
+        ensure!(
+            self.sponge == Sponge::keccak256(),
+            "unsupported sponge {:?}",
+            self.sponge
+        );
+        ensure!(
+            self.circuit_digest == digest_to_u64s(verifier_data.verifier_only.circuit_digest),
+            "the proof was generated for a different circuit"
+        );
+        ProofWithPublicInputs::from_bytes(self.proof.clone(), &verifier_data.common)
+    }
+
+    /// Loads the proof for the given verifier and verifies it.
+    pub fn verify(
+        &self,
+        verifier_data: &VerifierCircuitData<F, C, D>,
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        let proof = self.load_proof(verifier_data)?;
+        verifier_data.verify(proof.clone())?;
+        Ok(proof)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// The compact encoding. All integers are little-endian, strings and byte strings are
+    /// prefixed by their u32 length:
+    ///
+    /// ```text
+    /// magic "SKPC" | format_version u32 | crate_version str | config str
+    /// | sponge name str | rate_bytes u32 | capacity_bytes u32 | output_bytes u32 | domain_byte u8
+    /// | message count u32 | message_lens u32* | circuit_digest u64 x 4 | proof bytes
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        let put_u32 = |out: &mut Vec<u8>, x: u32| out.extend_from_slice(&x.to_le_bytes());
+        let put_bytes = |out: &mut Vec<u8>, bytes: &[u8]| {
+            put_u32(out, bytes.len() as u32);
+            out.extend_from_slice(bytes);
+        };
+        put_u32(&mut out, self.format_version);
+        put_bytes(&mut out, self.crate_version.as_bytes());
+        put_bytes(&mut out, self.config.as_bytes());
+        put_bytes(&mut out, self.sponge.name.as_bytes());
+        put_u32(&mut out, self.sponge.rate_bytes);
+        put_u32(&mut out, self.sponge.capacity_bytes);
+        put_u32(&mut out, self.sponge.output_bytes);
+        out.push(self.sponge.domain_byte);
+        put_u32(&mut out, self.message_lens.len() as u32);
+        for &len in self.message_lens.iter() {
+            put_u32(&mut out, len);
+        }
+        for x in self.circuit_digest {
+            out.extend_from_slice(&x.to_le_bytes());
+        }
+        put_bytes(&mut out, &self.proof);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        ensure!(bytes.starts_with(MAGIC), "not a proof container");
+        let mut reader = Reader {
+            bytes,
+            pos: MAGIC.len(),
+        };
+        let format_version = reader.u32()?;
+        ensure!(
+            format_version == FORMAT_VERSION,
+            "unsupported format version {}",
+            format_version
+        );
+        let crate_version = reader.string()?;
+        let config = reader.string()?;
+        let sponge = Sponge {
+            name: reader.string()?,
+            rate_bytes: reader.u32()?,
+            capacity_bytes: reader.u32()?,
+            output_bytes: reader.u32()?,
+            domain_byte: reader.take(1)?[0],
+        };
+        let num_messages = reader.u32()?;
+        let message_lens = (0..num_messages)
+            .map(|_| reader.u32())
+            .collect::<Result<Vec<_>>>()?;
+        let mut circuit_digest = [0u64; 4];
+        for x in circuit_digest.iter_mut() {
+            *x = u64::from_le_bytes(reader.take(8)?.try_into().unwrap());
+        }
+        let proof = reader.bytes()?.to_vec();
+        ensure!(reader.pos == bytes.len(), "trailing bytes after the proof");
+        Ok(Self {
+            format_version,
+            crate_version,
+            config,
+            sponge,
+            message_lens,
+            circuit_digest,
+            proof,
+        })
+    }
+
+    /// Decodes either encoding, telling them apart by the binary magic.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.starts_with(MAGIC) {
+            Self::from_bytes(bytes)
+        } else {
+            Self::from_json(std::str::from_utf8(bytes)?)
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos + n)
+            .ok_or_else(|| anyhow!("truncated proof container"))?;
+        self.pos += n;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        Ok(String::from_utf8(self.bytes()?.to_vec())?)
+    }
+}
+
+/// Serializes byte strings as hex in human-readable formats.
+mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        hex::decode(s).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::keccak256_circuit::{
+        build_keccak256_bytes_circuit, bytes_to_words, generate_keccak256_proof,
+    };
+    use crate::proof_container::ProofContainer;
+
+    #[test]
+    fn test_proof_container() {
+        let mut rng = rand::thread_rng();
+        let message: Vec<u8> = (0..3).map(|_| rng.gen()).collect();
+        let circuit = build_keccak256_bytes_circuit(message.len());
+        let proof = generate_keccak256_proof(bytes_to_words(&message), &circuit);
+        let verifier_data = circuit.data.verifier_data();
+        let container = ProofContainer::new(&proof, &verifier_data, &[message.len()]);
+
+        let decoded = ProofContainer::decode(&container.to_bytes()).unwrap();
+        assert_eq!(decoded, container);
+        let decoded = ProofContainer::decode(container.to_json().as_bytes()).unwrap();
+        assert_eq!(decoded, container);
+        assert_eq!(decoded.verify(&verifier_data).unwrap(), proof);
+
+        // A verifier for another circuit refuses the proof before decoding it.
+        let other = build_keccak256_bytes_circuit(message.len() + 1);
+        assert!(decoded.load_proof(&other.data.verifier_data()).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Measurements of one proof generation, returned by the `*_with_stats` prove functions. The same
+/// events are logged through `log`: each STARK proof at debug level, the whole proof at info.
+///
+/// When several STARK proofs go into one proof, as with sharding, their durations and sizes are
+/// summed and the trace height is the largest one.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProvingStats {
+    pub trace_generation: Duration,
+    pub stark_proving: Duration,
+    /// Time spent proving the outer plonky2 circuit.
+    pub recursion_proving: Duration,
+    pub trace_height: usize,
+    pub trace_width: usize,
+    pub num_perms: usize,
+    /// Size of the final proof in bytes, as given by `ProofWithPublicInputs::to_bytes`.
+    pub proof_size: usize,
+    /// Estimated peak memory of the STARK prover in bytes: the trace and its low-degree extension
+    /// by `2^rate_bits`, 8 bytes per field element. Commitment trees and quotient polynomials are
+    /// not counted.
+    pub estimated_peak_bytes: usize,
+}
+
+/// The estimate of `ProvingStats::estimated_peak_bytes`.
+pub(crate) fn estimate_peak_bytes(
+    trace_height: usize,
+    trace_width: usize,
+    rate_bits: usize,
+) -> usize {
+    8 * trace_height * trace_width * (1 + (1 << rate_bits))
+}
+
+impl ProvingStats {
+    pub(crate) fn stark(
+        num_perms: usize,
+        trace_height: usize,
+        trace_width: usize,
+        rate_bits: usize,
+        trace_generation: Duration,
+        stark_proving: Duration,
+    ) -> Self {
+        let stats = Self {
+            trace_generation,
+            stark_proving,
+            trace_height,
+            trace_width,
+            num_perms,
+            estimated_peak_bytes: estimate_peak_bytes(trace_height, trace_width, rate_bits),
+            ..Self::default()
+        };
+        log::debug!(
+            "stark proof of {} permutations ({}x{} trace): trace generation {:?}, proving {:?}",
+            num_perms,
+            trace_height,
+            trace_width,
+            trace_generation,
+            stark_proving
+        );
+        stats
+    }
+
+    /// Combines the stats of STARK proofs generated for the same outer proof.
+    pub(crate) fn merge(stats: impl IntoIterator<Item = ProvingStats>) -> Self {
+        stats.into_iter().fold(Self::default(), |acc, stats| Self {
+            trace_generation: acc.trace_generation + stats.trace_generation,
+            stark_proving: acc.stark_proving + stats.stark_proving,
+            recursion_proving: acc.recursion_proving + stats.recursion_proving,
+            trace_height: acc.trace_height.max(stats.trace_height),
+            trace_width: acc.trace_width.max(stats.trace_width),
+            num_perms: acc.num_perms + stats.num_perms,
+            proof_size: acc.proof_size + stats.proof_size,
+            estimated_peak_bytes: acc.estimated_peak_bytes + stats.estimated_peak_bytes,
+        })
+    }
+
+    /// Records the outer proof and logs the totals.
+    pub(crate) fn finish(&mut self, recursion_proving: Duration, proof_size: usize) {
+        self.recursion_proving = recursion_proving;
+        self.proof_size = proof_size;
+        log::info!("{}", self);
+    }
+}
+
+impl fmt::Display for ProvingStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} permutations, {}x{} trace, trace generation {:?}, stark proving {:?}, \
+             recursion proving {:?}, proof size {} bytes, estimated peak {} MiB",
+            self.num_perms,
+            self.trace_height,
+            self.trace_width,
+            self.trace_generation,
+            self.stark_proving,
+            self.recursion_proving,
+            self.proof_size,
+            self.estimated_peak_bytes >> 20
+        )
+    }
+}
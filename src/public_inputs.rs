@@ -0,0 +1,85 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use anyhow::{ensure, Result};
+use plonky2::field::types::PrimeField64;
+
+use crate::types::{to_u32, Digest256};
+
+/// A message and its keccak256 digest, as exposed by a keccak256 proof.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessageDigest {
+    pub message: Vec<u8>,
+    pub digest: Digest256,
+}
+
+/// Decodes the public inputs of a keccak256 proof with full public inputs: for each message of
+/// `input_num_bytes[i]` bytes, its little-endian u32 words followed by the 8 words of its digest.
+/// The unused bytes of a partial last word must be zero.
+pub fn decode_keccak256_public_inputs<F: PrimeField64>(
+    public_inputs: &[F],
+    input_num_bytes: &[usize],
+) -> Result<Vec<MessageDigest>> {
+    let mut offset = 0;
+    let mut pairs = vec![];
+    for &num_bytes in input_num_bytes {
+        let num_words = (num_bytes + 3) / 4;
+        ensure!(
+            public_inputs.len() >= offset + num_words + 8,
+            "too few public inputs"
+        );
+        let mut message = Vec::with_capacity(4 * num_words);
+        for x in public_inputs[offset..offset + num_words].iter() {
+            message.extend_from_slice(&to_u32(x)?.to_le_bytes());
+        }
+        ensure!(
+            message[num_bytes..].iter().all(|&b| b == 0),
+            "nonzero padding in the last word of a message"
+        );
+        message.truncate(num_bytes);
+        let digest = Digest256::from_field_elements(
+            &public_inputs[offset + num_words..offset + num_words + 8],
+        )?;
+        pairs.push(MessageDigest { message, digest });
+        offset += num_words + 8;
+    }
+    ensure!(offset == public_inputs.len(), "too many public inputs");
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+
+    use crate::keccak256_circuit::{bytes_to_words, keccak256_bytes};
+    use crate::public_inputs::decode_keccak256_public_inputs;
+
+    type F = GoldilocksField;
+
+    #[test]
+    fn test_decode_keccak256_public_inputs() {
+        let messages = [b"hello".to_vec(), vec![], vec![7; 8]];
+        let lens = messages.iter().map(|m| m.len()).collect::<Vec<_>>();
+        let mut public_inputs = messages
+            .iter()
+            .flat_map(|m| {
+                [bytes_to_words(m), keccak256_bytes(m).words().to_vec()]
+                    .concat()
+                    .into_iter()
+                    .map(F::from_canonical_u32)
+            })
+            .collect::<Vec<_>>();
+
+        let pairs = decode_keccak256_public_inputs(&public_inputs, &lens).unwrap();
+        for (pair, message) in pairs.iter().zip(messages.iter()) {
+            assert_eq!(&pair.message, message);
+            assert_eq!(pair.digest, keccak256_bytes(message));
+        }
+
+        assert!(decode_keccak256_public_inputs(&public_inputs[1..], &lens).is_err());
+        // The three unused bytes of the last word of "hello".
+        public_inputs[1] += F::from_canonical_u32(1 << 8);
+        assert!(decode_keccak256_public_inputs(&public_inputs, &lens).is_err());
+    }
+}
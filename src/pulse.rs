@@ -1,3 +1,6 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
 use itertools::Itertools;
 use plonky2::{
     field::{extension::Extendable, packed::PackedField, types::Field},
@@ -0,0 +1,387 @@
+//! RLP encoding, natively and in a circuit.
+//!
+//! The circuits have no variable-length keccak256, so an encoding has a fixed layout: an
+//! `RlpItemTarget` fixes the length of every string, and for a one-byte string whether the byte
+//! is below 0x80 and so is its own encoding. The prefixes follow from the layout and are
+//! constants. What varies is the bytes, which are range-checked, and the circuit enforces that
+//! they fit the layout, so every encoding it accepts is canonical.
+
+use anyhow::{anyhow, bail, ensure, Result};
+use plonky2::{
+    field::{extension::Extendable, types::Field},
+    hash::hash_types::RichField,
+    iop::{target::Target, witness::WitnessWrite},
+    plonk::circuit_builder::CircuitBuilder,
+};
+use primitive_types::U256;
+
+/// An RLP item. A scalar string is an integer, which has no leading zero byte.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RlpItem {
+    String { bytes: Vec<u8>, scalar: bool },
+    List(Vec<RlpItem>),
+}
+
+impl RlpItem {
+    pub fn bytes(bytes: &[u8]) -> Self {
+        Self::String {
+            bytes: bytes.to_vec(),
+            scalar: false,
+        }
+    }
+
+    /// The minimal big-endian bytes of `x`, empty for zero.
+    pub fn uint(x: U256) -> Self {
+        let mut word = [0u8; 32];
+        x.to_big_endian(&mut word);
+        let start = word.iter().position(|&b| b != 0).unwrap_or(32);
+        Self::String {
+            bytes: word[start..].to_vec(),
+            scalar: true,
+        }
+    }
+
+    pub fn u64(x: u64) -> Self {
+        Self::uint(x.into())
+    }
+}
+
+fn encode_length(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        vec![offset + len as u8]
+    } else {
+        let len_bytes = len.to_be_bytes();
+        let start = len_bytes.iter().position(|&b| b != 0).unwrap();
+        let mut prefix = vec![offset + 55 + (len_bytes.len() - start) as u8];
+        prefix.extend_from_slice(&len_bytes[start..]);
+        prefix
+    }
+}
+
+pub fn rlp_encode(item: &RlpItem) -> Vec<u8> {
+    match item {
+        RlpItem::String { bytes, .. } if bytes.len() == 1 && bytes[0] < 0x80 => bytes.clone(),
+        RlpItem::String { bytes, .. } => [encode_length(bytes.len(), 0x80), bytes.clone()].concat(),
+        RlpItem::List(items) => rlp_encode_list(items),
+    }
+}
+
+pub fn rlp_encode_list(items: &[RlpItem]) -> Vec<u8> {
+    let payload = items.iter().flat_map(rlp_encode).collect::<Vec<_>>();
+    [encode_length(payload.len(), 0xc0), payload].concat()
+}
+
+/// Decodes the item at the start of `bytes`, with the number of bytes it takes.
+fn decode_item(bytes: &[u8]) -> Result<(RlpItem, usize)> {
+    let (&prefix, rest) = bytes
+        .split_first()
+        .ok_or_else(|| anyhow!("truncated RLP"))?;
+    if prefix < 0x80 {
+        return Ok((RlpItem::bytes(&[prefix]), 1));
+    }
+    let is_list = prefix >= 0xc0;
+    let short_len = (prefix - if is_list { 0xc0 } else { 0x80 }) as usize;
+    let (header_len, len) = if short_len < 56 {
+        (1, short_len)
+    } else {
+        let len_len = short_len - 55;
+        ensure!(len_len <= USIZE_BYTES, "RLP length too long");
+        let len_bytes = rest
+            .get(..len_len)
+            .ok_or_else(|| anyhow!("truncated RLP"))?;
+        let len = len_bytes
+            .iter()
+            .fold(0usize, |acc, &b| (acc << 8) | b as usize);
+        (1 + len_len, len)
+    };
+    let end = header_len
+        .checked_add(len)
+        .ok_or_else(|| anyhow!("RLP length too long"))?;
+    let payload = bytes
+        .get(header_len..end)
+        .ok_or_else(|| anyhow!("truncated RLP"))?;
+    if !is_list {
+        return Ok((RlpItem::bytes(payload), end));
+    }
+    let mut items = vec![];
+    let mut offset = 0;
+    while offset < payload.len() {
+        let (item, item_len) = decode_item(&payload[offset..])?;
+        items.push(item);
+        offset += item_len;
+    }
+    Ok((RlpItem::List(items), end))
+}
+
+const USIZE_BYTES: usize = usize::BITS as usize / 8;
+
+/// Decodes an RLP item that takes all of `bytes`. Strings decode as non-scalars, since the
+/// encoding does not tell integers apart. The encoding must be canonical: `rlp_encode` of the
+/// item gives `bytes` back.
+pub fn rlp_decode(bytes: &[u8]) -> Result<RlpItem> {
+    let (item, len) = decode_item(bytes)?;
+    ensure!(len == bytes.len(), "trailing bytes after the RLP item");
+    ensure!(rlp_encode(&item) == bytes, "non-canonical RLP");
+    Ok(item)
+}
+
+/// The circuit counterpart of `RlpItem`, with one target per byte. `compact` is whether a
+/// one-byte string encodes as itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RlpItemTarget {
+    String {
+        bytes: Vec<Target>,
+        scalar: bool,
+        compact: bool,
+    },
+    List(Vec<RlpItemTarget>),
+}
+
+impl RlpItemTarget {
+    /// Virtual targets with the layout of `item`, to be set with `set_witness`.
+    pub fn new_virtual<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        item: &RlpItem,
+    ) -> Self {
+        match item {
+            RlpItem::String { bytes, scalar } => Self::String {
+                bytes: builder.add_virtual_targets(bytes.len()),
+                scalar: *scalar,
+                compact: bytes.len() == 1 && bytes[0] < 0x80,
+            },
+            RlpItem::List(items) => Self::List(
+                items
+                    .iter()
+                    .map(|item| Self::new_virtual(builder, item))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn set_witness<F: Field>(
+        &self,
+        witness: &mut impl WitnessWrite<F>,
+        item: &RlpItem,
+    ) -> Result<()> {
+        match (self, item) {
+            (
+                Self::String {
+                    bytes: targets,
+                    scalar,
+                    compact,
+                },
+                RlpItem::String {
+                    bytes,
+                    scalar: item_scalar,
+                },
+            ) => {
+                ensure!(
+                    targets.len() == bytes.len()
+                        && scalar == item_scalar
+                        && (bytes.len() != 1 || *compact == (bytes[0] < 0x80)),
+                    "the item does not have the layout of the targets"
+                );
+                for (&t, &byte) in targets.iter().zip(bytes.iter()) {
+                    witness.set_target(t, F::from_canonical_u8(byte));
+                }
+            }
+            (Self::List(targets), RlpItem::List(items)) => {
+                ensure!(
+                    targets.len() == items.len(),
+                    "expected {} items, got {}",
+                    targets.len(),
+                    items.len()
+                );
+                for (t, item) in targets.iter().zip(items.iter()) {
+                    t.set_witness(witness, item)?;
+                }
+            }
+            _ => bail!("the item does not have the layout of the targets"),
+        }
+        Ok(())
+    }
+
+    /// The length of the encoding, which the layout fixes.
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            Self::String { compact: true, .. } => 1,
+            Self::String { bytes, .. } => encode_length(bytes.len(), 0x80).len() + bytes.len(),
+            Self::List(items) => {
+                let payload_len = items.iter().map(Self::encoded_len).sum();
+                encode_length(payload_len, 0xc0).len() + payload_len
+            }
+        }
+    }
+}
+
+fn constant_bytes<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    bytes: &[u8],
+) -> Vec<Target> {
+    bytes
+        .iter()
+        .map(|&byte| builder.constant(F::from_canonical_u8(byte)))
+        .collect()
+}
+
+/// The circuit version of `rlp_encode`. The bytes of the items are range-checked to 8 bits, a
+/// scalar must not start with a zero byte, and a one-byte string must be below 0x80 exactly when
+/// its layout is compact.
+pub fn rlp_encode_circuit<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    item: &RlpItemTarget,
+) -> Vec<Target> {
+    match item {
+        RlpItemTarget::String {
+            bytes,
+            scalar,
+            compact,
+        } => {
+            for &byte in bytes {
+                builder.range_check(byte, 8);
+            }
+            if *scalar && !bytes.is_empty() {
+                let zero = builder.zero();
+                let is_zero = builder.is_equal(bytes[0], zero);
+                builder.assert_zero(is_zero.target);
+            }
+            if bytes.len() == 1 {
+                let high_bit = builder.split_le(bytes[0], 8)[7];
+                let expected = builder.constant(F::from_bool(!compact));
+                builder.connect(high_bit.target, expected);
+            }
+            if *compact {
+                bytes.clone()
+            } else {
+                let prefix = encode_length(bytes.len(), 0x80);
+                [constant_bytes(builder, &prefix), bytes.clone()].concat()
+            }
+        }
+        RlpItemTarget::List(items) => rlp_encode_list_circuit(builder, items),
+    }
+}
+
+/// The circuit version of `rlp_encode_list`.
+pub fn rlp_encode_list_circuit<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    items: &[RlpItemTarget],
+) -> Vec<Target> {
+    let payload = items
+        .iter()
+        .flat_map(|item| rlp_encode_circuit(builder, item))
+        .collect::<Vec<_>>();
+    let prefix = encode_length(payload.len(), 0xc0);
+    [constant_bytes(builder, &prefix), payload].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use anyhow::Result;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::CircuitConfig,
+        config::{GenericConfig, PoseidonGoldilocksConfig},
+    };
+
+    use crate::rlp::{rlp_decode, rlp_encode, rlp_encode_circuit, RlpItem, RlpItemTarget};
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_rlp_encode() {
+        // The examples of the Ethereum wiki.
+        assert_eq!(rlp_encode(&RlpItem::bytes(b"dog")), b"\x83dog");
+        let cat_dog = RlpItem::List(vec![RlpItem::bytes(b"cat"), RlpItem::bytes(b"dog")]);
+        assert_eq!(rlp_encode(&cat_dog), b"\xc8\x83cat\x83dog");
+        assert_eq!(rlp_encode(&RlpItem::bytes(b"")), [0x80]);
+        assert_eq!(rlp_encode(&RlpItem::List(vec![])), [0xc0]);
+        assert_eq!(rlp_encode(&RlpItem::u64(0)), [0x80]);
+        assert_eq!(rlp_encode(&RlpItem::u64(15)), [0x0f]);
+        assert_eq!(rlp_encode(&RlpItem::u64(1024)), [0x82, 0x04, 0x00]);
+        let lorem = b"Lorem ipsum dolor sit amet, consectetur adipisicing elit";
+        assert_eq!(
+            rlp_encode(&RlpItem::bytes(lorem)),
+            [&[0xb8, 0x38][..], &lorem[..]].concat()
+        );
+        // [ [], [[]], [ [], [[]] ] ]
+        let empty = || RlpItem::List(vec![]);
+        let set = RlpItem::List(vec![
+            empty(),
+            RlpItem::List(vec![empty()]),
+            RlpItem::List(vec![empty(), RlpItem::List(vec![empty()])]),
+        ]);
+        assert_eq!(
+            rlp_encode(&set),
+            [0xc7, 0xc0, 0xc1, 0xc0, 0xc3, 0xc0, 0xc1, 0xc0]
+        );
+
+        for item in [cat_dog, set, RlpItem::bytes(lorem), RlpItem::bytes(b"")] {
+            assert_eq!(rlp_decode(&rlp_encode(&item)).unwrap(), item);
+        }
+        assert_eq!(rlp_decode(&[0x0f]).unwrap(), RlpItem::bytes(&[0x0f]));
+        // A byte below 0x80 with a prefix, a long length that fits the short form, a length with
+        // a leading zero, trailing bytes, a truncated string and no bytes.
+        for bytes in [
+            &[0x81, 0x0f][..],
+            &[0xb8, 0x03, b'd', b'o', b'g'],
+            &[0xb9, 0x00, 0x38],
+            &[0x83, b'd', b'o', b'g', 0x00],
+            &[0x83, b'd', b'o'],
+            &[],
+        ] {
+            assert!(rlp_decode(bytes).is_err(), "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn test_rlp_encode_circuit() {
+        let item = RlpItem::List(vec![
+            RlpItem::u64(0),
+            RlpItem::u64(15),
+            RlpItem::u64(200),
+            RlpItem::u64(1024),
+            RlpItem::bytes(&[0xab; 60]),
+            RlpItem::List(vec![RlpItem::bytes(b"cat"), RlpItem::bytes(b"dog")]),
+        ]);
+        let encoded = rlp_encode(&item);
+
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let item_t = RlpItemTarget::new_virtual(&mut builder, &item);
+        let encoded_t = rlp_encode_circuit(&mut builder, &item_t);
+        assert_eq!(encoded_t.len(), encoded.len());
+        assert_eq!(item_t.encoded_len(), encoded.len());
+        for (&byte_t, &byte) in encoded_t.iter().zip(encoded.iter()) {
+            let expected = builder.constant(F::from_canonical_u8(byte));
+            builder.connect(byte_t, expected);
+        }
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        item_t.set_witness(&mut pw, &item).unwrap();
+        data.verify(data.prove(pw).unwrap()).unwrap();
+
+        // A scalar with a leading zero byte is not canonical.
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let item = RlpItem::u64(1024);
+        let item_t = RlpItemTarget::new_virtual(&mut builder, &item);
+        rlp_encode_circuit(&mut builder, &item_t);
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        let non_canonical = RlpItem::String {
+            bytes: vec![0x00, 0x04],
+            scalar: true,
+        };
+        item_t.set_witness(&mut pw, &non_canonical).unwrap();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| -> Result<()> {
+            data.verify(data.prove(pw)?)
+        }));
+        assert!(!matches!(result, Ok(Ok(()))));
+    }
+}
@@ -0,0 +1,200 @@
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use anyhow::{anyhow, ensure, Result};
+use plonky2::{
+    field::extension::Extendable,
+    gadgets::{
+        arithmetic::EqualityGenerator,
+        arithmetic_extension::QuotientGeneratorExtension,
+        range_check::LowHighGenerator,
+        split_base::BaseSumGenerator,
+        split_join::{SplitGenerator, WireSplitGenerator},
+    },
+    gates::{
+        arithmetic_base::{ArithmeticBaseGenerator, ArithmeticGate},
+        arithmetic_extension::{ArithmeticExtensionGate, ArithmeticExtensionGenerator},
+        base_sum::{BaseSplitGenerator, BaseSumGate},
+        constant::ConstantGate,
+        coset_interpolation::{CosetInterpolationGate, InterpolationGenerator},
+        exponentiation::{ExponentiationGate, ExponentiationGenerator},
+        gate::GateRef,
+        low_degree_interpolation::LowDegreeInterpolationGate,
+        multiplication_extension::{MulExtensionGate, MulExtensionGenerator},
+        noop::NoopGate,
+        poseidon::{PoseidonGate, PoseidonGenerator},
+        poseidon_mds::{PoseidonMdsGate, PoseidonMdsGenerator},
+        public_input::PublicInputGate,
+        random_access::{RandomAccessGate, RandomAccessGenerator},
+        reducing::{ReducingGate, ReducingGenerator},
+        reducing_extension::{
+            ReducingExtensionGate, ReducingGenerator as ReducingExtensionGenerator,
+        },
+    },
+    get_gate_tag_impl, get_generator_tag_impl,
+    hash::hash_types::RichField,
+    impl_gate_serializer, impl_generator_serializer,
+    iop::generator::{
+        ConstantGenerator, CopyGenerator, NonzeroTestGenerator, RandomValueGenerator,
+        WitnessGeneratorRef,
+    },
+    plonk::{
+        circuit_data::{CircuitData, CommonCircuitData},
+        config::{AlgebraicHasher, GenericConfig, GenericHashOut, Hasher},
+    },
+    read_gate_impl, read_generator_impl,
+    recursion::dummy_circuit::DummyProofGenerator,
+    util::serialization::{Buffer, GateSerializer, IoResult, WitnessGeneratorSerializer},
+};
+
+use crate::xor_gate::{U32XorGate, U32XorGenerator};
+
+/// The gates of plonky2's `DefaultGateSerializer` and the `U32XorGate` of the keccak256 sponge.
+/// Verifier data of circuits that absorb messages must be (de)serialized with it.
+pub struct KeccakGateSerializer;
+
+impl<F: RichField + Extendable<D>, const D: usize> GateSerializer<F, D> for KeccakGateSerializer {
+    impl_gate_serializer! {
+        KeccakGateSerializer,
+        ArithmeticGate,
+        ArithmeticExtensionGate<D>,
+        BaseSumGate<2>,
+        ConstantGate,
+        CosetInterpolationGate<F, D>,
+        ExponentiationGate<F, D>,
+        LowDegreeInterpolationGate<F, D>,
+        MulExtensionGate<D>,
+        NoopGate,
+        PoseidonMdsGate<F, D>,
+        PoseidonGate<F, D>,
+        PublicInputGate,
+        RandomAccessGate<F, D>,
+        ReducingExtensionGate<D>,
+        ReducingGate<D>,
+        U32XorGate
+    }
+}
+
+/// The generators of plonky2's `DefaultGeneratorSerializer` and the `U32XorGenerator` of the
+/// keccak256 sponge. Prover data of circuits that absorb messages must be (de)serialized with it,
+/// together with `KeccakGateSerializer`.
+pub struct KeccakGeneratorSerializer<C: GenericConfig<D>, const D: usize> {
+    pub _phantom: PhantomData<C>,
+}
+
+impl<C: GenericConfig<D>, const D: usize> Default for KeccakGeneratorSerializer<C, D> {
+    fn default() -> Self {
+        Self {
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<F, C, const D: usize> WitnessGeneratorSerializer<F, D> for KeccakGeneratorSerializer<C, D>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F> + 'static,
+    C::Hasher: AlgebraicHasher<F>,
+{
+    impl_generator_serializer! {
+        KeccakGeneratorSerializer,
+        DummyProofGenerator<F, C, D>,
+        ArithmeticBaseGenerator<F, D>,
+        ArithmeticExtensionGenerator<F, D>,
+        BaseSplitGenerator<2>,
+        BaseSumGenerator<2>,
+        ConstantGenerator<F>,
+        CopyGenerator,
+        EqualityGenerator,
+        ExponentiationGenerator<F, D>,
+        InterpolationGenerator<F, D>,
+        LowHighGenerator,
+        MulExtensionGenerator<F, D>,
+        NonzeroTestGenerator,
+        PoseidonGenerator<F, D>,
+        PoseidonMdsGenerator<D>,
+        QuotientGeneratorExtension<D>,
+        RandomAccessGenerator<F, D>,
+        RandomValueGenerator,
+        ReducingGenerator<D>,
+        ReducingExtensionGenerator<D>,
+        SplitGenerator,
+        WireSplitGenerator,
+        U32XorGenerator
+    }
+}
+
+/// The header of `circuit_data_to_bytes`: the crate version and the parameters the circuit is
+/// constructed from, each a little-endian u64 after their number as a u32.
+fn circuit_header(params: &[u64]) -> Vec<u8> {
+    let version = env!("CARGO_PKG_VERSION");
+    let mut bytes = Vec::new();
+    bytes.push(version.len() as u8);
+    bytes.extend_from_slice(version.as_bytes());
+    bytes.extend_from_slice(&(params.len() as u32).to_le_bytes());
+    for param in params {
+        bytes.extend_from_slice(&param.to_le_bytes());
+    }
+    bytes
+}
+
+/// Serializes prover data with `KeccakGateSerializer` and `KeccakGeneratorSerializer`, after a
+/// header naming the circuit: the crate version, `params` and the circuit digest.
+pub(crate) fn circuit_data_to_bytes<F, C, const D: usize>(
+    data: &CircuitData<F, C, D>,
+    params: &[u64],
+) -> Result<Vec<u8>>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F> + 'static,
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let mut bytes = circuit_header(params);
+    bytes.extend(data.verifier_only.circuit_digest.to_bytes());
+    let payload = data
+        .to_bytes(
+            &KeccakGateSerializer,
+            &KeccakGeneratorSerializer::<C, D>::default(),
+        )
+        .map_err(|_| anyhow!("failed to serialize the circuit"))?;
+    bytes.extend(payload);
+    Ok(bytes)
+}
+
+/// Deserializes prover data written by `circuit_data_to_bytes` with the same `params` by this
+/// version of the crate, and checks that it is the circuit of the digest in its header.
+pub(crate) fn circuit_data_from_bytes<F, C, const D: usize>(
+    bytes: &[u8],
+    params: &[u64],
+) -> Result<CircuitData<F, C, D>>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F> + 'static,
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let header = circuit_header(params);
+    let version_len = 1 + header[0] as usize;
+    ensure!(
+        bytes.get(..version_len) == Some(&header[..version_len]),
+        "the circuit was serialized by another version of the crate"
+    );
+    ensure!(
+        bytes.get(version_len..header.len()) == Some(&header[version_len..]),
+        "the circuit was built for other parameters"
+    );
+    let hash_size = <C::Hasher as Hasher<F>>::HASH_SIZE;
+    let rest = &bytes[header.len()..];
+    ensure!(rest.len() >= hash_size, "truncated circuit data");
+    let (digest, payload) = rest.split_at(hash_size);
+    let data = CircuitData::from_bytes(
+        payload,
+        &KeccakGateSerializer,
+        &KeccakGeneratorSerializer::<C, D>::default(),
+    )
+    .map_err(|_| anyhow!("invalid circuit data"))?;
+    ensure!(
+        data.verifier_only.circuit_digest.to_bytes() == digest,
+        "the circuit data does not match the circuit digest of its header"
+    );
+    Ok(data)
+}
@@ -0,0 +1,227 @@
+use std::time::Instant;
+
+use itertools::Itertools;
+use plonky2::{
+    field::extension::Extendable,
+    field::types::Field,
+    hash::hash_types::RichField,
+    iop::{
+        target::Target,
+        witness::{PartialWitness, WitnessWrite},
+    },
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CircuitData},
+        config::{GenericConfig, PoseidonGoldilocksConfig},
+        proof::ProofWithPublicInputs,
+    },
+};
+use plonky2_maybe_rayon::*;
+use starky::{
+    proof::StarkProofWithPublicInputsTarget, recursive_verifier::set_stark_proof_with_pis_target,
+};
+
+use crate::{
+    keccak256_circuit::{prove_keccak_statements_with_stats, verify_keccak_statements_circuit},
+    multi_keccak256_circuit::{multi_keccak256, multi_keccak256_circuit_with_statements},
+    proving_stats::ProvingStats,
+    types::Digest256Target,
+};
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+/// Splits `num_perms` permutations into shards of at most `max_perms_per_shard` permutations.
+/// The shards are balanced so that their sizes differ by at most one.
+pub fn shard_sizes(num_perms: usize, max_perms_per_shard: usize) -> Vec<usize> {
+    assert!(max_perms_per_shard > 0);
+    let num_shards = (num_perms + max_perms_per_shard - 1) / max_perms_per_shard;
+    let num_shards = num_shards.max(1);
+    let base = num_perms / num_shards;
+    let rem = num_perms % num_shards;
+    (0..num_shards)
+        .map(|i| if i < rem { base + 1 } else { base })
+        .collect()
+}
+
+pub struct ShardedKeccak256Circuit<F, C, const D: usize>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+{
+    pub data: CircuitData<F, C, D>,
+    pub stark_proofs_t: Vec<StarkProofWithPublicInputsTarget<D>>,
+    pub shard_sizes: Vec<usize>,
+    pub inputs_t: Vec<Vec<Target>>,
+    pub outputs_t: Vec<Digest256Target>,
+}
+
+/// Builds a circuit for the same statement as `build_multi_keccak256_circuit`, but the
+/// permutations are split across several `KeccakStark` proofs of at most
+/// `max_perms_per_shard` permutations each. Every shard proof is verified recursively, and the
+/// public inputs have the same layout as the multi-message circuit.
+pub fn build_sharded_keccak256_circuit(
+    input_lens: Vec<usize>,
+    max_perms_per_shard: usize,
+) -> ShardedKeccak256Circuit<F, C, D> {
+    let block_size = 136 / 4;
+    let num_perms: usize = input_lens
+        .iter()
+        .map(|input_len| input_len / block_size + 1)
+        .sum();
+    let shard_sizes = shard_sizes(num_perms, max_perms_per_shard);
+
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let inputs_t = input_lens
+        .iter()
+        .map(|&input_len| builder.add_virtual_targets(input_len))
+        .collect_vec();
+    let (outputs_t, statements_t) =
+        multi_keccak256_circuit_with_statements(&mut builder, inputs_t.clone());
+
+    let mut stark_proofs_t = vec![];
+    let mut rest = statements_t.as_slice();
+    for &shard_size in shard_sizes.iter() {
+        let (shard_statements_t, tail) = rest.split_at(shard_size);
+        stark_proofs_t.push(verify_keccak_statements_circuit(
+            &mut builder,
+            shard_statements_t,
+        ));
+        rest = tail;
+    }
+    assert!(rest.is_empty());
+
+    let inputs_and_outputs = inputs_t
+        .iter()
+        .zip(outputs_t.iter())
+        .flat_map(|(input_t, output_t)| [input_t.as_slice(), output_t.targets()].concat())
+        .collect_vec();
+    builder.register_public_inputs(&inputs_and_outputs);
+    let data = builder.build::<C>();
+
+    ShardedKeccak256Circuit {
+        data,
+        stark_proofs_t,
+        shard_sizes,
+        inputs_t,
+        outputs_t,
+    }
+}
+
+pub fn generate_sharded_keccak256_proof(
+    inputs: Vec<Vec<u32>>,
+    circuit: &ShardedKeccak256Circuit<F, C, D>,
+) -> ProofWithPublicInputs<F, C, D> {
+    generate_sharded_keccak256_proof_with_stats(inputs, circuit).0
+}
+
+/// Like `generate_sharded_keccak256_proof`. The stats of the shards are merged, so the STARK
+/// durations are summed over shards proven in parallel.
+pub fn generate_sharded_keccak256_proof_with_stats(
+    inputs: Vec<Vec<u32>>,
+    circuit: &ShardedKeccak256Circuit<F, C, D>,
+) -> (ProofWithPublicInputs<F, C, D>, ProvingStats) {
+    let (outputs, statements) = multi_keccak256(inputs.clone());
+    let mut shard_statements = vec![];
+    let mut rest = statements.as_slice();
+    for &shard_size in circuit.shard_sizes.iter() {
+        assert!(
+            rest.len() >= shard_size,
+            "inputs do not match the circuit's message lengths"
+        );
+        let (shard, tail) = rest.split_at(shard_size);
+        shard_statements.push(shard);
+        rest = tail;
+    }
+    assert!(
+        rest.is_empty(),
+        "inputs do not match the circuit's message lengths"
+    );
+
+    // The shards are independent, so they can be proven in parallel.
+    let (inner_proofs, shard_stats): (Vec<_>, Vec<_>) = shard_statements
+        .par_iter()
+        .map(|shard| prove_keccak_statements_with_stats(shard))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .unzip();
+    let mut stats = ProvingStats::merge(shard_stats);
+
+    let mut pw = PartialWitness::new();
+    inner_proofs
+        .iter()
+        .zip(circuit.stark_proofs_t.iter())
+        .for_each(|(inner_proof, stark_proof_t)| {
+            set_stark_proof_with_pis_target(&mut pw, stark_proof_t, inner_proof);
+        });
+    // set inputs
+    inputs
+        .iter()
+        .zip(circuit.inputs_t.iter())
+        .for_each(|(input, input_t)| {
+            input
+                .iter()
+                .zip(input_t.iter())
+                .for_each(|(w, t)| pw.set_target(*t, F::from_canonical_u32(*w)))
+        });
+    // set outputs
+    outputs
+        .iter()
+        .zip(circuit.outputs_t.iter())
+        .for_each(|(output, output_t)| output_t.set_witness(&mut pw, *output));
+    let now = Instant::now();
+    let proof = circuit.data.prove(pw).unwrap();
+    stats.finish(now.elapsed(), proof.to_bytes().len());
+    (proof, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use plonky2::field::{goldilocks_field::GoldilocksField, types::Field};
+    use rand::Rng;
+
+    use crate::multi_keccak256_circuit::multi_keccak256;
+    use crate::sharded_keccak256_circuit::{
+        build_sharded_keccak256_circuit, generate_sharded_keccak256_proof_with_stats, shard_sizes,
+    };
+
+    #[test]
+    fn test_shard_sizes() {
+        assert_eq!(shard_sizes(10, 4), vec![4, 3, 3]);
+        assert_eq!(shard_sizes(8, 4), vec![4, 4]);
+        assert_eq!(shard_sizes(3, 8), vec![3]);
+        assert_eq!(shard_sizes(1, 1), vec![1]);
+    }
+
+    #[test]
+    fn test_sharded_keccak256_circuit() {
+        let input_lens: Vec<usize> = vec![256, 1, 20];
+
+        let circuit = build_sharded_keccak256_circuit(input_lens.clone(), 4);
+        assert_eq!(circuit.shard_sizes, vec![4, 3, 3]);
+        let mut rng = rand::thread_rng();
+        let inputs: Vec<Vec<u32>> = input_lens
+            .iter()
+            .map(|&input_len| (0..input_len).map(|_| rng.gen()).collect())
+            .collect_vec();
+
+        let (proof, stats) = generate_sharded_keccak256_proof_with_stats(inputs.clone(), &circuit);
+        assert_eq!(stats.num_perms, 10);
+        circuit.data.verify(proof.clone()).unwrap();
+
+        // assertion
+        {
+            let (outputs, _statements) = multi_keccak256(inputs.clone());
+            let inputs_and_outputs = inputs
+                .iter()
+                .zip(outputs.iter())
+                .flat_map(|(input, output)| [input.as_slice(), &output.words()].concat())
+                .map(GoldilocksField::from_canonical_u32)
+                .collect_vec();
+            assert!(proof.public_inputs == inputs_and_outputs);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Forged traces that must be rejected.
+//!
+//! Each test takes an honest trace, breaks one relation, and regenerates every column derived
+//! from the mutated one (later stages of the same round, later rounds, and the claimed output),
+//! so that the intended relation is the only one violated. The constraint checker must report
+//! exactly the expected groups, and proving or verification must fail.
+
+use std::panic::{self, AssertUnwindSafe};
+
+use itertools::Itertools;
+use plonky2::field::polynomial::PolynomialValues;
+use plonky2::field::types::Field;
+use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+use plonky2::util::{timing::TimingTree, transpose};
+use starky::prover::prove;
+use starky::verifier::verify_stark_proof;
+
+use crate::columns::{
+    reg_a, reg_a_prime, reg_a_prime_prime, reg_a_prime_prime_0_0_bit, reg_a_prime_prime_prime,
+    reg_c, reg_c_prime, NUM_COLUMNS, REG_FILTER,
+};
+use crate::constants::rc_value_bit;
+use crate::constraint_checker::{check_keccak_constraints, ConstraintGroup};
+use crate::keccak_constraints::{
+    generate_a_prime, generate_a_prime_prime, generate_a_prime_prime_0_0_bits,
+    generate_a_prime_prime_prime_0_0, generate_c_prime, generate_keccak_trace_row_for_round,
+};
+use crate::keccak_stark::{KeccakStark, NUM_ROUNDS};
+use crate::pulse::{get_pulse_col, get_witness_col};
+use crate::types::KeccakState;
+use crate::utils::read_output;
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+type S = KeccakStark<F, D>;
+
+/// Row-major trace and public inputs of a single honest permutation.
+fn honest() -> (S, Vec<Vec<F>>, Vec<F>) {
+    let input = KeccakState(rand::random());
+    let output = input.permute();
+    let stark = S::new(1);
+    let trace = stark.generate_trace(vec![input], 8);
+    let public_inputs = stark.generate_public_inputs(vec![input], vec![output]);
+    let rows = transpose(&trace.into_iter().map(|col| col.values).collect_vec());
+    (stark, rows, public_inputs)
+}
+
+fn main_row(rows: &mut [Vec<F>], row: usize) -> &mut [F; NUM_COLUMNS] {
+    (&mut rows[row][..NUM_COLUMNS]).try_into().unwrap()
+}
+
+/// Regenerates the rounds after `row` in its permutation from the output of `row`, and the
+/// claimed output of the permutation.
+fn regenerate_after(rows: &mut [Vec<F>], public_inputs: &mut [F], row: usize) {
+    let perm = row / NUM_ROUNDS;
+    for r in row + 1..(perm + 1) * NUM_ROUNDS {
+        for x in 0..5 {
+            for y in 0..5 {
+                for limb in 0..2 {
+                    rows[r][reg_a(x, y) + limb] = rows[r - 1][reg_a_prime_prime_prime(x, y) + limb];
+                }
+            }
+        }
+        generate_keccak_trace_row_for_round(main_row(rows, r), r % NUM_ROUNDS);
+    }
+    let output = read_output(&rows[(perm + 1) * NUM_ROUNDS - 1]);
+    let offset = 100 * perm + 50;
+    public_inputs[offset..offset + 50].copy_from_slice(&output);
+}
+
+fn prove_and_verify(stark: S, rows: &[Vec<F>], public_inputs: &[F]) -> bool {
+    let config = stark.config();
+    let trace = transpose(rows)
+        .into_iter()
+        .map(PolynomialValues::new)
+        .collect_vec();
+    let public_inputs = public_inputs.to_vec();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let proof = prove::<F, C, S, D>(
+            stark,
+            &config,
+            trace,
+            public_inputs,
+            &mut TimingTree::default(),
+        )?;
+        verify_stark_proof(stark, proof, &config)
+    }));
+    matches!(result, Ok(Ok(())))
+}
+
+fn assert_rejected(stark: S, rows: &[Vec<F>], public_inputs: &[F], expected: &[ConstraintGroup]) {
+    let trace = transpose(rows)
+        .into_iter()
+        .map(PolynomialValues::new)
+        .collect_vec();
+    let failures = check_keccak_constraints(&stark, &trace, public_inputs);
+    for failure in failures.iter() {
+        assert!(
+            expected.contains(&failure.group),
+            "unexpected failure {}",
+            failure
+        );
+    }
+    for group in expected {
+        assert!(
+            failures.iter().any(|failure| failure.group == *group),
+            "{:?} is not violated",
+            group
+        );
+    }
+    assert!(!prove_and_verify(stark, rows, public_inputs));
+}
+
+#[test]
+fn test_honest_trace_is_accepted() {
+    let (stark, rows, public_inputs) = honest();
+    let trace = transpose(&rows)
+        .into_iter()
+        .map(PolynomialValues::new)
+        .collect_vec();
+    assert!(check_keccak_constraints(&stark, &trace, &public_inputs).is_empty());
+    assert!(prove_and_verify(stark, &rows, &public_inputs));
+}
+
+#[test]
+fn test_forged_c() {
+    let (stark, mut rows, mut public_inputs) = honest();
+    let row = 5;
+    let lv = main_row(&mut rows, row);
+    lv[reg_c(1, 7)] = F::ONE - lv[reg_c(1, 7)];
+    generate_c_prime(lv);
+    generate_a_prime(lv);
+    generate_a_prime_prime(lv);
+    generate_a_prime_prime_0_0_bits(lv);
+    generate_a_prime_prime_prime_0_0(lv, row);
+    regenerate_after(&mut rows, &mut public_inputs, row);
+    assert_rejected(
+        stark,
+        &rows,
+        &public_inputs,
+        &[ConstraintGroup::APrimeParity],
+    );
+}
+
+#[test]
+fn test_forged_c_prime() {
+    let (stark, mut rows, mut public_inputs) = honest();
+    let row = 11;
+    let lv = main_row(&mut rows, row);
+    lv[reg_c_prime(3, 40)] = F::ONE - lv[reg_c_prime(3, 40)];
+    generate_a_prime(lv);
+    generate_a_prime_prime(lv);
+    generate_a_prime_prime_0_0_bits(lv);
+    generate_a_prime_prime_prime_0_0(lv, row);
+    regenerate_after(&mut rows, &mut public_inputs, row);
+    assert_rejected(
+        stark,
+        &rows,
+        &public_inputs,
+        &[ConstraintGroup::ThetaCPrime],
+    );
+}
+
+#[test]
+fn test_forged_a_prime() {
+    let (stark, mut rows, mut public_inputs) = honest();
+    let row = 2;
+    let lv = main_row(&mut rows, row);
+    lv[reg_a_prime(2, 4, 9)] = F::ONE - lv[reg_a_prime(2, 4, 9)];
+    generate_a_prime_prime(lv);
+    generate_a_prime_prime_0_0_bits(lv);
+    generate_a_prime_prime_prime_0_0(lv, row);
+    regenerate_after(&mut rows, &mut public_inputs, row);
+    assert_rejected(
+        stark,
+        &rows,
+        &public_inputs,
+        &[
+            ConstraintGroup::APrimeConsistency,
+            ConstraintGroup::APrimeParity,
+        ],
+    );
+}
+
+#[test]
+fn test_forged_a_prime_prime() {
+    let (stark, mut rows, mut public_inputs) = honest();
+    let row = 17;
+    // A''[1, 2] is also the output lane A'''[1, 2].
+    let lv = main_row(&mut rows, row);
+    let lo = lv[reg_a_prime_prime(1, 2)].to_canonical_u64();
+    lv[reg_a_prime_prime(1, 2)] = F::from_canonical_u64(lo ^ 1);
+    regenerate_after(&mut rows, &mut public_inputs, row);
+    assert_rejected(stark, &rows, &public_inputs, &[ConstraintGroup::Chi]);
+}
+
+#[test]
+fn test_forged_a_prime_prime_0_0_bits() {
+    let (stark, mut rows, mut public_inputs) = honest();
+    let row = 9;
+    // Flip one bit of A''[0, 0] and make A'''[0, 0] follow the bits rather than A''[0, 0].
+    let lv = main_row(&mut rows, row);
+    let bit = reg_a_prime_prime_0_0_bit(13);
+    lv[bit] = F::ONE - lv[bit];
+    let limbs = [0, 1].map(|limb| lv[reg_a_prime_prime(0, 0) + limb]);
+    for limb in 0..2 {
+        let from_bits = (0..32).rev().fold(F::ZERO, |acc, i| {
+            acc.double() + lv[reg_a_prime_prime_0_0_bit(32 * limb + i)]
+        });
+        lv[reg_a_prime_prime(0, 0) + limb] = from_bits;
+    }
+    generate_a_prime_prime_prime_0_0(lv, row);
+    for limb in 0..2 {
+        lv[reg_a_prime_prime(0, 0) + limb] = limbs[limb];
+    }
+    regenerate_after(&mut rows, &mut public_inputs, row);
+    assert_rejected(stark, &rows, &public_inputs, &[ConstraintGroup::Iota]);
+}
+
+#[test]
+fn test_non_boolean_a_prime_prime_0_0_bits() {
+    let (stark, mut rows, public_inputs) = honest();
+    let row = 7;
+    // Replace bits (b, 1) by (b + 2, 0) where RC is zero, which preserves both the recomposition
+    // of A''[0, 0] and the xor with RC. Only booleanity rules this out.
+    let lv = main_row(&mut rows, row);
+    let i = (0..63)
+        .find(|&i| {
+            i != 31
+                && lv[reg_a_prime_prime_0_0_bit(i + 1)] == F::ONE
+                && rc_value_bit(row, i) == 0
+                && rc_value_bit(row, i + 1) == 0
+        })
+        .unwrap();
+    lv[reg_a_prime_prime_0_0_bit(i)] += F::TWO;
+    lv[reg_a_prime_prime_0_0_bit(i + 1)] = F::ZERO;
+    assert_rejected(
+        stark,
+        &rows,
+        &public_inputs,
+        &[ConstraintGroup::APrimePrime00Bits],
+    );
+}
+
+#[test]
+fn test_forged_filter() {
+    let (stark, mut rows, public_inputs) = honest();
+    rows[10][REG_FILTER] = F::ONE;
+    assert_rejected(stark, &rows, &public_inputs, &[ConstraintGroup::Filter]);
+
+    let (stark, mut rows, public_inputs) = honest();
+    rows[NUM_ROUNDS - 1][REG_FILTER] = F::TWO;
+    assert_rejected(stark, &rows, &public_inputs, &[ConstraintGroup::Filter]);
+}
+
+#[test]
+fn test_forged_pulse_counter() {
+    let (stark, mut rows, public_inputs) = honest();
+    let row = 10;
+    let counter = rows[row][NUM_COLUMNS] + F::ONE;
+    rows[row][NUM_COLUMNS] = counter;
+    for (i, pos) in [0, NUM_ROUNDS - 1].into_iter().enumerate() {
+        rows[row][get_witness_col(NUM_COLUMNS, i)] =
+            (counter - F::from_canonical_usize(pos)).inverse();
+    }
+    assert_rejected(stark, &rows, &public_inputs, &[ConstraintGroup::Pulse]);
+}
+
+#[test]
+fn test_forged_pulse() {
+    // Turning off the output pulse would unbind the output from the public inputs.
+    let (stark, mut rows, mut public_inputs) = honest();
+    rows[NUM_ROUNDS - 1][get_pulse_col(NUM_COLUMNS, 1)] = F::ZERO;
+    public_inputs[50] += F::ONE;
+    assert_rejected(stark, &rows, &public_inputs, &[ConstraintGroup::Pulse]);
+}
+
+#[test]
+fn test_forged_input() {
+    // Prove the permutation of a different input, claiming the original one.
+    let (stark, mut rows, mut public_inputs) = honest();
+    let lo = rows[0][reg_a(0, 0)].to_canonical_u64();
+    rows[0][reg_a(0, 0)] = F::from_canonical_u64(lo ^ 1);
+    generate_keccak_trace_row_for_round(main_row(&mut rows, 0), 0);
+    regenerate_after(&mut rows, &mut public_inputs, 0);
+    assert_rejected(
+        stark,
+        &rows,
+        &public_inputs,
+        &[ConstraintGroup::InputBinding],
+    );
+}
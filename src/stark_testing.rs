@@ -0,0 +1,305 @@
+use anyhow::{ensure, Result};
+use itertools::Itertools;
+use plonky2::{
+    field::{
+        extension::{Extendable, FieldExtension},
+        polynomial::PolynomialValues,
+        types::{Field, Sample},
+    },
+    hash::hash_types::RichField,
+    iop::witness::{PartialWitness, WitnessWrite},
+    plonk::{circuit_builder::CircuitBuilder, circuit_data::CircuitConfig, config::GenericConfig},
+    util::transpose,
+};
+use starky::{
+    constraint_consumer::{ConstraintConsumer, RecursiveConstraintConsumer},
+    stark::Stark,
+    vars::{StarkEvaluationTargets, StarkEvaluationVars},
+};
+
+/// Number of random challenges used to combine the constraints.
+const NUM_ALPHAS: usize = 2;
+
+/// A point at which the constraints of a stark are evaluated: a pair of consecutive rows, the
+/// public inputs and the values of the selectors used by the constraint consumer.
+#[derive(Clone, Debug)]
+pub struct EvaluationPoint<F: Field> {
+    pub local_values: Vec<F>,
+    pub next_values: Vec<F>,
+    pub public_inputs: Vec<F>,
+    pub z_last: F,
+    pub lagrange_first: F,
+    pub lagrange_last: F,
+}
+
+impl<F: Field> EvaluationPoint<F> {
+    /// A point with random rows, public inputs and selectors.
+    pub fn rand(num_columns: usize, num_public_inputs: usize) -> Self {
+        Self {
+            local_values: F::rand_vec(num_columns),
+            next_values: F::rand_vec(num_columns),
+            public_inputs: F::rand_vec(num_public_inputs),
+            z_last: F::rand(),
+            lagrange_first: F::rand(),
+            lagrange_last: F::rand(),
+        }
+    }
+}
+
+/// The points of an actual trace at the given rows, lifted to the extension field. The selectors
+/// are 0 or 1 as on the trace domain, so first-row, last-row and transition constraints apply
+/// where the prover enforces them.
+pub fn trace_points<F: RichField + Extendable<D>, const D: usize>(
+    trace: &[PolynomialValues<F>],
+    public_inputs: &[F],
+    rows: &[usize],
+) -> Vec<EvaluationPoint<F::Extension>> {
+    let trace_rows = transpose(&trace.iter().map(|col| col.values.clone()).collect_vec());
+    let num_rows = trace_rows.len();
+    let lift = |values: &[F]| {
+        values
+            .iter()
+            .map(|&v| F::Extension::from_basefield(v))
+            .collect_vec()
+    };
+    let indicator = |b: bool| {
+        if b {
+            F::Extension::ONE
+        } else {
+            F::Extension::ZERO
+        }
+    };
+    rows.iter()
+        .map(|&row| {
+            assert!(row < num_rows);
+            EvaluationPoint {
+                local_values: lift(&trace_rows[row]),
+                next_values: lift(&trace_rows[(row + 1) % num_rows]),
+                public_inputs: lift(public_inputs),
+                z_last: indicator(row != num_rows - 1),
+                lagrange_first: indicator(row == 0),
+                lagrange_last: indicator(row == num_rows - 1),
+            }
+        })
+        .collect()
+}
+
+/// Evaluates the constraints of `stark` at `point` with `eval_packed_generic` over the extension
+/// field, combined with each of `alphas`.
+fn eval_native<F, S, const D: usize>(
+    stark: &S,
+    point: &EvaluationPoint<F::Extension>,
+    alphas: &[F],
+) -> Vec<F::Extension>
+where
+    F: RichField + Extendable<D>,
+    S: Stark<F, D>,
+{
+    let vars = StarkEvaluationVars {
+        local_values: point.local_values.as_slice(),
+        next_values: point.next_values.as_slice(),
+        public_inputs: point.public_inputs.as_slice(),
+    };
+    let mut consumer = ConstraintConsumer::<F::Extension>::new(
+        alphas
+            .iter()
+            .map(|&alpha| F::Extension::from_basefield(alpha))
+            .collect_vec(),
+        point.z_last,
+        point.lagrange_first,
+        point.lagrange_last,
+    );
+    stark.eval_packed_generic::<F::Extension, F::Extension, D>(vars, &mut consumer);
+    consumer.accumulators()
+}
+
+/// Checks that `eval_packed_generic` and `eval_ext_circuit` of `stark` agree at every point.
+///
+/// The constraints are combined with random challenges, as in the prover, so a divergence in any
+/// single constraint changes the result except with negligible probability. The circuit side is
+/// checked by proving a circuit that evaluates `eval_ext_circuit` on the point and exposes the
+/// combined constraints as public inputs. The error names the first point where they differ, with
+/// the combined values of both evaluators.
+pub fn check_native_circuit_consistency<F, C, S, const D: usize>(
+    stark: &S,
+    points: &[EvaluationPoint<F::Extension>],
+) -> Result<()>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    S: Stark<F, D>,
+{
+    ensure!(!points.is_empty());
+    let num_columns = points[0].local_values.len();
+    let num_public_inputs = points[0].public_inputs.len();
+    ensure!(points.iter().all(|point| {
+        point.local_values.len() == num_columns
+            && point.next_values.len() == num_columns
+            && point.public_inputs.len() == num_public_inputs
+    }));
+    let alphas = F::rand_vec(NUM_ALPHAS);
+
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let local_values_t = builder.add_virtual_extension_targets(num_columns);
+    let next_values_t = builder.add_virtual_extension_targets(num_columns);
+    let public_inputs_t = builder.add_virtual_extension_targets(num_public_inputs);
+    let z_last_t = builder.add_virtual_extension_target();
+    let lagrange_first_t = builder.add_virtual_extension_target();
+    let lagrange_last_t = builder.add_virtual_extension_target();
+    let vars = StarkEvaluationTargets {
+        local_values: local_values_t.as_slice(),
+        next_values: next_values_t.as_slice(),
+        public_inputs: public_inputs_t.as_slice(),
+    };
+    let zero = builder.zero_extension();
+    let alphas_t = alphas
+        .iter()
+        .map(|&alpha| builder.constant(alpha))
+        .collect_vec();
+    let mut consumer = RecursiveConstraintConsumer::<F, D>::new(
+        zero,
+        alphas_t,
+        z_last_t,
+        lagrange_first_t,
+        lagrange_last_t,
+    );
+    stark.eval_ext_circuit(&mut builder, vars, &mut consumer);
+    for accumulator in consumer.accumulators() {
+        builder.register_public_inputs(&accumulator.to_target_array());
+    }
+    let data = builder.build::<C>();
+
+    for (i, point) in points.iter().enumerate() {
+        let native = eval_native(stark, point, &alphas);
+
+        let mut pw = PartialWitness::new();
+        let values = [
+            (&local_values_t, &point.local_values),
+            (&next_values_t, &point.next_values),
+            (&public_inputs_t, &point.public_inputs),
+        ];
+        for (targets, values) in values {
+            targets
+                .iter()
+                .zip(values.iter())
+                .for_each(|(&t, &v)| pw.set_extension_target(t, v));
+        }
+        pw.set_extension_target(z_last_t, point.z_last);
+        pw.set_extension_target(lagrange_first_t, point.lagrange_first);
+        pw.set_extension_target(lagrange_last_t, point.lagrange_last);
+        let proof = data.prove(pw)?;
+        let circuit = proof
+            .public_inputs
+            .chunks(D)
+            .map(|limbs| F::Extension::from_basefield_array(limbs.try_into().unwrap()))
+            .collect_vec();
+
+        ensure!(
+            native == circuit,
+            "native and circuit constraints diverge at point {}: combined with alphas {:?}, they \
+             are {:?} natively and {:?} in the circuit",
+            i,
+            alphas,
+            native,
+            circuit
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::marker::PhantomData;
+
+    use anyhow::Result;
+    use itertools::Itertools;
+    use plonky2::field::extension::{Extendable, FieldExtension};
+    use plonky2::field::packed::PackedField;
+    use plonky2::hash::hash_types::RichField;
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+    use starky::constraint_consumer::{ConstraintConsumer, RecursiveConstraintConsumer};
+    use starky::stark::Stark;
+    use starky::vars::{StarkEvaluationTargets, StarkEvaluationVars};
+
+    use crate::keccak_chain_stark::{chain_input, KeccakChainStark};
+    use crate::keccak_stark::KeccakStark;
+    use crate::stark_testing::{check_native_circuit_consistency, trace_points, EvaluationPoint};
+    use crate::types::{Digest256, KeccakState};
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_keccak_stark_consistency() -> Result<()> {
+        let num_io = 2;
+        let inputs = (0..num_io)
+            .map(|_| KeccakState(rand::random()))
+            .collect_vec();
+        let outputs = inputs.iter().map(|input| input.permute()).collect_vec();
+        let stark = KeccakStark::<F, D>::new(num_io);
+        let trace = stark.generate_trace(inputs.clone(), 8);
+        let public_inputs = stark.generate_public_inputs(inputs, outputs);
+
+        let num_rows = trace[0].len();
+        let mut points = trace_points(&trace, &public_inputs, &[0, 23, 24, 47, num_rows - 1]);
+        points.push(EvaluationPoint::rand(trace.len(), public_inputs.len()));
+        check_native_circuit_consistency::<F, C, _, D>(&stark, &points)
+    }
+
+    #[test]
+    fn test_keccak_chain_stark_consistency() -> Result<()> {
+        let num_perms = 2;
+        let stark = KeccakChainStark::<F, D>::new(num_perms);
+        let input = chain_input(Digest256(rand::random()));
+        let (trace, output) = stark.generate_trace(input, 8);
+        let public_inputs = stark.generate_public_inputs(input, output);
+
+        let num_rows = trace[0].len();
+        let mut points = trace_points(&trace, &public_inputs, &[0, 23, 47, num_rows - 1]);
+        points.push(EvaluationPoint::rand(trace.len(), public_inputs.len()));
+        check_native_circuit_consistency::<F, C, _, D>(&stark, &points)
+    }
+
+    /// A one-column stark whose circuit constraint differs from the native one.
+    #[derive(Copy, Clone)]
+    struct DivergentStark<F, const D: usize> {
+        f: PhantomData<F>,
+    }
+
+    impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for DivergentStark<F, D> {
+        fn eval_packed_generic<FE, P, const D2: usize>(
+            &self,
+            vars: StarkEvaluationVars<FE, P>,
+            yield_constr: &mut ConstraintConsumer<P>,
+        ) where
+            FE: FieldExtension<D2, BaseField = F>,
+            P: PackedField<Scalar = FE>,
+        {
+            yield_constr.constraint(vars.local_values[0] - P::ONES);
+        }
+
+        fn eval_ext_circuit(
+            &self,
+            builder: &mut CircuitBuilder<F, D>,
+            vars: StarkEvaluationTargets<D>,
+            yield_constr: &mut RecursiveConstraintConsumer<F, D>,
+        ) {
+            yield_constr.constraint(builder, vars.local_values[0]);
+        }
+
+        fn constraint_degree(&self) -> usize {
+            1
+        }
+    }
+
+    #[test]
+    fn test_divergence_is_detected() {
+        let stark = DivergentStark::<F, D> { f: PhantomData };
+        let points = vec![EvaluationPoint::rand(1, 0)];
+        let err = check_native_circuit_consistency::<F, C, _, D>(&stark, &points).unwrap_err();
+        assert!(err.to_string().contains("at point 0:"), "{}", err);
+    }
+}
@@ -0,0 +1,292 @@
+//! Storage slots of Solidity mappings and dynamic arrays, natively and in a circuit.
+//!
+//! The value of `mapping` at `key`, where `mapping` is declared at `slot`, lives at
+//! `keccak256(key ‖ slot)`, both as 32-byte big-endian words, except that `string` and `bytes`
+//! keys are hashed unpadded. A nested mapping applies this once per key, outermost first. Element
+//! `index` of a dynamic array declared at `slot` lives at `keccak256(slot) + index` for elements
+//! that take one slot; multiply the index by the number of slots of an element otherwise.
+//!
+//! In circuits a 256-bit word is 32 byte targets, most significant first. The functions
+//! range-check the bytes they are given to 8 bits; the bytes they return are range-checked by
+//! construction. The returned statements must be verified with
+//! `verify_keccak_statements_circuit`.
+
+use plonky2::{
+    field::{extension::Extendable, types::Field},
+    hash::hash_types::RichField,
+    iop::target::Target,
+    plonk::circuit_builder::CircuitBuilder,
+};
+use primitive_types::U256;
+
+use crate::keccak256_circuit::{bytes_to_words, keccak256_with_byte_len};
+use crate::types::{KeccakState, KeccakStateTarget};
+use crate::u32_keccak256_circuit::keccak256_circuit_checked_bytes;
+
+fn to_be_bytes(x: U256) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    x.to_big_endian(&mut bytes);
+    bytes
+}
+
+fn keccak256_u256(input: &[u8], statements: &mut Vec<(KeccakState, KeccakState)>) -> U256 {
+    let (digest, perms) = keccak256_with_byte_len(bytes_to_words(input), input.len());
+    statements.extend(perms);
+    digest.to_u256()
+}
+
+/// The slot of `mapping[key]` for a value-type key, with the permutation statements of its
+/// derivation. Addresses and other types narrower than 32 bytes are zero-extended, as
+/// `abi.encode` does.
+pub fn mapping_slot(key: U256, slot: U256) -> (U256, Vec<(KeccakState, KeccakState)>) {
+    nested_mapping_slot(&[key], slot)
+}
+
+/// The slot of `mapping[key]` for a `string` or `bytes` key.
+pub fn mapping_slot_with_bytes_key(
+    key: &[u8],
+    slot: U256,
+) -> (U256, Vec<(KeccakState, KeccakState)>) {
+    let mut statements = vec![];
+    let output = keccak256_u256(&[key, &to_be_bytes(slot)].concat(), &mut statements);
+    (output, statements)
+}
+
+/// The slot of `mapping[keys[0]][keys[1]]...`.
+pub fn nested_mapping_slot(keys: &[U256], slot: U256) -> (U256, Vec<(KeccakState, KeccakState)>) {
+    let mut statements = vec![];
+    let output = keys.iter().fold(slot, |slot, &key| {
+        let input = [to_be_bytes(key), to_be_bytes(slot)].concat();
+        keccak256_u256(&input, &mut statements)
+    });
+    (output, statements)
+}
+
+/// The slot of `array[index]`. Slots wrap around modulo 2^256, as in the EVM.
+pub fn dynamic_array_slot(slot: U256, index: U256) -> (U256, Vec<(KeccakState, KeccakState)>) {
+    let mut statements = vec![];
+    let base = keccak256_u256(&to_be_bytes(slot), &mut statements);
+    (base.overflowing_add(index).0, statements)
+}
+
+/// A constant 256-bit word as 32 big-endian byte targets.
+pub fn constant_u256_be<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    x: U256,
+) -> [Target; 32] {
+    to_be_bytes(x).map(|byte| builder.constant(F::from_canonical_u8(byte)))
+}
+
+fn range_check_bytes<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    bytes: &[Target],
+) {
+    for &byte in bytes {
+        builder.range_check(byte, 8);
+    }
+}
+
+/// `x + y` modulo 2^256 for range-checked big-endian bytes.
+fn add_u256_be_circuit<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    x: [Target; 32],
+    y: [Target; 32],
+) -> [Target; 32] {
+    let mut sum = [builder.zero(); 32];
+    let mut carry = builder.zero();
+    for i in (0..32).rev() {
+        let s = builder.add_many([x[i], y[i], carry]);
+        let (lo, hi) = builder.split_low_high(s, 8, 9);
+        sum[i] = lo;
+        carry = hi;
+    }
+    sum
+}
+
+/// The circuit version of `mapping_slot`.
+pub fn mapping_slot_circuit<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    key: [Target; 32],
+    slot: [Target; 32],
+) -> ([Target; 32], Vec<(KeccakStateTarget, KeccakStateTarget)>) {
+    nested_mapping_slot_circuit(builder, &[key], slot)
+}
+
+/// The circuit version of `mapping_slot_with_bytes_key`.
+pub fn mapping_slot_with_bytes_key_circuit<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    key: &[Target],
+    slot: [Target; 32],
+) -> ([Target; 32], Vec<(KeccakStateTarget, KeccakStateTarget)>) {
+    range_check_bytes(builder, key);
+    range_check_bytes(builder, &slot);
+    let mut statements = vec![];
+    let input = [key, &slot].concat();
+    let output = keccak256_circuit_checked_bytes(builder, &input, &mut statements);
+    (output, statements)
+}
+
+/// The circuit version of `nested_mapping_slot`.
+pub fn nested_mapping_slot_circuit<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    keys: &[[Target; 32]],
+    slot: [Target; 32],
+) -> ([Target; 32], Vec<(KeccakStateTarget, KeccakStateTarget)>) {
+    range_check_bytes(builder, &slot);
+    let mut statements = vec![];
+    let output = keys.iter().fold(slot, |slot, key| {
+        range_check_bytes(builder, key);
+        let input = [*key, slot].concat();
+        keccak256_circuit_checked_bytes(builder, &input, &mut statements)
+    });
+    (output, statements)
+}
+
+/// The circuit version of `dynamic_array_slot`.
+pub fn dynamic_array_slot_circuit<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    slot: [Target; 32],
+    index: [Target; 32],
+) -> ([Target; 32], Vec<(KeccakStateTarget, KeccakStateTarget)>) {
+    range_check_bytes(builder, &slot);
+    range_check_bytes(builder, &index);
+    let mut statements = vec![];
+    let base = keccak256_circuit_checked_bytes(builder, &slot, &mut statements);
+    (add_u256_be_circuit(builder, base, index), statements)
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::CircuitConfig,
+        config::{GenericConfig, PoseidonGoldilocksConfig},
+    };
+    use primitive_types::U256;
+    use starky::recursive_verifier::set_stark_proof_with_pis_target;
+
+    use crate::keccak256_circuit::{prove_keccak_statements, verify_keccak_statements_circuit};
+    use crate::storage_slot::{
+        constant_u256_be, dynamic_array_slot, dynamic_array_slot_circuit, mapping_slot,
+        mapping_slot_circuit, mapping_slot_with_bytes_key, mapping_slot_with_bytes_key_circuit,
+        nested_mapping_slot, nested_mapping_slot_circuit, to_be_bytes,
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    fn u256(s: &str) -> U256 {
+        U256::from_str_radix(s, 16).unwrap()
+    }
+
+    /// A USDT holder, from a state override of the `balances` mapping in alloy-rpc-types-eth's
+    /// `state.rs` tests.
+    const HOLDER: &str = "1b5212af6b76113afd94cd2b5a78a73b7d7a8222";
+    /// The owner and operator of a `setApprovalForAll` call in the geth struct-log trace
+    /// `structlogs_01.json` of alloy-rpc-types-trace.
+    const OWNER: &str = "a7194f8a5f509ed2c95ade0b4efb6940a45d7a11";
+    const OPERATOR: &str = "00000000000111abe46ff893f3b2fdf1f759a8a8";
+    /// An account depositing into the ERC-4337 EntryPoint in the ERC-7562 trace of
+    /// alloy-rpc-types-trace's `erc7562.rs` tests.
+    const DEPOSITOR: &str = "8c9d927336adc963536122f8e0d269319e79ed7a";
+    /// `keccak256(uint256(7)) + WRAP_INDEX` wraps around to 3.
+    const WRAP_INDEX: &str = "599336d74a1247d50642b66dd6abeaa5484f6bd96b415b31bb99e26578c9397b";
+
+    /// The slots of `test_storage_slots`. The first three are the well-known
+    /// `keccak256(uint256(0))`, `keccak256(uint256(1))` and `keccak256(uint256(0), uint256(0))`;
+    /// the next four were read off chain data, as noted in the test.
+    const KNOWN_SLOTS: [&str; 8] = [
+        "290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563",
+        "b10e2d527612073b26eecdfd717e6a320cf44b4afac2b0732d9fcbe2b7fa0cf6",
+        "ad3228b676f7d3cd4284a5443f17f1962b36e491b30a40b2405849e597ba5fb5",
+        "ede27e4e7f3676edbf125879f17a896d6507958df3d57bda6219f1880cae8a41",
+        "6693dabf5ec7ab1a0d1c5bc58451f85d5e44d504c9ffeb75799bfdb61aa2997a",
+        "e18cacf0f1fc038f916461d29104232bd93d822359dfc137927858cc71f1e4ed",
+        "405787fa12a823e0f2b7631cc41b3ba8828b3321ca811111fa75cd3aa3bb5ad2",
+        "0000000000000000000000000000000000000000000000000000000000000003",
+    ];
+
+    #[test]
+    fn test_storage_slots() {
+        let slots = [
+            // uint256[] at slot 0, element 0
+            dynamic_array_slot(0.into(), 0.into()),
+            // uint256[] at slot 1, element 0
+            dynamic_array_slot(1.into(), 0.into()),
+            // mapping(uint256 => uint256) at slot 0, key 0
+            mapping_slot(0.into(), 0.into()),
+            // USDT (0xdAC17F958D2ee523a2206206994597C13D831ec7) `balances` at slot 2
+            mapping_slot(u256(HOLDER), 2.into()),
+            // ERC-721 `_operatorApprovals` at slot 5: the key of the trace's SSTORE
+            nested_mapping_slot(&[u256(OWNER), u256(OPERATOR)], 5.into()),
+            // EntryPoint `deposits` at slot 0: the key written with the 2 ether deposit
+            mapping_slot(u256(DEPOSITOR), 0.into()),
+            // uint256[] at slot 2, element 4: a storage key of contract
+            // 0x201d43c399f2495e19a591eab93fa3384ec6c72e in alloy-rpc-types-debug's state dump
+            dynamic_array_slot(2.into(), 4.into()),
+            // uint256[] at slot 7, at an index where the slot wraps around
+            dynamic_array_slot(7.into(), u256(WRAP_INDEX)),
+        ];
+        for ((slot, statements), expected) in slots.into_iter().zip(KNOWN_SLOTS) {
+            assert_eq!(slot, u256(expected));
+            assert!(!statements.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_storage_slots_circuit() {
+        let (balance_slot, balance_statements) = mapping_slot(u256(HOLDER), 2.into());
+        let (approval_slot, approval_statements) =
+            nested_mapping_slot(&[u256(OWNER), u256(OPERATOR)], 5.into());
+        let (name_slot, name_statements) = mapping_slot_with_bytes_key(b"abc", 1.into());
+        let (array_slot, array_statements) = dynamic_array_slot(7.into(), u256(WRAP_INDEX));
+        let statements = [
+            balance_statements,
+            approval_statements,
+            name_statements,
+            array_statements,
+        ]
+        .concat();
+
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let holder = constant_u256_be(&mut builder, u256(HOLDER));
+        let owner = constant_u256_be(&mut builder, u256(OWNER));
+        let operator = constant_u256_be(&mut builder, u256(OPERATOR));
+        let name = b"abc".map(|byte| builder.constant(F::from_canonical_u8(byte)));
+        let index = constant_u256_be(&mut builder, u256(WRAP_INDEX));
+        let slots = [1u64, 2, 5, 7].map(|slot| constant_u256_be(&mut builder, slot.into()));
+
+        let derived = [
+            mapping_slot_circuit(&mut builder, holder, slots[1]),
+            nested_mapping_slot_circuit(&mut builder, &[owner, operator], slots[2]),
+            mapping_slot_with_bytes_key_circuit(&mut builder, &name, slots[0]),
+            dynamic_array_slot_circuit(&mut builder, slots[3], index),
+        ];
+        let mut statements_t = vec![];
+        for ((slot_t, perms_t), expected) in
+            derived
+                .into_iter()
+                .zip([balance_slot, approval_slot, name_slot, array_slot])
+        {
+            statements_t.extend(perms_t);
+            for (byte_t, byte) in slot_t.into_iter().zip(to_be_bytes(expected)) {
+                let byte = builder.constant(F::from_canonical_u8(byte));
+                builder.connect(byte_t, byte);
+            }
+        }
+        let stark_proof_t = verify_keccak_statements_circuit(&mut builder, &statements_t);
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        set_stark_proof_with_pis_target(
+            &mut pw,
+            &stark_proof_t,
+            &prove_keccak_statements(&statements),
+        );
+        data.verify(data.prove(pw).unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,461 @@
+//! Typed Keccak sponge states and keccak256 digests, and their circuit counterparts.
+//!
+//! A `KeccakState` holds the 25 lanes of a Keccak-f[1600] state, lane `x + 5y` at index
+//! `x + 5 * y`. Wherever a state is a list of field elements (the `KeccakStark` trace and public
+//! inputs, `KeccakStateTarget`) each lane is split into two u32 limbs, low limb first. A
+//! `Digest256` is the first 32 bytes of a squeezed state, which are also its first 8 limbs.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use anyhow::{ensure, Result};
+use plonky2::{
+    field::{
+        extension::Extendable,
+        types::{Field, PrimeField64},
+    },
+    hash::hash_types::RichField,
+    iop::{target::Target, witness::WitnessWrite},
+    plonk::circuit_builder::CircuitBuilder,
+};
+use primitive_types::U256;
+use tiny_keccak::keccakf;
+
+use crate::keccak_stark::NUM_INPUTS;
+
+/// The number of u32 limbs of a `KeccakState`.
+pub const NUM_STATE_LIMBS: usize = 2 * NUM_INPUTS;
+
+/// The number of u32 words of a `Digest256`.
+pub const NUM_DIGEST_WORDS: usize = 8;
+
+/// `0x`-prefixed lowercase hex of `bytes`.
+pub fn encode_hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut s = String::with_capacity(2 + 2 * bytes.len());
+    s.push_str("0x");
+    for &b in bytes {
+        s.push(DIGITS[(b >> 4) as usize] as char);
+        s.push(DIGITS[(b & 0xf) as usize] as char);
+    }
+    s
+}
+
+/// Decodes hex with an optional `0x` prefix into exactly `out.len()` bytes.
+pub fn decode_hex(s: &str, out: &mut [u8]) -> Result<()> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    ensure!(
+        s.len() == 2 * out.len(),
+        "expected {} hex digits, got {}",
+        2 * out.len(),
+        s.len()
+    );
+    for (byte, pair) in out.iter_mut().zip(s.as_bytes().chunks(2)) {
+        let digit = |c: u8| -> Result<u8> {
+            match c {
+                b'0'..=b'9' => Ok(c - b'0'),
+                b'a'..=b'f' => Ok(c - b'a' + 10),
+                b'A'..=b'F' => Ok(c - b'A' + 10),
+                _ => Err(anyhow::anyhow!("invalid hex digit {:?}", c as char)),
+            }
+        };
+        *byte = (digit(pair[0])? << 4) | digit(pair[1])?;
+    }
+    Ok(())
+}
+
+/// A public input that must be a u32, such as a word of a message or digest.
+pub(crate) fn to_u32<F: PrimeField64>(x: &F) -> Result<u32> {
+    let x = x.to_canonical_u64();
+    ensure!(x < 1 << 32, "public input {} is not a u32", x);
+    Ok(x as u32)
+}
+
+/// A Keccak-f[1600] state.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct KeccakState(pub [u64; NUM_INPUTS]);
+
+impl KeccakState {
+    pub const ZERO: Self = Self([0; NUM_INPUTS]);
+
+    pub fn from_lanes(lanes: [u64; NUM_INPUTS]) -> Self {
+        Self(lanes)
+    }
+
+    pub fn lanes(&self) -> [u64; NUM_INPUTS] {
+        self.0
+    }
+
+    pub fn from_limbs(limbs: [u32; NUM_STATE_LIMBS]) -> Self {
+        Self(core::array::from_fn(|i| {
+            limbs[2 * i] as u64 | ((limbs[2 * i + 1] as u64) << 32)
+        }))
+    }
+
+    /// The u32 limbs of the lanes, low limb first.
+    pub fn limbs(&self) -> [u32; NUM_STATE_LIMBS] {
+        core::array::from_fn(|i| (self.0[i / 2] >> (32 * (i % 2))) as u32)
+    }
+
+    /// The state as little-endian bytes, as absorbed by the sponge.
+    pub fn from_bytes(bytes: [u8; 8 * NUM_INPUTS]) -> Self {
+        Self(core::array::from_fn(|i| {
+            u64::from_le_bytes(bytes[8 * i..8 * i + 8].try_into().unwrap())
+        }))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 8 * NUM_INPUTS] {
+        let mut bytes = [0u8; 8 * NUM_INPUTS];
+        for (chunk, lane) in bytes.chunks_mut(8).zip(self.0.iter()) {
+            chunk.copy_from_slice(&lane.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_hex(s: &str) -> Result<Self> {
+        let mut bytes = [0u8; 8 * NUM_INPUTS];
+        decode_hex(s, &mut bytes)?;
+        Ok(Self::from_bytes(bytes))
+    }
+
+    /// `0x`-prefixed hex of `to_bytes`.
+    pub fn to_hex(&self) -> String {
+        encode_hex(&self.to_bytes())
+    }
+
+    pub fn from_field_elements<F: PrimeField64>(elements: &[F]) -> Result<Self> {
+        ensure!(
+            elements.len() == NUM_STATE_LIMBS,
+            "a state has {} limbs, got {}",
+            NUM_STATE_LIMBS,
+            elements.len()
+        );
+        let mut limbs = [0u32; NUM_STATE_LIMBS];
+        for (limb, x) in limbs.iter_mut().zip(elements.iter()) {
+            *limb = to_u32(x)?;
+        }
+        Ok(Self::from_limbs(limbs))
+    }
+
+    pub fn to_field_elements<F: Field>(&self) -> [F; NUM_STATE_LIMBS] {
+        self.limbs().map(F::from_canonical_u32)
+    }
+
+    /// Applies Keccak-f[1600].
+    pub fn permute(&self) -> Self {
+        let mut lanes = self.0;
+        keccakf(&mut lanes);
+        Self(lanes)
+    }
+
+    /// The keccak256 digest squeezed from this state.
+    pub fn digest(&self) -> Digest256 {
+        Digest256::from_lanes(self.0[0..4].try_into().unwrap())
+    }
+}
+
+/// A keccak256 digest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Digest256(pub [u8; 32]);
+
+impl Digest256 {
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+
+    /// The little-endian u32 words of the digest, as in the public inputs of the keccak256
+    /// circuits.
+    pub fn from_words(words: [u32; NUM_DIGEST_WORDS]) -> Self {
+        let mut bytes = [0u8; 32];
+        for (chunk, word) in bytes.chunks_mut(4).zip(words.iter()) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        Self(bytes)
+    }
+
+    pub fn words(&self) -> [u32; NUM_DIGEST_WORDS] {
+        core::array::from_fn(|i| u32::from_le_bytes(self.0[4 * i..4 * i + 4].try_into().unwrap()))
+    }
+
+    /// The first 4 lanes of a squeezed state.
+    pub fn from_lanes(lanes: [u64; 4]) -> Self {
+        let mut bytes = [0u8; 32];
+        for (chunk, lane) in bytes.chunks_mut(8).zip(lanes.iter()) {
+            chunk.copy_from_slice(&lane.to_le_bytes());
+        }
+        Self(bytes)
+    }
+
+    pub fn lanes(&self) -> [u64; 4] {
+        core::array::from_fn(|i| u64::from_le_bytes(self.0[8 * i..8 * i + 8].try_into().unwrap()))
+    }
+
+    pub fn from_hex(s: &str) -> Result<Self> {
+        let mut bytes = [0u8; 32];
+        decode_hex(s, &mut bytes)?;
+        Ok(Self(bytes))
+    }
+
+    /// `0x`-prefixed hex of the digest bytes, as printed by Ethereum tooling.
+    pub fn to_hex(&self) -> String {
+        encode_hex(&self.0)
+    }
+
+    /// Reads the digest as a big-endian 256-bit integer, as the EVM does.
+    pub fn to_u256(&self) -> U256 {
+        U256::from_big_endian(&self.0)
+    }
+
+    pub fn from_u256(x: U256) -> Self {
+        let mut bytes = [0u8; 32];
+        x.to_big_endian(&mut bytes);
+        Self(bytes)
+    }
+
+    pub fn from_field_elements<F: PrimeField64>(elements: &[F]) -> Result<Self> {
+        ensure!(
+            elements.len() == NUM_DIGEST_WORDS,
+            "a digest has {} words, got {}",
+            NUM_DIGEST_WORDS,
+            elements.len()
+        );
+        let mut words = [0u32; NUM_DIGEST_WORDS];
+        for (word, x) in words.iter_mut().zip(elements.iter()) {
+            *word = to_u32(x)?;
+        }
+        Ok(Self::from_words(words))
+    }
+
+    pub fn to_field_elements<F: Field>(&self) -> [F; NUM_DIGEST_WORDS] {
+        self.words().map(F::from_canonical_u32)
+    }
+}
+
+impl fmt::Display for Digest256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl From<[u8; 32]> for Digest256 {
+    fn from(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<Digest256> for [u8; 32] {
+    fn from(digest: Digest256) -> Self {
+        digest.0
+    }
+}
+
+/// The input and output state of each permutation, as a `KeccakStark` proves them.
+pub fn statements_to_field_elements<F: Field>(statements: &[(KeccakState, KeccakState)]) -> Vec<F> {
+    statements
+        .iter()
+        .flat_map(|(input, output)| {
+            input
+                .to_field_elements::<F>()
+                .into_iter()
+                .chain(output.to_field_elements::<F>())
+        })
+        .collect()
+}
+
+/// The inverse of `statements_to_field_elements`. Fails if an element is not a u32.
+pub fn statements_from_field_elements<F: PrimeField64>(
+    elements: &[F],
+) -> Result<Vec<(KeccakState, KeccakState)>> {
+    ensure!(
+        elements.len() % (2 * NUM_STATE_LIMBS) == 0,
+        "the public inputs are not a whole number of permutations"
+    );
+    elements
+        .chunks(2 * NUM_STATE_LIMBS)
+        .map(|chunk| {
+            Ok((
+                KeccakState::from_field_elements(&chunk[..NUM_STATE_LIMBS])?,
+                KeccakState::from_field_elements(&chunk[NUM_STATE_LIMBS..])?,
+            ))
+        })
+        .collect()
+}
+
+/// A `KeccakState` in a circuit, as its u32 limbs, low limb first. The limbs are not
+/// range-checked: they are meant to be connected to the public inputs of a `KeccakStark` proof,
+/// which constrains them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeccakStateTarget(pub [Target; NUM_STATE_LIMBS]);
+
+impl KeccakStateTarget {
+    pub fn new_virtual<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        Self(builder.add_virtual_target_arr())
+    }
+
+    pub fn constant<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        value: KeccakState,
+    ) -> Self {
+        Self(
+            value
+                .limbs()
+                .map(|x| builder.constant(F::from_canonical_u32(x))),
+        )
+    }
+
+    /// The targets of a state within a list of targets, such as the public inputs of a
+    /// `KeccakStark` proof.
+    pub fn from_targets(targets: &[Target]) -> Self {
+        Self(targets.try_into().unwrap())
+    }
+
+    pub fn targets(&self) -> &[Target] {
+        &self.0
+    }
+
+    /// The digest squeezed from this state: its first 8 limbs.
+    pub fn digest(&self) -> Digest256Target {
+        Digest256Target(self.0[0..NUM_DIGEST_WORDS].try_into().unwrap())
+    }
+
+    pub fn set_witness<F: Field>(&self, witness: &mut impl WitnessWrite<F>, value: KeccakState) {
+        for (&t, x) in self.0.iter().zip(value.to_field_elements::<F>()) {
+            witness.set_target(t, x);
+        }
+    }
+
+    pub fn connect<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        a: Self,
+        b: Self,
+    ) {
+        for (x, y) in a.0.into_iter().zip(b.0) {
+            builder.connect(x, y);
+        }
+    }
+
+    pub fn register_public_inputs<F: RichField + Extendable<D>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) {
+        builder.register_public_inputs(&self.0);
+    }
+}
+
+/// A `Digest256` in a circuit, as its little-endian u32 words. Like `KeccakStateTarget`, the words
+/// are not range-checked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Digest256Target(pub [Target; NUM_DIGEST_WORDS]);
+
+impl Digest256Target {
+    pub fn new_virtual<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        Self(builder.add_virtual_target_arr())
+    }
+
+    pub fn constant<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        value: Digest256,
+    ) -> Self {
+        Self(
+            value
+                .words()
+                .map(|x| builder.constant(F::from_canonical_u32(x))),
+        )
+    }
+
+    pub fn targets(&self) -> &[Target] {
+        &self.0
+    }
+
+    pub fn set_witness<F: Field>(&self, witness: &mut impl WitnessWrite<F>, value: Digest256) {
+        for (&t, x) in self.0.iter().zip(value.to_field_elements::<F>()) {
+            witness.set_target(t, x);
+        }
+    }
+
+    pub fn connect<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        a: Self,
+        b: Self,
+    ) {
+        for (x, y) in a.0.into_iter().zip(b.0) {
+            builder.connect(x, y);
+        }
+    }
+
+    pub fn register_public_inputs<F: RichField + Extendable<D>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) {
+        builder.register_public_inputs(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use primitive_types::U256;
+    use tiny_keccak::{Hasher, Keccak};
+
+    use crate::types::{statements_from_field_elements, statements_to_field_elements};
+    use crate::types::{Digest256, KeccakState};
+
+    type F = GoldilocksField;
+
+    #[test]
+    fn test_conversions() {
+        let state = KeccakState(rand::random());
+        assert_eq!(KeccakState::from_limbs(state.limbs()), state);
+        assert_eq!(KeccakState::from_bytes(state.to_bytes()), state);
+        assert_eq!(KeccakState::from_hex(&state.to_hex()).unwrap(), state);
+        assert_eq!(state.limbs()[0], state.0[0] as u32);
+        assert_eq!(state.limbs()[1], (state.0[0] >> 32) as u32);
+
+        let statements = [(state, state.permute()), (KeccakState::ZERO, state)];
+        let elements = statements_to_field_elements::<F>(&statements);
+        assert_eq!(elements.len(), 2 * 2 * 50);
+        assert_eq!(
+            statements_from_field_elements(&elements).unwrap(),
+            statements
+        );
+        assert!(statements_from_field_elements(&elements[1..]).is_err());
+
+        let digest = Digest256(rand::random());
+        assert_eq!(Digest256::from_words(digest.words()), digest);
+        assert_eq!(Digest256::from_lanes(digest.lanes()), digest);
+        assert_eq!(Digest256::from_hex(&digest.to_hex()).unwrap(), digest);
+        assert_eq!(Digest256::from_u256(digest.to_u256()), digest);
+        assert!(Digest256::from_hex("0x12").is_err());
+        assert!(Digest256::from_hex(&"zz".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn test_digest_of_state() {
+        // The state after absorbing the empty message is the padded block.
+        let mut block = [0u8; 200];
+        block[0] = 0x01;
+        block[135] = 0x80;
+        let digest = KeccakState::from_bytes(block).permute().digest();
+
+        let mut expected = [0u8; 32];
+        let mut hasher = Keccak::v256();
+        hasher.update(&[]);
+        hasher.finalize(&mut expected);
+        assert_eq!(digest, Digest256(expected));
+        assert_eq!(
+            digest.to_hex(),
+            "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        assert_eq!(digest.to_u256(), U256::from_big_endian(&expected));
+        assert_eq!(digest.to_u256().byte(31), 0xc5);
+    }
+}
@@ -0,0 +1,253 @@
+//! keccak256 over `U32Target` words and byte targets, so that the circuits compose with the
+//! gadgets of the plonky2_u32 ecosystem (ECDSA, BigUint, RLP, ...) without glue.
+//!
+//! Every message word enters the sponge through a `U32XorGate`, which decomposes both operands
+//! into bits, so absorbing a word range-checks it to 32 bits. That is the only 32-bit check a
+//! word gets: raw targets need no separate check and can be wrapped as `U32Target`s as they are.
+//! The unused bytes of a partial last word are checked once more, since the XOR does not cover
+//! them. Bytes are checked to 8 bits before they are packed, because a 32-bit check of the packed
+//! word would let a byte overflow into the next one. The digest words are limbs of the
+//! `KeccakStark` public inputs, which the stark constrains to 32 bits, so they are valid
+//! `U32Target`s once the statements are verified with `verify_keccak_statements_circuit`.
+
+use itertools::Itertools;
+use plonky2::{
+    field::{extension::Extendable, types::Field},
+    hash::hash_types::RichField,
+    iop::target::Target,
+    plonk::circuit_builder::CircuitBuilder,
+};
+use plonky2_u32::gadgets::arithmetic_u32::U32Target;
+
+use crate::keccak256_circuit::keccak256_circuit_inner;
+use crate::types::{Digest256Target, KeccakStateTarget, NUM_DIGEST_WORDS};
+
+/// Packs bytes into little-endian words, the last one zero-extended, as `bytes_to_words` does.
+/// The bytes must already be range-checked to 8 bits.
+pub fn bytes_to_u32_words<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    bytes: &[Target],
+) -> Vec<U32Target> {
+    let zero = builder.zero();
+    let base = F::from_canonical_u32(1 << 8);
+    bytes
+        .chunks(4)
+        .map(|chunk| {
+            let word = chunk
+                .iter()
+                .rev()
+                .fold(zero, |acc, &byte| builder.mul_const_add(base, acc, byte));
+            U32Target(word)
+        })
+        .collect()
+}
+
+/// The little-endian bytes of the words. Each byte is range-checked by the decomposition.
+pub fn u32_words_to_bytes<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    words: &[U32Target],
+) -> Vec<Target> {
+    words
+        .iter()
+        .flat_map(|word| {
+            let bits = builder.split_le(word.0, 32);
+            bits.chunks(8)
+                .map(|byte_bits| builder.le_sum(byte_bits.iter()))
+                .collect_vec()
+        })
+        .collect()
+}
+
+pub fn digest_to_u32_targets(digest: Digest256Target) -> [U32Target; NUM_DIGEST_WORDS] {
+    digest.0.map(U32Target)
+}
+
+pub fn digest_from_u32_targets(words: [U32Target; NUM_DIGEST_WORDS]) -> Digest256Target {
+    Digest256Target(words.map(|word| word.0))
+}
+
+/// keccak256 of a message of `num_bytes` bytes given as `U32Target` words, as
+/// `keccak256_circuit_with_byte_len`. Absorbing the words range-checks them, so they may be
+/// unchecked targets. The statements must be verified with `verify_keccak_statements_circuit`.
+pub fn keccak256_circuit_u32<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    input: &[U32Target],
+    num_bytes: usize,
+) -> (
+    [U32Target; NUM_DIGEST_WORDS],
+    Vec<(KeccakStateTarget, KeccakStateTarget)>,
+) {
+    let input = input.iter().map(|word| word.0).collect_vec();
+    // The unused bytes of a partial last word are not covered by the XOR, so they are checked.
+    let (output, statements) = keccak256_circuit_inner(builder, input, num_bytes, true);
+    (digest_to_u32_targets(output), statements)
+}
+
+/// keccak256 of a message given as one target per byte. The bytes are range-checked to 8 bits
+/// here, and their packed words by the absorbing XOR.
+pub fn keccak256_circuit_bytes<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    bytes: &[Target],
+) -> (
+    [U32Target; NUM_DIGEST_WORDS],
+    Vec<(KeccakStateTarget, KeccakStateTarget)>,
+) {
+    for &byte in bytes {
+        builder.range_check(byte, 8);
+    }
+    let words = bytes_to_u32_words(builder, bytes)
+        .into_iter()
+        .map(|word| word.0)
+        .collect_vec();
+    let (output, statements) = keccak256_circuit_inner(builder, words, bytes.len(), false);
+    (digest_to_u32_targets(output), statements)
+}
+
+/// keccak256 of bytes that are already range-checked to 8 bits, as 32 byte targets in digest
+/// order. The statements of its permutations are appended to `statements`.
+pub(crate) fn keccak256_circuit_checked_bytes<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    bytes: &[Target],
+    statements: &mut Vec<(KeccakStateTarget, KeccakStateTarget)>,
+) -> [Target; 32] {
+    let words = bytes_to_u32_words(builder, bytes)
+        .into_iter()
+        .map(|word| word.0)
+        .collect_vec();
+    let (digest, perms) = keccak256_circuit_inner(builder, words, bytes.len(), false);
+    statements.extend(perms);
+    u32_words_to_bytes(builder, &digest_to_u32_targets(digest))
+        .try_into()
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CircuitData},
+        config::{GenericConfig, PoseidonGoldilocksConfig},
+    };
+    use plonky2_u32::gadgets::arithmetic_u32::{CircuitBuilderU32, U32Target};
+    use starky::recursive_verifier::set_stark_proof_with_pis_target;
+
+    use crate::keccak256_circuit::{
+        bytes_to_words, keccak256, keccak256_with_byte_len, prove_keccak_statements,
+        verify_keccak_statements_circuit,
+    };
+    use crate::u32_keccak256_circuit::{
+        keccak256_circuit_bytes, keccak256_circuit_u32, u32_words_to_bytes,
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_keccak256_circuit_u32() {
+        // The message words are the outputs of u32 additions.
+        let a: Vec<u32> = (0..20).map(|_| rand::random()).collect();
+        let b: Vec<u32> = (0..20).map(|_| rand::random()).collect();
+        let message = a
+            .iter()
+            .zip(b.iter())
+            .map(|(&x, &y)| x.wrapping_add(y))
+            .collect_vec();
+        let (digest, statements) = keccak256(message.clone());
+
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let message_t = a
+            .iter()
+            .zip(b.iter())
+            .map(|(&x, &y)| {
+                let x = builder.constant_u32(x);
+                let y = builder.constant_u32(y);
+                builder.add_u32(x, y).0
+            })
+            .collect_vec();
+        let (digest_t, statements_t) =
+            keccak256_circuit_u32(&mut builder, &message_t, 4 * message.len());
+        let stark_proof_t = verify_keccak_statements_circuit(&mut builder, &statements_t);
+        for (word_t, word) in digest_t.into_iter().zip(digest.words()) {
+            let expected = builder.constant_u32(word);
+            builder.connect_u32(word_t, expected);
+        }
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        set_stark_proof_with_pis_target(
+            &mut pw,
+            &stark_proof_t,
+            &prove_keccak_statements(&statements),
+        );
+        data.verify(data.prove(pw).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_keccak256_circuit_bytes() {
+        let message: Vec<u8> = (0..37).map(|_| rand::random()).collect();
+        let (digest, statements) = keccak256_with_byte_len(bytes_to_words(&message), 37);
+
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let message_t = message
+            .iter()
+            .map(|&byte| builder.constant(F::from_canonical_u8(byte)))
+            .collect_vec();
+        let (digest_t, statements_t) = keccak256_circuit_bytes(&mut builder, &message_t);
+        let stark_proof_t = verify_keccak_statements_circuit(&mut builder, &statements_t);
+        let digest_bytes_t = u32_words_to_bytes(&mut builder, &digest_t);
+        for (byte_t, byte) in digest_bytes_t.into_iter().zip(digest.0) {
+            let expected = builder.constant(F::from_canonical_u8(byte));
+            builder.connect(byte_t, expected);
+        }
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        set_stark_proof_with_pis_target(
+            &mut pw,
+            &stark_proof_t,
+            &prove_keccak_statements(&statements),
+        );
+        data.verify(data.prove(pw).unwrap()).unwrap();
+    }
+
+    /// The number of `split_le` decompositions, which is how `range_check` checks a target.
+    fn num_range_checks(data: &CircuitData<F, C, D>) -> usize {
+        data.prover_only
+            .generators
+            .iter()
+            .filter(|generator| generator.0.id().contains("WireSplitGenerator"))
+            .count()
+    }
+
+    #[test]
+    fn test_absorption_range_checks() {
+        let config = CircuitConfig::standard_recursion_config();
+
+        // Full words are only checked by the XOR.
+        let mut builder = CircuitBuilder::<F, D>::new(config.clone());
+        let words = builder.add_virtual_targets(34).into_iter().map(U32Target);
+        let (digest_t, _) = keccak256_circuit_u32(&mut builder, &words.collect_vec(), 136);
+        builder.register_public_inputs(&digest_t.map(|word| word.0));
+        assert_eq!(num_range_checks(&builder.build::<C>()), 0);
+
+        // A partial last word has its unused bytes checked.
+        let mut builder = CircuitBuilder::<F, D>::new(config.clone());
+        let words = builder.add_virtual_targets(10).into_iter().map(U32Target);
+        let (digest_t, _) = keccak256_circuit_u32(&mut builder, &words.collect_vec(), 37);
+        builder.register_public_inputs(&digest_t.map(|word| word.0));
+        assert_eq!(num_range_checks(&builder.build::<C>()), 1);
+
+        // Bytes are checked once each, and their packed words only by the XOR.
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let bytes = builder.add_virtual_targets(37);
+        let (digest_t, _) = keccak256_circuit_bytes(&mut builder, &bytes);
+        builder.register_public_inputs(&digest_t.map(|word| word.0));
+        assert_eq!(num_range_checks(&builder.build::<C>()), 37);
+    }
+}
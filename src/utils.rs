@@ -1,3 +1,6 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
 use plonky2::{
     field::{extension::Extendable, packed::PackedField},
     hash::hash_types::RichField,
@@ -12,17 +15,6 @@ use super::{
     keccak_stark::NUM_ROUNDS,
 };
 
-pub fn split_lo_and_hi(input: [u64; 25]) -> [u32; 50] {
-    let mut output = [0u32; 50];
-    for i in 0..25 {
-        let output_lo = (input[i] & 0xFFFFFFFF) as u32;
-        let output_hi = (input[i] >> 32) as u32;
-        output[2 * i] = output_lo;
-        output[2 * i + 1] = output_hi;
-    }
-    output
-}
-
 pub fn read_state<F: Clone + core::fmt::Debug>(lv: &[F], cur_col: &mut usize) -> [F; 50] {
     let state = lv[*cur_col..*cur_col + 50].to_vec();
     *cur_col += 50;
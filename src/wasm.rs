@@ -0,0 +1,10 @@
+use wasm_bindgen::prelude::*;
+
+use crate::keccak256_verifier::verify_keccak256_proof_bytes;
+
+/// Returns whether `proof` proves `digest = keccak256(message)` for the circuit of
+/// `verifier_data`. See `verify_keccak256_proof_bytes` for the encodings.
+#[wasm_bindgen(js_name = verifyKeccak256)]
+pub fn verify_keccak256(verifier_data: &[u8], proof: &[u8], message: &[u8], digest: &[u8]) -> bool {
+    verify_keccak256_proof_bytes(verifier_data, proof, message, digest).is_ok()
+}
@@ -0,0 +1,342 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use plonky2::{
+    field::{
+        extension::Extendable,
+        types::{Field, PrimeField64},
+    },
+    gates::gate::Gate,
+    hash::hash_types::RichField,
+    iop::{
+        ext_target::ExtensionTarget,
+        generator::{GeneratedValues, SimpleGenerator, WitnessGenerator},
+        target::Target,
+        wire::Wire,
+        witness::{PartitionWitness, Witness, WitnessWrite},
+    },
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::CircuitConfig,
+        vars::{EvaluationTargets, EvaluationVars},
+    },
+    util::serialization::{Buffer, IoResult, Read, Write},
+};
+
+const NUM_BITS: usize = 32;
+
+/// A gate computing `z = x ^ y` for several pairs of u32 values per row. Both operands are
+/// decomposed into bits in advice wires, which also range-checks them to 32 bits, and `z` is
+/// recombined from the bitwise xors `a + b - 2ab`. Every constraint has degree 2.
+///
+/// The routed wires of op `i` are `x`, `y` and `z`, then the bits of all ops follow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct U32XorGate {
+    pub num_ops: usize,
+}
+
+impl U32XorGate {
+    pub fn new_from_config(config: &CircuitConfig) -> Self {
+        Self {
+            num_ops: Self::num_ops(config),
+        }
+    }
+
+    /// As many ops as fit in the routed wires for the operands and result, and in all wires for
+    /// the bits.
+    pub(crate) fn num_ops(config: &CircuitConfig) -> usize {
+        let wires_per_op = 3 + 2 * NUM_BITS;
+        (config.num_routed_wires / 3).min(config.num_wires / wires_per_op)
+    }
+
+    pub fn wire_x(&self, i: usize) -> usize {
+        debug_assert!(i < self.num_ops);
+        3 * i
+    }
+
+    pub fn wire_y(&self, i: usize) -> usize {
+        debug_assert!(i < self.num_ops);
+        3 * i + 1
+    }
+
+    pub fn wire_z(&self, i: usize) -> usize {
+        debug_assert!(i < self.num_ops);
+        3 * i + 2
+    }
+
+    /// Bit `j` of `x` in op `i`, least significant first.
+    pub fn wire_x_bit(&self, i: usize, j: usize) -> usize {
+        debug_assert!(j < NUM_BITS);
+        3 * self.num_ops + 2 * NUM_BITS * i + j
+    }
+
+    /// Bit `j` of `y` in op `i`, least significant first.
+    pub fn wire_y_bit(&self, i: usize, j: usize) -> usize {
+        debug_assert!(j < NUM_BITS);
+        3 * self.num_ops + 2 * NUM_BITS * i + NUM_BITS + j
+    }
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> Gate<F, D> for U32XorGate {
+    fn id(&self) -> String {
+        format!("{self:?}")
+    }
+
+    fn serialize(&self, dst: &mut Vec<u8>) -> IoResult<()> {
+        dst.write_usize(self.num_ops)
+    }
+
+    fn deserialize(src: &mut Buffer) -> IoResult<Self> {
+        let num_ops = src.read_usize()?;
+        Ok(Self { num_ops })
+    }
+
+    fn eval_unfiltered(&self, vars: EvaluationVars<F, D>) -> Vec<F::Extension> {
+        let mut constraints = Vec::with_capacity(self.num_constraints());
+        for i in 0..self.num_ops {
+            let mut x_sum = F::Extension::ZERO;
+            let mut y_sum = F::Extension::ZERO;
+            let mut z_sum = F::Extension::ZERO;
+            for j in 0..NUM_BITS {
+                let a = vars.local_wires[self.wire_x_bit(i, j)];
+                let b = vars.local_wires[self.wire_y_bit(i, j)];
+                constraints.push(a * (a - F::Extension::ONE));
+                constraints.push(b * (b - F::Extension::ONE));
+                let base = F::Extension::from_canonical_u64(1 << j);
+                x_sum += a * base;
+                y_sum += b * base;
+                z_sum += (a + b - (a * b).double()) * base;
+            }
+            constraints.push(x_sum - vars.local_wires[self.wire_x(i)]);
+            constraints.push(y_sum - vars.local_wires[self.wire_y(i)]);
+            constraints.push(z_sum - vars.local_wires[self.wire_z(i)]);
+        }
+        constraints
+    }
+
+    fn eval_unfiltered_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        vars: EvaluationTargets<D>,
+    ) -> Vec<ExtensionTarget<D>> {
+        let mut constraints = Vec::with_capacity(self.num_constraints());
+        for i in 0..self.num_ops {
+            let mut x_sum = builder.zero_extension();
+            let mut y_sum = builder.zero_extension();
+            let mut z_sum = builder.zero_extension();
+            for j in 0..NUM_BITS {
+                let a = vars.local_wires[self.wire_x_bit(i, j)];
+                let b = vars.local_wires[self.wire_y_bit(i, j)];
+                constraints.push(builder.mul_sub_extension(a, a, a));
+                constraints.push(builder.mul_sub_extension(b, b, b));
+                let base = F::from_canonical_u64(1 << j);
+                x_sum = builder.mul_const_add_extension(base, a, x_sum);
+                y_sum = builder.mul_const_add_extension(base, b, y_sum);
+                let ab = builder.mul_extension(a, b);
+                let a_plus_b = builder.add_extension(a, b);
+                let xor = builder.mul_const_add_extension(-F::TWO, ab, a_plus_b);
+                z_sum = builder.mul_const_add_extension(base, xor, z_sum);
+            }
+            constraints.push(builder.sub_extension(x_sum, vars.local_wires[self.wire_x(i)]));
+            constraints.push(builder.sub_extension(y_sum, vars.local_wires[self.wire_y(i)]));
+            constraints.push(builder.sub_extension(z_sum, vars.local_wires[self.wire_z(i)]));
+        }
+        constraints
+    }
+
+    fn generators(&self, row: usize, _local_constants: &[F]) -> Vec<Box<dyn WitnessGenerator<F>>> {
+        (0..self.num_ops)
+            .map(|i| {
+                let generator: Box<dyn WitnessGenerator<F>> = Box::new(
+                    U32XorGenerator {
+                        gate: *self,
+                        row,
+                        i,
+                    }
+                    .adapter(),
+                );
+                generator
+            })
+            .collect()
+    }
+
+    fn num_wires(&self) -> usize {
+        self.num_ops * (3 + 2 * NUM_BITS)
+    }
+
+    fn num_constants(&self) -> usize {
+        0
+    }
+
+    fn degree(&self) -> usize {
+        2
+    }
+
+    fn num_constraints(&self) -> usize {
+        self.num_ops * (2 * NUM_BITS + 3)
+    }
+}
+
+/// Fills `z` and the bits of op `i` of a `U32XorGate` from `x` and `y`.
+#[derive(Clone, Debug)]
+pub struct U32XorGenerator {
+    gate: U32XorGate,
+    row: usize,
+    i: usize,
+}
+
+impl U32XorGenerator {
+    fn wire(&self, column: usize) -> Wire {
+        Wire {
+            row: self.row,
+            column,
+        }
+    }
+}
+
+impl<F: RichField> SimpleGenerator<F> for U32XorGenerator {
+    fn id(&self) -> String {
+        "U32XorGenerator".into()
+    }
+
+    fn dependencies(&self) -> Vec<Target> {
+        [self.gate.wire_x(self.i), self.gate.wire_y(self.i)]
+            .into_iter()
+            .map(|column| Target::wire(self.row, column))
+            .collect()
+    }
+
+    fn run_once(&self, witness: &PartitionWitness<F>, out_buffer: &mut GeneratedValues<F>) {
+        let x = witness
+            .get_wire(self.wire(self.gate.wire_x(self.i)))
+            .to_canonical_u64();
+        let y = witness
+            .get_wire(self.wire(self.gate.wire_y(self.i)))
+            .to_canonical_u64();
+        // Operands wider than 32 bits get truncated bits, which the gate then rejects.
+        for j in 0..NUM_BITS {
+            out_buffer.set_wire(
+                self.wire(self.gate.wire_x_bit(self.i, j)),
+                F::from_canonical_u64((x >> j) & 1),
+            );
+            out_buffer.set_wire(
+                self.wire(self.gate.wire_y_bit(self.i, j)),
+                F::from_canonical_u64((y >> j) & 1),
+            );
+        }
+        out_buffer.set_wire(
+            self.wire(self.gate.wire_z(self.i)),
+            F::from_canonical_u64((x ^ y) & 0xFFFFFFFF),
+        );
+    }
+
+    fn serialize(&self, dst: &mut Vec<u8>) -> IoResult<()> {
+        dst.write_usize(self.gate.num_ops)?;
+        dst.write_usize(self.row)?;
+        dst.write_usize(self.i)
+    }
+
+    fn deserialize(src: &mut Buffer) -> IoResult<Self> {
+        let num_ops = src.read_usize()?;
+        let row = src.read_usize()?;
+        let i = src.read_usize()?;
+        Ok(Self {
+            gate: U32XorGate { num_ops },
+            row,
+            i,
+        })
+    }
+}
+
+/// `x ^ y` for u32 values, in a free slot of a `U32XorGate`. Both operands are range-checked to
+/// 32 bits.
+pub fn xor_u32_circuit<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    x: Target,
+    y: Target,
+) -> Target {
+    let gate = U32XorGate::new_from_config(&builder.config);
+    let (row, i) = builder.find_slot(gate, &[], &[]);
+    builder.connect(x, Target::wire(row, gate.wire_x(i)));
+    builder.connect(y, Target::wire(row, gate.wire_y(i)));
+    Target::wire(row, gate.wire_z(i))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use anyhow::Result;
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::gates::gate_testing::{test_eval_fns, test_low_degree};
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CircuitData},
+        config::{GenericConfig, PoseidonGoldilocksConfig},
+    };
+
+    use crate::serialization::{KeccakGateSerializer, KeccakGeneratorSerializer};
+    use crate::xor_gate::{xor_u32_circuit, U32XorGate};
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_u32_xor_gate() -> Result<()> {
+        let gate = U32XorGate::new_from_config(&CircuitConfig::standard_recursion_config());
+        assert_eq!(gate.num_ops, 2);
+        test_low_degree::<GoldilocksField, _, 4>(gate);
+        test_eval_fns::<F, C, _, D>(gate)?;
+
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let pairs = (0..5)
+            .map(|_| (rand::random::<u32>(), rand::random::<u32>()))
+            .collect::<Vec<_>>();
+        let mut pw = PartialWitness::new();
+        for &(x, y) in pairs.iter() {
+            let x_t = builder.add_virtual_target();
+            let y_t = builder.add_virtual_target();
+            let z_t = xor_u32_circuit(&mut builder, x_t, y_t);
+            let expected = builder.constant(F::from_canonical_u32(x ^ y));
+            builder.connect(z_t, expected);
+            pw.set_target(x_t, F::from_canonical_u32(x));
+            pw.set_target(y_t, F::from_canonical_u32(y));
+        }
+        let data = builder.build::<C>();
+        data.verify(data.prove(pw.clone())?)?;
+
+        // The prover data round-trips through the keccak serializers.
+        let generator_serializer = KeccakGeneratorSerializer::<C, D>::default();
+        let bytes = data
+            .to_bytes(&KeccakGateSerializer, &generator_serializer)
+            .unwrap();
+        let data = CircuitData::<F, C, D>::from_bytes(
+            &bytes,
+            &KeccakGateSerializer,
+            &generator_serializer,
+        )
+        .unwrap();
+        data.verify(data.prove(pw)?)?;
+
+        // An operand wider than 32 bits is rejected.
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let x_t = builder.add_virtual_target();
+        let y_t = builder.constant(F::ONE);
+        xor_u32_circuit(&mut builder, x_t, y_t);
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        pw.set_target(x_t, F::from_canonical_u64(1 << 32));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| -> Result<()> {
+            data.verify(data.prove(pw)?)
+        }));
+        assert!(!matches!(result, Ok(Ok(()))));
+        Ok(())
+    }
+}
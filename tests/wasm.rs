@@ -0,0 +1,34 @@
+//! Runs the wasm verifier in a headless wasm runtime. The fixtures are written by a native test,
+//! so run this through `scripts/wasm-test.sh`.
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use starky_keccak::wasm::verify_keccak256;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+macro_rules! fixture {
+    ($name:literal) => {
+        include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/target/wasm-fixtures/",
+            $name
+        ))
+    };
+}
+
+const VERIFIER_DATA: &[u8] = fixture!("abc.verifier_data");
+const PROOF: &[u8] = fixture!("abc.proof");
+const MESSAGE: &[u8] = fixture!("abc.message");
+const DIGEST: &[u8] = fixture!("abc.digest");
+
+#[wasm_bindgen_test]
+fn test_verify_keccak256() {
+    assert!(verify_keccak256(VERIFIER_DATA, PROOF, MESSAGE, DIGEST));
+}
+
+#[wasm_bindgen_test]
+fn test_reject_wrong_digest() {
+    let mut digest = DIGEST.to_vec();
+    digest[31] ^= 1;
+    assert!(!verify_keccak256(VERIFIER_DATA, PROOF, MESSAGE, &digest));
+    assert!(!verify_keccak256(VERIFIER_DATA, PROOF, b"abd", DIGEST));
+}
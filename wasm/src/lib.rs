@@ -0,0 +1,3 @@
+//! The `starky-keccak` browser verifier, packaged as a cdylib for wasm-pack.
+
+pub use starky_keccak::wasm::*;